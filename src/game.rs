@@ -1,38 +1,57 @@
 pub mod game_controller;
 pub mod game_view;
 pub mod benchmarks;
+pub mod explosion;
+pub mod diagnostics;
+pub mod world;
+pub mod audio;
+// Only run from the command line of the binary
+#[allow(dead_code)]
+pub mod scenarios;
 
 use std::cell::RefCell;
 use std::collections::HashMap;
-use std::path::Path;
 use std::rc::Rc;
 
-use benchmarks::BenchmarkTests;
 use graphics::math::Matrix2d;
 use graphics::Rectangle;
 use graphics::Transformed;
+use opengl_graphics::{CreateTexture, Format};
 use piston_window::TextureSettings;
-use rand::seq::SliceRandom;
 
 use crate::physics::material::*;
+use crate::physics::material_library::*;
 use crate::physics::shape::Shape;
 use crate::physics::shape_type::ShapeType;
 use crate::physics::rigid_body::RigidBody;
+use crate::physics::arena::{BodyArena, BodyHandle};
 use crate::physics::soft_body::Constraint;
 use crate::physics::soft_body::Joint;
 use crate::Vector2f;
-use crate::utils::helpers::*;
 use crate::GlGraphics;
 use crate::GlyphCache;
 use crate::Texture;
 use crate::physics::circle::Circle;
 use crate::physics::polygon::Polygon;
+use crate::physics::compound::Compound;
+use crate::physics::capsule::Capsule;
+use crate::physics::chain::Chain;
+use crate::physics::heightfield::Heightfield;
 use crate::color;
 use crate::physics::soft_body::SoftBody;
+use crate::physics::fluid::Fluid;
+use crate::utils::real::{to_f64, Real};
+use crate::utils::real::consts::PI;
+use explosion::Shockwave;
+use audio::ImpactAudio;
+use world::World;
+use diagnostics::EnergyDiagnostics;
 use crate::Context;
 
 
-const PHYSICS_ITERATIONS: usize = 8;
+pub const EXPLOSION_RADIUS: Real = 250.0;
+// The impulse given to a body right at the center of the explosion
+pub const EXPLOSION_IMPULSE: Real = 1.0e6;
 
 #[allow(dead_code)]
 const MAX_SCALE: f64 = 10.0;
@@ -41,14 +60,15 @@ const MAX_SCALE: f64 = 10.0;
 const MIN_SCALE: f64 = 0.1;
 
 pub struct Projectile {
-    pub target: Option<Vector2f<f64>>,
+    pub target: Option<Vector2f<Real>>,
     pub body: RigidBody,
-    pub scale: f64,
+    pub scale: Real,
 }
 
 pub enum Utility {
     Launch,
     String(Vec<Joint>),
+    Explode,
 }
 
 pub struct GameSettings {
@@ -87,6 +107,7 @@ pub struct ViewSettings {
     pub show_velocites: bool,
     pub show_contact_points: bool,
     pub show_tiles: bool,
+    pub show_energy: bool,
 }
 
 impl Default for ViewSettings {
@@ -95,14 +116,18 @@ impl Default for ViewSettings {
             show_velocites: false, 
             show_contact_points: false, 
             show_tiles: false,
+            show_energy: false,
         }
     }
 }
 
 pub struct PhysicsData {
-    pub gravity: Vector2f<f64>,
-    pub air_density: f64,
-    pub dt: f64,
+    pub gravity: Vector2f<Real>,
+    pub air_density: Real,
+    pub drag_coefficient: Real,
+    pub lift_coefficient: Real,
+    pub material_pairs: MaterialTable,
+    pub dt: Real,
 }
 
 impl Default for PhysicsData {
@@ -110,32 +135,41 @@ impl Default for PhysicsData {
         PhysicsData { 
             gravity: Vector2f { x: 0.0, y: 250.0 }, 
             air_density: 0.08,
+            drag_coefficient: 0.2,
+            lift_coefficient: 0.4,
+            material_pairs: MaterialTable::builtin(),
             dt: 1.0 / 120.0, 
         }
     }
 }
 
 pub struct ContactDebug {
-    pub contact: Vector2f<f64>,
-    pub normal: Vector2f<f64>,
+    pub contact: Vector2f<Real>,
+    pub normal: Vector2f<Real>,
 }
 
 pub struct Game {
     pub settings: GameSettings,
-    pub physics: PhysicsData,
-    pub player: Rc<RefCell<RigidBody>>,
-    pub bodies: Vec<Rc<RefCell<RigidBody>>>,
-    pub strings: Vec<Rc<RefCell<SoftBody>>>,
+    pub world: World,
+    pub player: BodyHandle,
     pub projectile: Projectile,
-    pub contacts: Vec<ContactDebug>,
     pub textures: HashMap<MaterialName, Rc<Texture>>,
+    pub fallback_texture: Rc<Texture>,
+    pub materials: MaterialLibrary,
     pub context: Context,
     pub camera_transform: Matrix2d,
-    pub benchmarks: BenchmarkTests,
+    pub shockwaves: Vec<Shockwave>,
+    pub energy: EnergyDiagnostics,
+    pub audio: ImpactAudio,
 }
 
 impl Default for Game {
     fn default() -> Self {
+        let materials = MaterialLibrary::load(MATERIAL_LIBRARY_PATH).unwrap_or_else(|e| {
+            println!("{}, using the built-in materials", e);
+            MaterialLibrary::default()
+        });
+
         // Create bodies
         let floor_shape = ShapeType::Polygon(Polygon::new_rectangle(
             Vector2f::new(640.0, 650.0), 
@@ -143,8 +177,7 @@ impl Default for Game {
             50.0, 
             0.0,
         ));
-        let floor = RigidBody::new(floor_shape, CONCRETE, true);
-        let floor_ref = Rc::new(RefCell::new(floor.clone()));
+        let floor = RigidBody::new(floor_shape, materials.resolve(CONCRETE), true);
 
         let ramp1 = ShapeType::Polygon(Polygon::new_rectangle(
             Vector2f::new(450.0, 300.0), 
@@ -157,18 +190,106 @@ impl Default for Game {
         ramp2.translate(Vector2f::new(400.0, -150.0));
         ramp2.rotate(-1.0);
 
-        let ramp1_ref = Rc::new(RefCell::new(RigidBody::new(ramp1, STEEL, true)));
-        let ramp2_ref = Rc::new(RefCell::new(RigidBody::new(ramp2, ICE,  true)));
+        let ramp1 = RigidBody::new(ramp1, materials.resolve(STEEL), true);
+        let ramp2 = RigidBody::new(ramp2, materials.resolve(ICE),  true);
 
-        let triangle = Rc::new(RefCell::new(RigidBody::new(
+        let triangle = RigidBody::new(
             ShapeType::Polygon(
                 Polygon::new_regular_polygon(3, 60.0, Vector2f::new(800.0, 595.0), 0.0)),
-                WOOD,
+                materials.resolve(WOOD),
                 true,
-        )));
-
-        let player = RigidBody::new(ShapeType::Circle(Circle::new(Vector2f::new(640.0, 280.0), 25.0, 0.0)), WOOD, false);
-        let player_ref = Rc::new(RefCell::new(player.clone()));
+        );
+
+        // A concave L-shape, which is split into convex parts for the collisions
+        let bracket = RigidBody::new(
+            ShapeType::Polygon(Polygon::new(vec![
+                Vector2f::new(0.0, 0.0),
+                Vector2f::new(30.0, 0.0),
+                Vector2f::new(30.0, 70.0),
+                Vector2f::new(100.0, 70.0),
+                Vector2f::new(100.0, 100.0),
+                Vector2f::new(0.0, 100.0),
+            ], Vector2f::new(1150.0, 500.0), 0.0)),
+            materials.resolve(WOOD),
+            false,
+        );
+
+        // A compound hammer, with the heavy end pulling the centroid towards the head
+        let hammer = RigidBody::new(
+            ShapeType::Compound(Compound::new(vec![
+                ShapeType::Polygon(Polygon::new_rectangle(Vector2f::new(0.0, 0.0), 16.0, 90.0, 0.0)),
+                ShapeType::Polygon(Polygon::new_square(Vector2f::new(0.0, -55.0), 40.0, 0.0)),
+            ], Vector2f::new(1300.0, 450.0), 0.4)),
+            materials.resolve(STEEL),
+            false,
+        );
+
+        let rod = RigidBody::new(
+            ShapeType::Capsule(Capsule::from_segment(Vector2f::new(1000.0, 420.0), Vector2f::new(1090.0, 400.0), 12.0)),
+            materials.resolve(PLASTIC),
+            false,
+        );
+
+        // Terrain drawn with chains of segments, a half-pipe left of the start and some hills past the tank
+        let half_pipe = RigidBody::new(
+            ShapeType::Chain(Chain::new((0..=24).map(|i| {
+                let angle = PI * i as Real / 24.0;
+                Vector2f::new(-800.0 + 300.0 * angle.cos(), 325.0 + 300.0 * angle.sin())
+            }).collect(), false)),
+            materials.resolve(CONCRETE),
+            true,
+        );
+        let hills = RigidBody::new(
+            ShapeType::Chain(Chain::new(vec![
+                Vector2f::new(2150.0, 625.0),
+                Vector2f::new(2250.0, 580.0),
+                Vector2f::new(2330.0, 565.0),
+                Vector2f::new(2400.0, 585.0),
+                Vector2f::new(2470.0, 540.0),
+                Vector2f::new(2560.0, 520.0),
+                Vector2f::new(2640.0, 560.0),
+            ], false)),
+            materials.resolve(DIRT),
+            true,
+        );
+
+        // Rolling heightfield terrain continuing on from the hills
+        let rolling_hills = RigidBody::new(
+            ShapeType::Heightfield(Heightfield::flat(Vector2f::new(2640.0, 675.0), 20.0, 81, 115.0).with_perlin(60.0, 300.0, 3, 7)),
+            materials.resolve(DIRT),
+            true,
+        );
+
+        let player = RigidBody::new(ShapeType::Circle(Circle::new(Vector2f::new(640.0, 280.0), 25.0, 0.0)), materials.resolve(WOOD), false);
+
+        // Water tank with a few bodies of different densities
+        let tank_left = RigidBody::new(
+            ShapeType::Polygon(Polygon::new_rectangle(Vector2f::new(1490.0, 475.0), 20.0, 300.0, 0.0)),
+            materials.resolve(CONCRETE),
+            true,
+        );
+        let tank_right = RigidBody::new(
+            ShapeType::Polygon(Polygon::new_rectangle(Vector2f::new(2110.0, 475.0), 20.0, 300.0, 0.0)),
+            materials.resolve(CONCRETE),
+            true,
+        );
+        let water = Fluid::new_water(Vector2f::new(1800.0, 512.5), 600.0, 225.0);
+
+        let ice_block = RigidBody::new(
+            ShapeType::Polygon(Polygon::new_square(Vector2f::new(1620.0, 250.0), 50.0, 0.3)),
+            materials.resolve(ICE),
+            false,
+        );
+        let wood_plank = RigidBody::new(
+            ShapeType::Polygon(Polygon::new_rectangle(Vector2f::new(1800.0, 250.0), 120.0, 20.0, 0.0)),
+            materials.resolve(WOOD),
+            false,
+        );
+        let steel_ball = RigidBody::new(
+            ShapeType::Circle(Circle::new(Vector2f::new(1980.0, 250.0), 20.0, 0.0)),
+            materials.resolve(STEEL),
+            false,
+        );
 
         let j1 = Joint::new(Vector2f::new(500.0, 200.0), None);
         let j2 = Joint::new(Vector2f::new(520.0, 200.0), None);
@@ -185,63 +306,88 @@ impl Default for Game {
             joints: vec![j1, j2, j3, j4], 
             constraints: vec![c1, c2, c3, c4, c5], 
             damping: 0.05,
+            material: None,
         };
 
         let tex_settings = TextureSettings::new();
-        let tex_path = Path::new("./src/assets/textures/materials");        
         let mut tex_map = HashMap::new();
+        for def in materials.definitions() {
+            let texture = def.texture.as_ref().and_then(|path| {
+                Texture::from_path(path, &tex_settings)
+                    .inspect_err(|e| println!("Failed to load texture for {}: {}", def.name, e))
+                    .ok()
+            }).unwrap_or_else(|| flat_texture(def.tint, &tex_settings));
+            tex_map.insert(def.material().name, Rc::new(texture));
+        }
+
+        let mut bodies = BodyArena::new();
+        for body in [floor, ramp1, ramp2, triangle, bracket, hammer, rod] {
+            bodies.insert(body);
+        }
+        let player = bodies.insert(player);
+        for body in [tank_left, tank_right, ice_block, wood_plank, steel_ball, half_pipe, hills, rolling_hills] {
+            bodies.insert(body);
+        }
 
-        tex_map.insert(MaterialName::Concrete, Rc::new(Texture::from_path(Path::new(&tex_path).join("concrete.png"), &tex_settings).unwrap()));
-        tex_map.insert(MaterialName::Steel, Rc::new(Texture::from_path(Path::new(&tex_path).join("steel.png"), &tex_settings).unwrap()));
-        tex_map.insert(MaterialName::Ice, Rc::new(Texture::from_path(Path::new(&tex_path).join("ice.png"), &tex_settings).unwrap()));
-        tex_map.insert(MaterialName::Wood, Rc::new(Texture::from_path(Path::new(&tex_path).join("wood.png"), &tex_settings).unwrap()));
+        let mut world = World::new(
+            PhysicsData { material_pairs: materials.pairs.clone(), ..PhysicsData::default() },
+            bodies,
+            vec![Rc::new(RefCell::new(soft_triangle))],
+        );
+        world.fluids.push(water);
+        world.unbreakable.push(player);
 
         Self { 
             settings: GameSettings::default(), 
-            physics: PhysicsData::default(),
-            player: player_ref.clone(),
-            bodies: vec![floor_ref, ramp1_ref, ramp2_ref, triangle, player_ref], 
+            world,
+            player,
             projectile: Projectile { 
                 target: None, 
                 body: RigidBody::from(ShapeType::Circle(Circle::new(Vector2f::zero(), 25.0, 0.0))), 
                 scale: 1.0 
             },
-            contacts: vec![], 
             textures: tex_map,
+            fallback_texture: Rc::new(flat_texture(color::WHITE, &tex_settings)),
+            audio: ImpactAudio::new(&materials),
+            materials,
             context: Context::new(),
             camera_transform: [[1.0, 0.0, 0.0], [0.0, 1.0, 0.0]],
-            strings: vec![Rc::new(RefCell::new(soft_triangle))],
-            benchmarks: BenchmarkTests::default(),
+            shockwaves: vec![],
+            energy: EnergyDiagnostics::default(),
         }
     }
 }
 
 impl Game {
-    pub fn draw(&self, _: &mut GlyphCache<'static, (), Texture>, c: Context, gl: &mut GlGraphics) {
+    // Returns the texture of the material, or a plain texture for materials outside the library
+    pub fn get_texture(&self, name: MaterialName) -> &Rc<Texture> {
+        self.textures.get(&name).unwrap_or(&self.fallback_texture)
+    }
+
+    pub fn draw(&self, glyphs: &mut GlyphCache<'static, (), Texture>, c: Context, gl: &mut GlGraphics) {
         graphics::clear(color::WHITE, gl);
 
-        for string in self.strings.as_slice() {
+        for string in self.world.strings.as_slice() {
             let string = string.borrow();
             string.draw(self.camera_transform, c, gl);
             if self.settings.debug_mode || self.settings.view.show_velocites {
                 for joint in string.joints.as_slice() {
-                    let start = joint.position;
-                    let end = start + joint.velocity * self.physics.dt;
+                    let start = joint.position.cast::<f64>();
+                    let end = (joint.position + joint.velocity * self.world.physics.dt).cast::<f64>();
                     let l = [start.x, start.y, end.x, end.y];
                     graphics::line(color::CYAN, 1.0, l, self.camera_transform, gl);
                 }
             }
         }
         
-        for obj in self.bodies.as_slice() {
-            let obj = obj.borrow();
-            obj.draw(self.camera_transform, &self.textures.get(&obj.material.name).unwrap(), c, gl);
+        for obj in self.world.bodies.bodies() {
+            obj.draw(self.camera_transform, self.get_texture(obj.material.name), c, gl);
             if self.settings.view.show_tiles {
-                obj.mesh.draw_tile_outline(self.camera_transform.trans_pos(obj.shape.get_center()).rot_rad(obj.shape.get_rotation()), gl);
+                obj.mesh.draw_tile_outline(self.camera_transform.trans_pos(obj.shape.get_center().cast::<f64>()).rot_rad(to_f64(obj.shape.get_rotation())), gl);
             }
             if self.settings.view.show_velocites || self.settings.debug_mode {
-                let start = obj.shape.get_center();
-                let end = start + obj.linear_velocity * self.physics.dt;
+                let start = obj.shape.get_center().cast::<f64>();
+                let end = (obj.shape.get_center() + obj.linear_velocity * self.world.physics.dt).cast::<f64>();
                 let l = [start.x, start.y, end.x, end.y];
                 graphics::line(color::CYAN, 1.0, l, self.camera_transform, gl);
             }
@@ -252,21 +398,35 @@ impl Game {
                 aabb.top_left -= center;
                 aabb.bottom_right -= center;
                 aabb.bottom_right *= 2.0;
-                let rect = [aabb.top_left.x, aabb.top_left.y, aabb.bottom_right.x, aabb.bottom_right.y];
+                let (top_left, bottom_right) = (aabb.top_left.cast::<f64>(), aabb.bottom_right.cast::<f64>());
+                let rect = [top_left.x, top_left.y, bottom_right.x, bottom_right.y];
                 Rectangle::new_border(color::BLACK, 1.0)
-                    .draw(rect, &c.draw_state, self.camera_transform.trans_pos(center), gl);
+                    .draw(rect, &c.draw_state, self.camera_transform.trans_pos(center.cast::<f64>()), gl);
             }
         }
 
+        for fluid in self.world.fluids.as_slice() {
+            fluid.draw(self.camera_transform, gl);
+        }
+
+        for shockwave in self.shockwaves.as_slice() {
+            shockwave.draw(self.camera_transform, c, gl);
+        }
+
         if self.settings.view.show_contact_points || self.settings.debug_mode {
-            for cd in self.contacts.as_slice() {
-                let square = graphics::rectangle::centered_square(cd.contact.x, cd.contact.y, 5.0);
+            for cd in self.world.contacts.as_slice() {
+                let contact = cd.contact.cast::<f64>();
+                let square = graphics::rectangle::centered_square(contact.x, contact.y, 5.0);
                 graphics::ellipse(color::YELLOW, square, self.camera_transform, gl);
-                let cn = cd.contact + cd.normal * 15.0;
-                let l = [cd.contact.x, cd.contact.y, cn.x, cn.y];
+                let cn = contact + cd.normal.cast::<f64>() * 15.0;
+                let l = [contact.x, contact.y, cn.x, cn.y];
                 graphics::line(color::GREEN, 1.0, l, self.camera_transform, gl);
             }
         }
+
+        if self.settings.view.show_energy {
+            self.energy.draw(glyphs, c, gl);
+        }
     }
 
     #[allow(dead_code)]
@@ -278,62 +438,23 @@ impl Game {
                                 .trans_pos(-self.settings.camera.position);
     }
 
-    pub fn update(&mut self, dt: f64) {
-        self.benchmarks.updating.start();
-        self.contacts.clear();
-        self.physics.dt = dt;
-
-        for obj in self.bodies.as_slice() {
-            let mut obj = obj.borrow_mut();
-            obj.update_velocity(&self.physics);
-        }
-
-        // Detect body on body collisions and push out
-        self.benchmarks.rigid_collision_detection.start();
-        let mut collisions = vec![];
-        for i in 0..self.bodies.len() {
-            for j in (i+1)..self.bodies.len() {
-                let (a_ref, b_ref) = get_pair_mut(&mut self.bodies, i, j);
-                let (mut a, mut b) = (a_ref.borrow_mut(), b_ref.borrow_mut());
-                if let Some(collision) = a.collide_with(&mut b, self.physics.dt) {
-                    self.contacts.extend(collision.contacts.iter().map(|&contact| ContactDebug { contact, normal: collision.normal}));
-                    collisions.push((i, j, collision));
-                }
-            }
-        }
-        self.benchmarks.rigid_collision_detection.stop(Some(self.bodies.len()));
-
-        // Resolve collisions
-        self.benchmarks.rigid_collision_solving.start();
-        let mut rng = rand::rng();
-        for _ in 0..PHYSICS_ITERATIONS {
-            collisions.shuffle(&mut rng);
-            for (i, j, collision) in collisions.as_slice() {
-                let (a, b) = get_pair_mut(&mut self.bodies, *i, *j);
-                let mut a = a.borrow_mut();
-                let mut b = b.borrow_mut();
-                a.resolve_collision(&mut b, collision);
-            }
-        }    
-        self.benchmarks.rigid_collision_solving.stop(Some(collisions.len()));
-
-        // Resolve constraints with verlet integration
-        let mut new_strings = vec![];
-        for string in self.strings.as_slice() {
-            let mut string = string.borrow_mut();
-            if let Some(new_string) = string.resolve_constraints(
-                &self.physics, 
-                &self.bodies, 
-                &mut self.contacts, 
-            ) {
-                new_strings.push(Rc::new(RefCell::new(new_string)));
-            }
-        }
-        self.strings.extend(new_strings);  
+    pub fn update(&mut self, dt: Real) {
+        self.shockwaves.retain_mut(|shockwave| shockwave.update(to_f64(dt)));
+        self.world.step(dt);
+        self.audio.play(&self.world.impacts);
+        self.energy.record(self.world.measure_energy(), dt);
+    }
 
-        for obj_ref in self.bodies.as_mut_slice() {
-            obj_ref.borrow_mut().update_position(self.physics.dt);
-        }
-        self.benchmarks.updating.stop(None);
+    // Blasts everything around the center away, see World::explode, and shows the shockwave
+    pub fn explode(&mut self, center: Vector2f<Real>, radius: Real, impulse: Real) {
+        self.world.explode(center, radius, impulse);
+        self.shockwaves.push(Shockwave::new(center.cast(), to_f64(radius)));
     }
-}
\ No newline at end of file
+}
+
+// A small single colored texture used in place of missing material textures
+fn flat_texture(color: [f32; 4], settings: &TextureSettings) -> Texture {
+    let pixel = color.map(|c| (c.clamp(0.0, 1.0) * 255.0) as u8);
+    let memory: Vec<u8> = pixel.iter().copied().cycle().take(4 * 4).collect();
+    Texture::create(&mut (), Format::Rgba8, &memory, [2, 2], settings).unwrap()
+}
@@ -0,0 +1,98 @@
+use std::collections::HashMap;
+
+use kira::sound::static_sound::StaticSoundData;
+use kira::{AudioManager, AudioManagerSettings, Decibels, DefaultBackend};
+
+use crate::physics::material::MaterialName;
+use crate::physics::material_library::MaterialLibrary;
+use crate::utils::real::{to_f64, Real};
+
+// Hits that change the speed of a body less than this are too soft to be heard, which also keeps
+// resting contacts quiet
+const MIN_IMPACT_SPEED: Real = 60.0;
+// Hits that change the speed of a body by this much or more play at full volume
+const FULL_VOLUME_SPEED: Real = 600.0;
+
+// Plays the sound of the material of a body whenever it is hit hard enough
+pub struct ImpactAudio {
+    // None when there is no audio device, the game then runs without sound
+    manager: Option<AudioManager>,
+    sounds: HashMap<MaterialName, StaticSoundData>,
+}
+
+impl ImpactAudio {
+    pub fn new(materials: &MaterialLibrary) -> Self {
+        let manager = AudioManager::<DefaultBackend>::new(AudioManagerSettings::default())
+            .inspect_err(|e| println!("Failed to start audio, playing without sound: {}", e))
+            .ok();
+
+        let mut sounds = HashMap::new();
+        if manager.is_some() {
+            for def in materials.definitions() {
+                let Some(path) = def.sound.as_ref() else {
+                    continue;
+                };
+
+                match StaticSoundData::from_file(path) {
+                    Ok(sound) => { sounds.insert(def.material().name, sound); }
+                    Err(e) => println!("Failed to load sound for {}: {}", def.name, e),
+                }
+            }
+        }
+
+        Self { manager, sounds }
+    }
+
+    // Plays the impacts of a step, given as the material of the body and its change in speed
+    pub fn play(&mut self, impacts: &[(MaterialName, Real)]) {
+        let Some(manager) = self.manager.as_mut() else {
+            return;
+        };
+
+        for (name, speed) in impacts {
+            let (Some(sound), Some(volume)) = (self.sounds.get(name), impact_volume(*speed)) else {
+                continue;
+            };
+
+            if let Err(e) = manager.play(sound.volume(volume)) {
+                println!("Failed to play sound for {}: {}", name.as_str(), e);
+            }
+        }
+    }
+}
+
+// The volume grows with the amplitude of the sound, which follows the change in speed
+fn impact_volume(speed: Real) -> Option<Decibels> {
+    if speed < MIN_IMPACT_SPEED {
+        return None;
+    }
+
+    let amplitude = to_f64(speed / FULL_VOLUME_SPEED).min(1.0);
+    Some(Decibels((20.0 * amplitude.log10()) as f32))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn harder_impacts_are_louder() {
+        assert!(impact_volume(MIN_IMPACT_SPEED / 2.0).is_none());
+
+        let soft = impact_volume(MIN_IMPACT_SPEED).unwrap();
+        let hard = impact_volume(FULL_VOLUME_SPEED / 2.0).unwrap();
+        assert!(soft.0 < hard.0 && hard.0 < 0.0);
+        assert_eq!(impact_volume(FULL_VOLUME_SPEED).unwrap().0, 0.0);
+        assert_eq!(impact_volume(FULL_VOLUME_SPEED * 10.0).unwrap().0, 0.0);
+    }
+
+    #[test]
+    fn library_sounds_load() {
+        let materials = MaterialLibrary::load(crate::physics::material_library::MATERIAL_LIBRARY_PATH).unwrap();
+        let paths: Vec<_> = materials.definitions().iter().filter_map(|def| def.sound.as_ref()).collect();
+        assert!(!paths.is_empty());
+        for path in paths {
+            assert!(StaticSoundData::from_file(path).is_ok(), "{}", path.display());
+        }
+    }
+}
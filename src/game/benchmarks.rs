@@ -6,6 +6,8 @@ pub struct BenchmarkStatistic {
     name: String,
     n_name: Option<String>,
     benchmarks: Vec<Duration>,
+    // Time spent in the tasks of a parallel section, summed over the threads
+    work: Vec<Duration>,
     n: usize,
 }
 
@@ -17,6 +19,7 @@ impl BenchmarkStatistic {
             name,
             n_name,
             benchmarks: vec![],
+            work: vec![],
             n: 0,
         }
     }
@@ -35,10 +38,26 @@ impl BenchmarkStatistic {
         if self.benchmarks.len() >= self.max_samples {
             println!("{}", self);
             self.benchmarks.clear();
+            self.work.clear();
             self.n = 0;
         }
     }
 
+    pub fn add_work(&mut self, work: Duration) {
+        self.work.push(work);
+    }
+
+    // How much faster the tasks ran than they would have one after another, 1 without the
+    // parallel feature minus the time spent splitting up the work
+    pub fn get_speedup(&self) -> Option<f64> {
+        if self.work.is_empty() {
+            return None;
+        }
+        let total: Duration = self.benchmarks.iter().sum();
+        let work: Duration = self.work.iter().sum();
+        Some(work.as_secs_f64() / total.as_secs_f64())
+    }
+
     // average benchmarks in micros
     pub fn get_averages(&self) -> (f64, f64) {
         let total: f64 = self.benchmarks.iter().map(|d| d.as_secs_f64()).sum();
@@ -56,11 +75,15 @@ impl Display for BenchmarkStatistic {
         if let Some(n_name) = &self.n_name {
             write!(f, "\n\nBenchmark analysis for {}. 
             \nAvg time per frame: {}µs. \nAvg {}s per frame: {}. \nAvg time per {}: {}µs", self.name,
-            avg_time, n_name, avg_n, n_name, avg_time_per_n) 
+            avg_time, n_name, avg_n, n_name, avg_time_per_n)?;
         } else {
             write!(f, "\n\nBenchmarks analysis for {}.
-            \nTime per frame: {}µs", self.name, avg_time)        
+            \nTime per frame: {}µs", self.name, avg_time)?;
+        }
+        if let Some(speedup) = self.get_speedup() {
+            write!(f, "\nParallel speedup: {:.2}x", speedup)?;
         }
+        Ok(())
     }
 }
 
@@ -0,0 +1,197 @@
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::rc::Rc;
+
+use graphics::{Rectangle, Text, Transformed};
+
+use crate::utils::real::{to_f64, Real};
+use crate::color;
+use crate::physics::arena::BodyArena;
+use crate::physics::soft_body::SoftBody;
+use crate::Context;
+use crate::GlGraphics;
+use crate::GlyphCache;
+use crate::Texture;
+use crate::Vector2f;
+
+use super::PhysicsData;
+
+// How many steps the overlay graph shows, 5 seconds at the default step
+const HISTORY_LENGTH: usize = 600;
+const GRAPH_WIDTH: f64 = 300.0;
+const GRAPH_HEIGHT: f64 = 150.0;
+const GRAPH_MARGIN: f64 = 25.0;
+
+pub const ENERGY_LOG_PATH: &str = "energy.csv";
+
+// A value read from the samples and the color of its line in the graph
+type Series = (fn(&EnergySample) -> Real, [f32; 4]);
+
+// The totals of the simulation at the end of a step. Static bodies have no mass, so they are
+// left out. The potential energy and angular momentum are taken around the world origin.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct EnergySample {
+    pub time: Real,
+    pub linear_kinetic: Real,
+    pub angular_kinetic: Real,
+    pub potential: Real,
+    pub spring: Real,
+    pub linear_momentum: Vector2f<Real>,
+    pub angular_momentum: Real,
+}
+
+impl EnergySample {
+    pub fn measure(bodies: &BodyArena, strings: &[Rc<RefCell<SoftBody>>], physics: &PhysicsData) -> Self {
+        let mut sample = Self::default();
+
+        for obj in bodies.bodies() {
+            if obj.is_static {
+                continue;
+            }
+
+            let props = obj.mass_properties();
+            let inertia = if obj.locks.rotation { 0.0 } else { props.inertia };
+            let position = obj.shape.get_transform().apply(props.local_center);
+            sample.add_point_mass(props.mass, position, obj.linear_velocity, physics.gravity);
+            sample.angular_kinetic += 0.5 * inertia * obj.angular_velocity * obj.angular_velocity;
+            sample.angular_momentum += inertia * obj.angular_velocity;
+        }
+
+        for string in strings {
+            let string = string.borrow();
+            // Attached joints move with their body, which is already counted
+            for joint in string.joints.iter().filter(|j| j.attachment.is_none()) {
+                sample.add_point_mass(joint.mass, joint.position, joint.velocity, physics.gravity);
+            }
+            sample.spring += string.spring_energy(bodies, physics.dt);
+        }
+
+        sample
+    }
+
+    fn add_point_mass(&mut self, mass: Real, position: Vector2f<Real>, velocity: Vector2f<Real>, gravity: Vector2f<Real>) {
+        self.linear_kinetic += 0.5 * mass * velocity.len_squared();
+        self.potential -= mass * gravity.dot(position);
+        self.linear_momentum += velocity * mass;
+        self.angular_momentum += position.cross(velocity * mass);
+    }
+
+    pub fn kinetic(&self) -> Real {
+        self.linear_kinetic + self.angular_kinetic
+    }
+
+    pub fn total(&self) -> Real {
+        self.kinetic() + self.potential + self.spring
+    }
+}
+
+// Keeps the recent samples for the overlay graph, and writes every sample to a CSV file while
+// logging is turned on
+pub struct EnergyDiagnostics {
+    pub samples: VecDeque<EnergySample>,
+    pub time: Real,
+    log: Option<BufWriter<File>>,
+}
+
+impl Default for EnergyDiagnostics {
+    fn default() -> Self {
+        Self {
+            samples: VecDeque::with_capacity(HISTORY_LENGTH),
+            time: 0.0,
+            log: None,
+        }
+    }
+}
+
+impl EnergyDiagnostics {
+    pub fn record(&mut self, mut sample: EnergySample, dt: Real) {
+        self.time += dt;
+        sample.time = self.time;
+
+        if let Some(log) = &mut self.log && let Err(e) = write_row(log, &sample) {
+            println!("Failed to write the energy log: {}", e);
+            self.log = None;
+        }
+
+        if self.samples.len() >= HISTORY_LENGTH {
+            self.samples.pop_front();
+        }
+        self.samples.push_back(sample);
+    }
+
+    pub fn latest(&self) -> Option<&EnergySample> {
+        self.samples.back()
+    }
+
+    pub fn is_logging(&self) -> bool {
+        self.log.is_some()
+    }
+
+    // Starts a new log at the path, replacing the file if it exists
+    pub fn start_log(&mut self, path: &str) -> io::Result<()> {
+        let mut log = BufWriter::new(File::create(path)?);
+        writeln!(log, "time,linear_kinetic,angular_kinetic,potential,spring,total,momentum_x,momentum_y,angular_momentum")?;
+        self.log = Some(log);
+        Ok(())
+    }
+
+    pub fn stop_log(&mut self) {
+        if let Some(mut log) = self.log.take() && let Err(e) = log.flush() {
+            println!("Failed to write the energy log: {}", e);
+        }
+    }
+
+    // Graphs the kinetic, potential, spring and total energy in the bottom right corner of the
+    // screen. The graph is scaled to fit the samples, so only the changes are visible.
+    pub fn draw(&self, glyphs: &mut GlyphCache<'static, (), Texture>, c: Context, gl: &mut GlGraphics) {
+        let Some(latest) = self.latest() else {
+            return;
+        };
+
+        let dims = c.get_view_size();
+        let transform = c.transform.trans(dims[0] - GRAPH_WIDTH - GRAPH_MARGIN, dims[1] - GRAPH_HEIGHT - GRAPH_MARGIN);
+        let rect = [0.0, 0.0, GRAPH_WIDTH, GRAPH_HEIGHT];
+        Rectangle::new_border(color::BLACK, 1.0).color([1.0, 1.0, 1.0, 0.8]).draw(rect, &c.draw_state, transform, gl);
+
+        let series: [Series; 4] = [
+            (EnergySample::kinetic, color::RED),
+            (|s| s.potential, color::BLUE),
+            (|s| s.spring, color::GREEN),
+            (EnergySample::total, color::BLACK),
+        ];
+
+        let (mut min, mut max) = (Real::INFINITY, Real::NEG_INFINITY);
+        for sample in self.samples.iter() {
+            for (value, _) in series.iter() {
+                min = min.min(value(sample));
+                max = max.max(value(sample));
+            }
+        }
+        let range = (max - min).max(Real::EPSILON);
+        let step = GRAPH_WIDTH / (HISTORY_LENGTH - 1) as f64;
+        let to_graph = |i: usize, value: Real| [i as f64 * step, GRAPH_HEIGHT * (1.0 - to_f64((value - min) / range))];
+
+        for (value, line_color) in series.iter() {
+            for (i, (a, b)) in self.samples.iter().zip(self.samples.iter().skip(1)).enumerate() {
+                let [x1, y1] = to_graph(i, value(a));
+                let [x2, y2] = to_graph(i + 1, value(b));
+                graphics::line(*line_color, 1.0, [x1, y1, x2, y2], transform, gl);
+            }
+        }
+
+        let text = Text::new_color(color::BLACK, 12);
+        let label = format!("E {:.3e}  dE {:+.3e}", latest.total(), latest.total() - self.samples[0].total());
+        text.draw(&label, glyphs, &c.draw_state, transform.trans(5.0, -5.0), gl).unwrap();
+    }
+}
+
+fn write_row(log: &mut BufWriter<File>, s: &EnergySample) -> io::Result<()> {
+    writeln!(
+        log,
+        "{},{},{},{},{},{},{},{},{}",
+        s.time, s.linear_kinetic, s.angular_kinetic, s.potential, s.spring, s.total(),
+        s.linear_momentum.x, s.linear_momentum.y, s.angular_momentum,
+    )
+}
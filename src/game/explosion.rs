@@ -0,0 +1,46 @@
+use graphics::math::Matrix2d;
+use graphics::Ellipse;
+
+use crate::color;
+use crate::Context;
+use crate::GlGraphics;
+use crate::Vector2f;
+
+const SHOCKWAVE_DURATION: f64 = 0.4;
+const SHOCKWAVE_WIDTH: f64 = 6.0;
+
+// The ring drawn around an explosion, growing out to the blast radius while it fades away
+#[derive(Clone, Copy)]
+pub struct Shockwave {
+    pub center: Vector2f<f64>,
+    pub radius: f64,
+    pub age: f64,
+}
+
+impl Shockwave {
+    pub fn new(center: Vector2f<f64>, radius: f64) -> Self {
+        Self { center, radius, age: 0.0 }
+    }
+
+    // Returns false once the shockwave has faded out
+    pub fn update(&mut self, dt: f64) -> bool {
+        self.age += dt;
+        self.age < SHOCKWAVE_DURATION
+    }
+
+    pub fn draw(&self, transform: Matrix2d, c: Context, gl: &mut GlGraphics) {
+        let t = (self.age / SHOCKWAVE_DURATION).min(1.0);
+        // Eases out, so the ring starts fast and slows down towards the edge of the blast
+        let radius = self.radius * (1.0 - (1.0 - t).powi(3));
+        let alpha = (1.0 - t) as f32;
+
+        let mut flash = color::YELLOW;
+        flash[3] = 0.35 * alpha * alpha;
+        let mut ring = color::RED;
+        ring[3] = alpha;
+
+        let square = graphics::rectangle::centered_square(self.center.x, self.center.y, radius);
+        Ellipse::new(flash).draw(square, &c.draw_state, transform, gl);
+        Ellipse::new_border(ring, SHOCKWAVE_WIDTH * alpha as f64 / 2.0).draw(square, &c.draw_state, transform, gl);
+    }
+}
@@ -75,7 +75,7 @@ impl GameController {
             match key {
                 Key::Space => {
                     if self.game.settings.debug_mode {
-                        self.game.update(self.game.physics.dt);
+                        self.game.update(self.game.world.physics.dt);
                     }
                 }
                 Key::X => self.game.settings.debug_mode = !self.game.settings.debug_mode,
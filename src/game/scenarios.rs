@@ -0,0 +1,358 @@
+use std::cell::RefCell;
+use crate::utils::real::{to_f64, Real};
+use crate::utils::real::consts::PI;
+use std::fmt::Display;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+use serde::{Deserialize, Serialize};
+
+use crate::physics::arena::{BodyArena, BodyHandle};
+use crate::physics::circle::Circle;
+use crate::physics::material::*;
+use crate::physics::polygon::Polygon;
+use crate::physics::rigid_body::RigidBody;
+use crate::physics::shape_type::ShapeType;
+use crate::physics::soft_body::{Attachment, SoftBody};
+use crate::Vector2f;
+
+use super::world::World;
+use super::PhysicsData;
+
+// Reproducible scenes, which are stepped without a window and compared against the trajectories
+// recorded in their golden files. Run them with `physics-playground --scenarios [record] [names]`.
+
+// Rounding sends f32 runs down their own trajectories, so they are compared against goldens
+// recorded in f32
+#[cfg(not(feature = "f32"))]
+pub const GOLDEN_DIR: &str = "./src/assets/golden";
+#[cfg(feature = "f32")]
+pub const GOLDEN_DIR: &str = "./src/assets/golden/f32";
+const SCENARIO_SEED: u64 = 0;
+const SCENARIO_DT: Real = 1.0 / 120.0;
+// Only every n-th step is recorded, to keep the golden files small
+const SAMPLE_INTERVAL: usize = 10;
+const ROTATION_TOLERANCE: Real = 0.02;
+
+pub struct Scenario {
+    pub name: &'static str,
+    pub steps: usize,
+    // How far a body may move away from its golden trajectory
+    pub tolerance: Real,
+    pub build: fn() -> World,
+}
+
+pub const SCENARIOS: [Scenario; 5] = [
+    Scenario { name: "box_pyramid", steps: 600, tolerance: 1.0, build: box_pyramid },
+    Scenario { name: "newtons_cradle", steps: 600, tolerance: 1.0, build: newtons_cradle },
+    Scenario { name: "domino_line", steps: 600, tolerance: 1.0, build: domino_line },
+    Scenario { name: "inclined_plane", steps: 600, tolerance: 1.0, build: inclined_plane },
+    Scenario { name: "rope_bridge", steps: 600, tolerance: 1.0, build: rope_bridge },
+];
+
+// The dynamic bodies as x, y and rotation, and the joints of every string as x and y
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Frame {
+    pub step: usize,
+    pub bodies: Vec<[f32; 3]>,
+    pub joints: Vec<[f32; 2]>,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Trajectory {
+    pub scenario: String,
+    pub frames: Vec<Frame>,
+}
+
+// The first place a run left the golden trajectory
+pub struct Drift {
+    pub step: usize,
+    pub what: String,
+    pub error: Real,
+    pub tolerance: Real,
+}
+
+impl Display for Drift {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} is off by {:.4} at step {} (tolerance {})", self.what, self.error, self.step, self.tolerance)
+    }
+}
+
+#[derive(Debug)]
+pub enum GoldenError {
+    Io(std::io::Error),
+    Parse(ron::error::SpannedError),
+    Write(ron::Error),
+}
+
+impl Display for GoldenError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            GoldenError::Io(e) => write!(f, "could not access golden file: {}", e),
+            GoldenError::Parse(e) => write!(f, "could not parse golden file: {}", e),
+            GoldenError::Write(e) => write!(f, "could not write golden file: {}", e),
+        }
+    }
+}
+
+impl Scenario {
+    // Builds the scene and steps it, with the collision order seeded so every run is the same
+    pub fn run(&self) -> Trajectory {
+        let mut world = (self.build)();
+        world.rng = StdRng::seed_from_u64(SCENARIO_SEED);
+
+        let mut frames = vec![Frame::capture(&world, 0)];
+        for step in 1..=self.steps {
+            world.step(SCENARIO_DT);
+            if step % SAMPLE_INTERVAL == 0 {
+                frames.push(Frame::capture(&world, step));
+            }
+        }
+
+        Trajectory { scenario: self.name.to_string(), frames }
+    }
+
+    pub fn golden_path(&self) -> PathBuf {
+        Path::new(GOLDEN_DIR).join(format!("{}.ron", self.name))
+    }
+
+    pub fn load_golden(&self) -> Result<Trajectory, GoldenError> {
+        let content = fs::read_to_string(self.golden_path()).map_err(GoldenError::Io)?;
+        ron::from_str(&content).map_err(GoldenError::Parse)
+    }
+
+    pub fn save_golden(&self, trajectory: &Trajectory) -> Result<(), GoldenError> {
+        // One frame per line, so a diff of the file shows when the trajectory changed
+        let config = ron::ser::PrettyConfig::new().depth_limit(2).compact_arrays(true);
+        let content = ron::ser::to_string_pretty(trajectory, config).map_err(GoldenError::Write)?;
+        fs::create_dir_all(GOLDEN_DIR).map_err(GoldenError::Io)?;
+        fs::write(self.golden_path(), content).map_err(GoldenError::Io)
+    }
+
+    // Returns the largest error against the golden trajectory, or where the run first drifted
+    // further from it than the tolerance
+    pub fn compare(&self, golden: &Trajectory, run: &Trajectory) -> Result<Real, Drift> {
+        let mut max_error: Real = 0.0;
+        for (expected, actual) in golden.frames.iter().zip(run.frames.iter()) {
+            let drift = |what: String, error: Real, tolerance: Real| Drift { step: actual.step, what, error, tolerance };
+            if expected.bodies.len() != actual.bodies.len() || expected.joints.len() != actual.joints.len() {
+                let what = format!(
+                    "the body and joint count ({}, {} instead of {}, {})",
+                    actual.bodies.len(), actual.joints.len(), expected.bodies.len(), expected.joints.len(),
+                );
+                return Err(drift(what, Real::INFINITY, 0.0));
+            }
+
+            for (i, (e, a)) in expected.bodies.iter().zip(actual.bodies.iter()).enumerate() {
+                let error = distance([e[0], e[1]], [a[0], a[1]]);
+                let (sin, cos) = (a[2] as Real - e[2] as Real).sin_cos();
+                let rotation_error = sin.atan2(cos).abs();
+                if error > self.tolerance {
+                    return Err(drift(format!("body {}", i), error, self.tolerance));
+                }
+                if rotation_error > ROTATION_TOLERANCE {
+                    return Err(drift(format!("the rotation of body {}", i), rotation_error, ROTATION_TOLERANCE));
+                }
+                max_error = max_error.max(error);
+            }
+
+            for (i, (e, a)) in expected.joints.iter().zip(actual.joints.iter()).enumerate() {
+                let error = distance(*e, *a);
+                if error > self.tolerance {
+                    return Err(drift(format!("joint {}", i), error, self.tolerance));
+                }
+                max_error = max_error.max(error);
+            }
+        }
+
+        if golden.frames.len() != run.frames.len() {
+            let what = format!("the frame count ({} instead of {})", run.frames.len(), golden.frames.len());
+            return Err(Drift { step: 0, what, error: Real::INFINITY, tolerance: 0.0 });
+        }
+        Ok(max_error)
+    }
+}
+
+impl Frame {
+    pub fn capture(world: &World, step: usize) -> Self {
+        let bodies = world.bodies.bodies()
+            .filter(|obj| !obj.is_static)
+            .map(|obj| {
+                let center = obj.shape.get_center();
+                [to_f64(center.x) as f32, to_f64(center.y) as f32, to_f64(obj.shape.get_rotation()) as f32]
+            })
+            .collect();
+        let joints = world.strings.iter()
+            .flat_map(|string| string.borrow().joints.iter().map(|j| [to_f64(j.position.x) as f32, to_f64(j.position.y) as f32]).collect::<Vec<_>>())
+            .collect();
+
+        Self { step, bodies, joints }
+    }
+}
+
+fn distance(a: [f32; 2], b: [f32; 2]) -> Real {
+    let dx = a[0] as Real - b[0] as Real;
+    let dy = a[1] as Real - b[1] as Real;
+    (dx * dx + dy * dy).sqrt()
+}
+
+// Runs the named scenarios, or all of them, and prints how each one compares to its golden file.
+// With `record` as the first argument the golden files are written instead. Returns the exit code.
+pub fn run_suite(args: &[String]) -> i32 {
+    let record = args.first().is_some_and(|arg| arg == "record");
+    let names = if record { &args[1..] } else { args };
+
+    let mut failed = false;
+    for scenario in SCENARIOS.iter().filter(|s| names.is_empty() || names.iter().any(|n| n == s.name)) {
+        let run = scenario.run();
+        if record {
+            match scenario.save_golden(&run) {
+                Ok(()) => println!("{}: recorded {} frames", scenario.name, run.frames.len()),
+                Err(e) => {
+                    println!("{}: {}", scenario.name, e);
+                    failed = true;
+                }
+            }
+            continue;
+        }
+
+        match scenario.load_golden().map(|golden| scenario.compare(&golden, &run)) {
+            Ok(Ok(max_error)) => println!("{}: ok, max error {:.4}", scenario.name, max_error),
+            Ok(Err(drift)) => {
+                println!("{}: DRIFTED, {}", scenario.name, drift);
+                failed = true;
+            }
+            Err(e) => {
+                println!("{}: {}", scenario.name, e);
+                failed = true;
+            }
+        }
+    }
+
+    if failed { 1 } else { 0 }
+}
+
+fn body(shape: ShapeType, material: Material, is_static: bool) -> RigidBody {
+    RigidBody::new(shape, material, is_static)
+}
+
+fn rectangle(center: Vector2f<Real>, width: Real, height: Real, rotation: Real) -> ShapeType {
+    ShapeType::Polygon(Polygon::new_rectangle(center, width, height, rotation))
+}
+
+// A string between two points, with the ends fastened to the given bodies
+fn rope(start: Vector2f<Real>, end: Vector2f<Real>, num_joints: usize, a: BodyHandle, b: BodyHandle, bodies: &BodyArena) -> Rc<RefCell<SoftBody>> {
+    let mut rope = SoftBody::new_string(start, end, num_joints);
+    for (joint, handle) in [(0, a), (num_joints - 1, b)] {
+        let obj = bodies.get(handle).unwrap();
+        let rel_pos = obj.shape.get_transform().apply_inv(rope.joints[joint].position);
+        rope.joints[joint].attachment = Some(Attachment { body: handle, rel_pos });
+    }
+    Rc::new(RefCell::new(rope))
+}
+
+fn floor() -> RigidBody {
+    body(rectangle(Vector2f::new(0.0, 25.0), 2000.0, 50.0, 0.0), CONCRETE, true)
+}
+
+// Five rows of wooden boxes stacked on the floor
+fn box_pyramid() -> World {
+    let size = 40.0;
+    let mut bodies = BodyArena::new();
+    bodies.insert(floor());
+    for row in 0..5 {
+        let count = 5 - row;
+        for i in 0..count {
+            let x = (i as Real - (count - 1) as Real / 2.0) * (size + 1.0);
+            let y = -size / 2.0 - row as Real * size;
+            bodies.insert(body(rectangle(Vector2f::new(x, y), size, size, 0.0), WOOD, false));
+        }
+    }
+    World::new(PhysicsData::default(), bodies, vec![])
+}
+
+// Five touching steel balls hanging from a beam, with the first one lifted to the side
+fn newtons_cradle() -> World {
+    let radius = 20.0;
+    let length = 250.0;
+    let lift = PI / 3.0;
+
+    let mut bodies = BodyArena::new();
+    let beam = bodies.insert(body(rectangle(Vector2f::new(0.0, -length - 10.0), 400.0, 20.0, 0.0), STEEL, true));
+    let mut strings = vec![];
+    for i in 0..5 {
+        let pivot = Vector2f::new((i as Real - 2.0) * radius * 2.0, -length);
+        let angle = if i == 0 { lift } else { 0.0 };
+        let dir = Vector2f::new(-angle.sin(), angle.cos());
+        let ball = bodies.insert(body(ShapeType::Circle(Circle::new(pivot + dir * (length + radius), radius, 0.0)), STEEL, false));
+        strings.push(rope(pivot, pivot + dir * length, 8, beam, ball, &bodies));
+    }
+    World::new(PhysicsData::default(), bodies, strings)
+}
+
+// A line of standing wooden dominoes, where the first one is tipped over
+fn domino_line() -> World {
+    let (width, height, spacing) = (10.0, 60.0, 35.0);
+    let mut bodies = BodyArena::new();
+    bodies.insert(floor());
+    for i in 0..10 {
+        let mut domino = body(rectangle(Vector2f::new(i as Real * spacing, -height / 2.0), width, height, 0.0), WOOD, false);
+        if i == 0 {
+            domino.angular_velocity = 4.0;
+        }
+        bodies.insert(domino);
+    }
+    World::new(PhysicsData::default(), bodies, vec![])
+}
+
+// The steel and ice ramps of the playground, with a wooden box and a steel ball on each of them
+fn inclined_plane() -> World {
+    let ramp1 = rectangle(Vector2f::new(450.0, 300.0), 400.0, 25.0, 0.5);
+    let mut ramp2 = ramp1.clone();
+    ramp2.translate(Vector2f::new(400.0, -150.0));
+    ramp2.rotate(-1.0);
+
+    let mut bodies = BodyArena::new();
+    bodies.insert(body(rectangle(Vector2f::new(640.0, 650.0), 4000.0, 50.0, 0.0), CONCRETE, true));
+    for (ramp, material) in [(ramp1, STEEL), (ramp2, ICE)] {
+        let (center, rotation) = (ramp.get_center(), ramp.get_rotation());
+        let up = ramp.get_transform().rotation.apply(Vector2f::new(0.0, -1.0));
+        let along = ramp.get_transform().rotation.apply(Vector2f::new(1.0, 0.0));
+        bodies.insert(body(ramp, material, true));
+        bodies.insert(body(rectangle(center + up * 35.0 - along * 100.0, 40.0, 40.0, rotation), WOOD, false));
+        bodies.insert(body(ShapeType::Circle(Circle::new(center + up * 35.0 + along * 50.0, 20.0, 0.0)), STEEL, false));
+    }
+    World::new(PhysicsData::default(), bodies, vec![])
+}
+
+// A rope hung between two pillars, with two boxes dropped onto it
+fn rope_bridge() -> World {
+    let mut bodies = BodyArena::new();
+    bodies.insert(body(rectangle(Vector2f::new(0.0, 400.0), 1000.0, 50.0, 0.0), CONCRETE, true));
+    let left = bodies.insert(body(rectangle(Vector2f::new(-220.0, 100.0), 40.0, 200.0, 0.0), CONCRETE, true));
+    let right = bodies.insert(body(rectangle(Vector2f::new(220.0, 100.0), 40.0, 200.0, 0.0), CONCRETE, true));
+    bodies.insert(body(rectangle(Vector2f::new(0.0, -80.0), 30.0, 30.0, 0.0), WOOD, false));
+    bodies.insert(body(rectangle(Vector2f::new(80.0, -140.0), 30.0, 30.0, 0.0), WOOD, false));
+
+    let bridge = rope(Vector2f::new(-200.0, 0.0), Vector2f::new(200.0, 0.0), 17, left, right, &bodies);
+    World::new(PhysicsData::default(), bodies, vec![bridge])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Checks the goldens of the precision the tests are built with
+    #[test]
+    fn scenarios_match_their_goldens() {
+        for scenario in SCENARIOS.iter() {
+            let golden = scenario.load_golden().unwrap_or_else(|e| panic!("{}: {}", scenario.name, e));
+            if let Err(drift) = scenario.compare(&golden, &scenario.run()) {
+                panic!("{} drifted, {}", scenario.name, drift);
+            }
+        }
+    }
+}
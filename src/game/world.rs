@@ -0,0 +1,344 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+
+use crate::utils::real::Real;
+use crate::physics::arena::{BodyArena, BodyHandle};
+use crate::physics::broadphase::SpatialGrid;
+use crate::physics::circle::Circle;
+use crate::physics::collision::{shape_vs_shape, AABB};
+use crate::physics::fluid::Fluid;
+use crate::physics::fracture::fracture;
+use crate::physics::material::MaterialName;
+use crate::physics::island::{self, Island};
+use crate::physics::polygon::Polygon;
+use crate::physics::query::{self, QueryFilter, QueryResult, RaycastHit, ShapeCastHit};
+use crate::physics::rigid_body::RigidBody;
+use crate::physics::shape::Shape;
+use crate::physics::shape_type::ShapeType;
+use crate::physics::soft_body::{SoftBody, JOINT_RADIUS};
+use crate::physics::tiled_mesh::TiledMesh;
+use crate::Vector2f;
+
+use super::benchmarks::BenchmarkTests;
+use super::diagnostics::EnergySample;
+use super::{ContactDebug, PhysicsData};
+
+const PHYSICS_ITERATIONS: usize = 8;
+const BROADPHASE_CELL_SIZE: Real = 128.0;
+
+// How much of the blast radius is carved out of the terrain
+const CRATER_RATIO: Real = 0.3;
+
+// Everything taking part in the simulation, without anything needed to draw it, so it can also
+// be stepped without a window
+pub struct World {
+    pub physics: PhysicsData,
+    pub bodies: BodyArena,
+    pub strings: Vec<Rc<RefCell<SoftBody>>>,
+    pub fluids: Vec<Fluid>,
+    pub contacts: Vec<ContactDebug>,
+    // The material and change in speed of every body that was hit during the last step
+    pub impacts: Vec<(MaterialName, Real)>,
+    // Bodies that never break into fragments, like the player
+    pub unbreakable: Vec<BodyHandle>,
+    // The handles of the bodies, sorted into cells for the world queries
+    pub broadphase: SpatialGrid<BodyHandle>,
+    pub joint_broadphase: SpatialGrid<(usize, usize)>,
+    pub benchmarks: BenchmarkTests,
+    // Seeds the shuffling of the collisions in every island. Seed it to make the simulation repeatable.
+    pub rng: StdRng,
+}
+
+impl World {
+    pub fn new(physics: PhysicsData, bodies: BodyArena, strings: Vec<Rc<RefCell<SoftBody>>>) -> Self {
+        let mut world = Self {
+            physics,
+            bodies,
+            strings,
+            fluids: vec![],
+            contacts: vec![],
+            impacts: vec![],
+            unbreakable: vec![],
+            broadphase: SpatialGrid::new(BROADPHASE_CELL_SIZE),
+            joint_broadphase: SpatialGrid::new(BROADPHASE_CELL_SIZE),
+            benchmarks: BenchmarkTests::default(),
+            rng: StdRng::from_os_rng(),
+        };
+        world.update_broadphase();
+        world
+    }
+
+    // Advances the simulation by dt
+    pub fn step(&mut self, dt: Real) {
+        self.benchmarks.updating.start();
+        self.contacts.clear();
+        self.impacts.clear();
+        self.physics.dt = dt;
+
+        for obj in self.bodies.bodies_mut() {
+            obj.update_velocity(&self.physics);
+            for fluid in self.fluids.as_slice() {
+                fluid.apply_forces(obj, &self.physics);
+            }
+        }
+
+        // Detect body on body collisions and push out. The pairs are split into islands that
+        // share no dynamic bodies, so each island can be handled on its own thread.
+        self.benchmarks.rigid_collision_detection.start();
+        let dt = self.physics.dt;
+        let body_count = self.bodies.len();
+        let pairs = island::find_pairs(&self.bodies, dt);
+        let mut islands = Island::build(&mut self.bodies, &pairs);
+        let work = island::for_each_island(&mut islands, |island| timed(|| island.detect_collisions(dt)));
+        self.benchmarks.rigid_collision_detection.add_work(work.iter().sum());
+        self.benchmarks.rigid_collision_detection.stop(Some(body_count));
+
+        // Resolve collisions, see island::solve_islands
+        self.benchmarks.rigid_collision_solving.start();
+        let work = island::solve_islands(&mut islands, PHYSICS_ITERATIONS, &mut self.rng, &self.physics.material_pairs);
+        self.benchmarks.rigid_collision_solving.add_work(work.iter().sum());
+        let order = island::collision_order(&islands);
+        for &(i, c) in order.as_slice() {
+            let collision = &islands[i].collisions[c].1;
+            self.contacts.extend(collision.contacts.iter().map(|&contact| ContactDebug { contact, normal: collision.normal}));
+        }
+        let collision_count = order.len();
+        self.benchmarks.rigid_collision_solving.stop(Some(collision_count));
+
+        self.fracture_bodies();
+
+        // Resolve constraints with verlet integration
+        let mut new_strings = vec![];
+        for string in self.strings.as_slice() {
+            let mut string = string.borrow_mut();
+            if let Some(new_string) = string.resolve_constraints(
+                &self.physics, 
+                &mut self.bodies, 
+                &mut self.contacts, 
+            ) {
+                new_strings.push(Rc::new(RefCell::new(new_string)));
+            }
+        }
+        self.strings.extend(new_strings);  
+
+        for obj in self.bodies.bodies_mut() {
+            obj.update_position(self.physics.dt);
+        }
+        self.update_broadphase();
+        self.benchmarks.updating.stop(None);
+    }
+
+    // The energy and momentum of everything that can move, see EnergySample
+    pub fn measure_energy(&self) -> EnergySample {
+        EnergySample::measure(&self.bodies, &self.strings, &self.physics)
+    }
+
+    // Sorts the bodies and joints into the grids again, must be called after they move or are added
+    pub fn update_broadphase(&mut self) {
+        self.broadphase.clear();
+        for (handle, obj) in self.bodies.iter() {
+            self.broadphase.insert(&obj.shape.get_aabb(), handle);
+        }
+
+        self.joint_broadphase.clear();
+        for (i, string) in self.strings.iter().enumerate() {
+            for (j, joint) in string.borrow().joints.iter().enumerate() {
+                let extent = Vector2f::new(JOINT_RADIUS, JOINT_RADIUS);
+                let aabb = AABB { top_left: joint.position - extent, bottom_right: joint.position + extent };
+                self.joint_broadphase.insert(&aabb, (i, j));
+            }
+        }
+    }
+
+    // Returns the bodies containing the point and the joints touching it
+    pub fn query_point(&self, point: Vector2f<Real>, filter: &QueryFilter) -> QueryResult {
+        let region = AABB { top_left: point, bottom_right: point };
+        QueryResult {
+            bodies: query::overlap_bodies(&self.bodies, &self.broadphase, &region, filter, |shape| shape.contains_point(point)),
+            joints: query::overlap_joints(&self.strings, &self.joint_broadphase, &region, filter, |joint| {
+                (joint - point).len_squared() <= JOINT_RADIUS * JOINT_RADIUS
+            }),
+        }
+    }
+
+    // Returns the bodies and joints overlapping the box
+    #[allow(dead_code)]
+    pub fn query_aabb(&self, aabb: &AABB, filter: &QueryFilter) -> QueryResult {
+        let center = (aabb.top_left + aabb.bottom_right) / 2.0;
+        let region = ShapeType::Polygon(Polygon::new_rectangle(center, aabb.width(), aabb.height(), 0.0));
+        let extent = Vector2f::new(JOINT_RADIUS, JOINT_RADIUS);
+        let (min, max) = (aabb.top_left - extent, aabb.bottom_right + extent);
+        QueryResult {
+            bodies: query::overlap_bodies(&self.bodies, &self.broadphase, aabb, filter, |shape| {
+                shape.contains_point(center) || shape_vs_shape(&region, shape).is_some()
+            }),
+            joints: query::overlap_joints(&self.strings, &self.joint_broadphase, aabb, filter, |joint| {
+                joint.x >= min.x && joint.x <= max.x && joint.y >= min.y && joint.y <= max.y
+            }),
+        }
+    }
+
+    // Returns the bodies and joints overlapping the shape placed at the given position and rotation
+    pub fn query_shape(&self, shape: &ShapeType, position: Vector2f<Real>, rotation: Real, filter: &QueryFilter) -> QueryResult {
+        let mut shape = shape.clone();
+        shape.set_center(position);
+        shape.set_rotation(rotation);
+        let region = shape.get_aabb();
+        QueryResult {
+            bodies: query::overlap_bodies(&self.bodies, &self.broadphase, &region, filter, |other| shape_vs_shape(&shape, other).is_some()),
+            joints: query::overlap_joints(&self.strings, &self.joint_broadphase, &region, filter, |joint| {
+                shape.contains_point(joint) || shape_vs_shape(&shape, &ShapeType::Circle(Circle::new(joint, JOINT_RADIUS, 0.0))).is_some()
+            }),
+        }
+    }
+
+    // Returns the closest body hit by the ray within the max distance
+    pub fn raycast(&self, origin: Vector2f<Real>, dir: Vector2f<Real>, max_dist: Real, filter: &QueryFilter) -> Option<RaycastHit> {
+        query::raycast(&self.bodies, &self.broadphase, origin, dir, max_dist, filter)
+    }
+
+    // Returns every body hit by the ray within the max distance, closest first
+    #[allow(dead_code)]
+    pub fn raycast_all(&self, origin: Vector2f<Real>, dir: Vector2f<Real>, max_dist: Real, filter: &QueryFilter) -> Vec<RaycastHit> {
+        query::raycast_all(&self.bodies, &self.broadphase, origin, dir, max_dist, filter)
+    }
+
+    // Returns the first body the shape touches when moved from the given position and rotation
+    // along the translation
+    #[allow(dead_code)]
+    pub fn shape_cast(&self, shape: &ShapeType, position: Vector2f<Real>, rotation: Real, translation: Vector2f<Real>, filter: &QueryFilter) -> Option<ShapeCastHit> {
+        query::shape_cast(&self.bodies, &self.broadphase, shape, position, rotation, translation, filter)
+    }
+
+    // Replaces the bodies that were hit harder than their material can handle with their fragments,
+    // and keeps every impact around for the sounds
+    fn fracture_bodies(&mut self) {
+        for handle in self.bodies.handles() {
+            let obj = self.bodies.get_mut(handle).unwrap();
+            let impact = obj.impact.take();
+            if let Some(impact) = impact {
+                self.impacts.push((obj.material.name, impact.impulse * obj.get_inv_mass()));
+            }
+
+            let fragments = match impact {
+                Some(impact) if !self.unbreakable.contains(&handle) => fracture(obj, &impact),
+                _ => None,
+            };
+
+            if let Some(fragments) = fragments {
+                let broken = self.bodies.remove(handle).unwrap();
+                let fragments: Vec<_> = fragments.into_iter().map(|f| self.bodies.insert(f)).collect();
+                self.reattach_strings(handle, &broken, &fragments);
+            }
+        }
+    }
+
+    // Pushes every body and free joint within the radius away from the center, with an impulse
+    // falling off linearly towards the edge. The impulse hits the point of the body closest to the
+    // center, so bodies also spin. Bodies and joints hidden behind static bodies are shielded.
+    pub fn explode(&mut self, center: Vector2f<Real>, radius: Real, impulse: Real) {
+        let blast = ShapeType::Circle(Circle::new(center, radius, 0.0));
+        let in_range = self.query_shape(&blast, center, 0.0, &QueryFilter { static_bodies: false, ..Default::default() });
+
+        // Static bodies around the center can not shield it from itself
+        let cover = QueryFilter {
+            dynamic_bodies: false,
+            exclude: self.query_point(center, &QueryFilter { dynamic_bodies: false, joints: false, ..Default::default() }).bodies,
+            ..Default::default()
+        };
+        let is_visible = |target: Vector2f<Real>| {
+            let to_target = target - center;
+            let distance = to_target.len();
+            distance <= Real::EPSILON || self.raycast(center, to_target, distance, &cover).is_none_or(|hit| hit.fraction >= 1.0 - 1e-6)
+        };
+        let falloff = |distance: Real| impulse * (1.0 - distance / radius).max(0.0);
+
+        // The bodies are only pushed once all of them have been checked, so no body moves out of
+        // the way of the occlusion rays
+        let mut impulses = vec![];
+        for handle in in_range.bodies {
+            let (point, body_center) = {
+                let shape = &self.bodies.get(handle).unwrap().shape;
+                let point = if shape.contains_point(center) { center } else { shape.find_closest_surface_point(center).0 };
+                (point, shape.get_center())
+            };
+            if !is_visible(point) && !is_visible(body_center) {
+                continue;
+            }
+
+            let offset = point - center;
+            let direction = if offset.len_squared() > Real::EPSILON {
+                offset.normalize()
+            } else if (body_center - center).len_squared() > Real::EPSILON {
+                (body_center - center).normalize()
+            } else {
+                Vector2f::new(0.0, -1.0)
+            };
+            impulses.push((handle, direction * falloff(offset.len()), point));
+        }
+
+        for handle in in_range.joints {
+            let mut string = handle.soft_body.borrow_mut();
+            let joint = &mut string.joints[handle.index];
+            let offset = joint.position - center;
+            if offset.len_squared() <= Real::EPSILON || !is_visible(joint.position) {
+                continue;
+            }
+            joint.apply_impulse(offset.normalize() * falloff(offset.len()));
+        }
+
+        for (handle, impulse, point) in impulses {
+            self.bodies.get_mut(handle).unwrap().apply_impulse(impulse, point);
+        }
+
+        self.deform_terrain(center, radius * CRATER_RATIO);
+    }
+
+    // Carves a circle out of every heightfield, rebuilding the meshes of the ones that changed
+    pub fn deform_terrain(&mut self, center: Vector2f<Real>, radius: Real) {
+        for obj in self.bodies.bodies_mut() {
+            if let ShapeType::Heightfield(heightfield) = &mut obj.shape && heightfield.deform(center, radius) {
+                obj.mesh = TiledMesh::from(&*heightfield);
+            }
+        }
+    }
+
+    // Moves the string attachments of a broken body onto the fragment holding the attachment point
+    fn reattach_strings(&self, handle: BodyHandle, broken: &RigidBody, fragments: &[BodyHandle]) {
+        for string in self.strings.as_slice() {
+            let mut string = string.borrow_mut();
+            for joint in string.joints.as_mut_slice() {
+                if let Some(attachment) = &mut joint.attachment && attachment.body == handle {
+                    let point = attachment.get_attachment_point(broken);
+                    let fragment = fragments.iter()
+                        .map(|&f| (f, self.bodies.get(f).unwrap()))
+                        .find(|(_, obj)| obj.shape.contains_point(point))
+                        .or_else(|| fragments.iter().map(|&f| (f, self.bodies.get(f).unwrap())).min_by(|(_, a), (_, b)| {
+                            let da = (a.shape.get_center() - point).len_squared();
+                            let db = (b.shape.get_center() - point).len_squared();
+                            da.total_cmp(&db)
+                        }));
+
+                    match fragment {
+                        Some((fragment, obj)) => {
+                            attachment.rel_pos = obj.shape.get_transform().apply_inv(point);
+                            attachment.body = fragment;
+                        }
+                        None => joint.attachment = None,
+                    }
+                }
+            }
+        }
+    }
+}
+
+// Runs the task, returning how long it took
+fn timed(task: impl FnOnce()) -> Duration {
+    let start = Instant::now();
+    task();
+    start.elapsed()
+}
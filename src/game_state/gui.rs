@@ -6,6 +6,7 @@ use crate::color;
 use crate::Vector2f;
 use piston_window::*;
 use crate::game::Game;
+use crate::game::diagnostics::ENERGY_LOG_PATH;
 
 pub struct GUI {
     pub components: Vec<Box<dyn GUIComponent>>,
@@ -103,12 +104,60 @@ impl GUI {
             },
         );
 
+        button_position.y += 75.0;
+        rect.color = if model.settings.view.show_energy { color::CYAN } else { color::RED };
+        let display = Display::new(rect, DisplayContent::Text(text, "Show energy".to_string()));
+        let show_energy_button = GUIButton::new(
+            button_position, 
+            button_size, 
+            display,
+            |btn, event, game| { 
+                match event {
+                    GUIEvent::Click => {
+                        game.settings.view.show_energy = !game.settings.view.show_energy;
+                        btn.display.rect.color = if game.settings.view.show_energy { color::CYAN } else { color::RED };
+                    }
+                    GUIEvent::Hover => btn.display.rect.border = Rectangle::new_round_border(color::BLACK, 15.0, 2.0).border,
+                    GUIEvent::UnHover => btn.display.rect.border = Rectangle::new_round_border(color::BLACK, 15.0, 1.0).border,
+                    _ => {}
+                }
+                event
+            },
+        );
+
+        button_position.y += 75.0;
+        rect.color = if model.energy.is_logging() { color::CYAN } else { color::RED };
+        let display = Display::new(rect, DisplayContent::Text(text, "Log energy".to_string()));
+        let log_energy_button = GUIButton::new(
+            button_position, 
+            button_size, 
+            display,
+            |btn, event, game| { 
+                match event {
+                    GUIEvent::Click => {
+                        if game.energy.is_logging() {
+                            game.energy.stop_log();
+                        } else if let Err(e) = game.energy.start_log(ENERGY_LOG_PATH) {
+                            println!("Failed to create {}: {}", ENERGY_LOG_PATH, e);
+                        }
+                        btn.display.rect.color = if game.energy.is_logging() { color::CYAN } else { color::RED };
+                    }
+                    GUIEvent::Hover => btn.display.rect.border = Rectangle::new_round_border(color::BLACK, 15.0, 2.0).border,
+                    GUIEvent::UnHover => btn.display.rect.border = Rectangle::new_round_border(color::BLACK, 15.0, 1.0).border,
+                    _ => {}
+                }
+                event
+            },
+        );
+
         Self { 
             components: vec![
                 Box::new(back_button),
                 Box::new(show_velocity_button),
                 Box::new(show_contacts_button),
                 Box::new(show_tiles_button),
+                Box::new(show_energy_button),
+                Box::new(log_energy_button),
             ] 
         }
     }
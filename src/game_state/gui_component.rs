@@ -1,5 +1,6 @@
 use std::rc::Rc;
 
+use crate::physics::material::MaterialName;
 use crate::physics::rigid_body::RigidBody;
 use crate::physics::shape::Renderable;
 use crate::physics::shape_type::ShapeType;
@@ -232,8 +233,9 @@ impl GUIComponent for GUIButton {
 pub enum DisplayContent {
     Text(Text, String),
     Shape(ShapeType),
-    Body(RigidBody, Rc<Texture>),
+    Body(Box<RigidBody>, Rc<Texture>),
     Image(Rc<Texture>),
+    Material(MaterialName, Rc<Texture>),
 }
 
 // A rectangular box with centered text
@@ -264,14 +266,14 @@ impl Display {
                 text.draw(&str, glyphs, &c.draw_state, c.transform.trans(text_x, text_y), gl).unwrap();
             }
             DisplayContent::Shape(shape) => {
-                let offset = (position + size / 2.0) - shape.get_center();
+                let offset = (position + size / 2.0) - shape.get_center().cast();
                 shape.draw(c.transform.trans_pos(offset), gl, color::BLACK);
             } 
             DisplayContent::Body(body, tex) => {
-                let offset = (position + size / 2.0) - body.shape.get_center();
+                let offset = (position + size / 2.0) - body.shape.get_center().cast();
                 body.draw(c.transform.trans_pos(offset), &tex, c, gl);
             }
-            DisplayContent::Image(tex) => {
+            DisplayContent::Image(tex) | DisplayContent::Material(_, tex) => {
                 let img = Image::new().rect(rect);
                 img.draw(tex.as_ref(), &c.draw_state, c.transform.trans_pos(position), gl);
             }
@@ -5,18 +5,15 @@ use std::rc::Rc;
 use crate::game;
 use crate::game::game_controller::ControlArgs;
 use crate::game::Utility;
-use crate::physics::material::CONCRETE;
-use crate::physics::material::ICE;
-use crate::physics::material::STEEL;
-use crate::physics::material::WOOD;
 use crate::physics::rigid_body::RigidBody;
 use crate::physics::shape::Shape;
 use crate::physics::soft_body::Attachment;
 use crate::physics::soft_body::Joint;
 use crate::physics::soft_body::SoftBody;
-use crate::physics::*;
+use crate::physics::query::QueryFilter;
 use crate::game_state::gui_component::*;
 use crate::Vector2f;
+use crate::utils::real::{from_f64, to_f64, Real};
 use crate::color;
 use crate::Text;
 use crate::GlyphCache;
@@ -24,6 +21,8 @@ use crate::game_state::GameState;
 use crate::physics::circle::Circle;
 use crate::physics::polygon::Polygon;
 use crate::physics::shape_type::ShapeType;
+use crate::physics::compound::Compound;
+use crate::physics::capsule::Capsule;
 use super::pause_state::PauseState;
 use crate::Texture;
 use graphics::math::translate;
@@ -70,17 +69,18 @@ impl GameState for PlayingState {
         match &game.settings.utility {
             game::Utility::Launch => {
                 if let Some(target) = game.projectile.target {
-                    let projectile_pos = game.projectile.body.shape.get_center();
+                    let (projectile_pos, target) = (game.projectile.body.shape.get_center().cast::<f64>(), target.cast::<f64>());
                     let line = [projectile_pos.x, projectile_pos.y, target.x, target.y];
                     graphics::line(color::BLACK, 1.0, line, game.camera_transform, gl);
 
                     let projectile = game.projectile.body.scale(game.projectile.scale);
-                    projectile.draw(game.camera_transform, game.textures.get(&projectile.material.name).unwrap(), c, gl);
+                    projectile.draw(game.camera_transform, game.get_texture(projectile.material.name), c, gl);
                 }
             }
             game::Utility::String(joints) => {
                 SoftBody::from(joints.clone()).draw(game.camera_transform, game.context, gl);
             }
+            game::Utility::Explode => {}
         }
     }   
 
@@ -143,38 +143,39 @@ impl GameState for PlayingState {
         
         // Update game logic
         if let Some(args) = e.update_args() {
-            game.update(args.dt);
+            game.update(from_f64(args.dt));
         }
 
         let dims = Vector2f::from(game.context.get_view_size());
         let inv_scale = 1.0 / game.settings.camera.scale;
         let transform = translate(game.settings.camera.position.into()).scale(inv_scale, inv_scale).trans_pos(-dims / 2.0);
-        let cursor_world_position = Vector2f::from(row_mat2x3_transform_pos2(transform, control_args.cursor_pos().into()));
+        let cursor_world_position: Vector2f<Real> = Vector2f::from(row_mat2x3_transform_pos2(transform, control_args.cursor_pos().into())).cast();
 
         // Set target on press
         if let Some(Button::Mouse(MouseButton::Left)) = e.press_args() {
+            let filter = QueryFilter { joints: false, ..Default::default() };
+            let hovered = game.world.query_point(cursor_world_position, &filter).bodies.first().cloned();
             match &mut game.settings.utility {
                 game::Utility::Launch => if !interaction {
-                    game.projectile.target = Some(cursor_world_position.into())
+                    game.projectile.target = Some(cursor_world_position)
                 },
                 game::Utility::String(joints) => if !interaction {
                     let mut position = cursor_world_position;
                     let mut attachment = None;
-                    for obj_ref in game.bodies.as_slice() {
-                        let obj = obj_ref.borrow();
-                        if obj.shape.contains_point(position) {
-                            position = if obj.is_static {
-                                obj.shape.find_closest_surface_point(position).0
-                            } else {
-                                obj.shape.get_center()
-                            };
-                            let rel_pos = (position - obj.shape.get_center()).rotate(-obj.shape.get_rotation());
-                            attachment = Some(Attachment { obj_ref: obj_ref.clone(), rel_pos });
-                            break;
-                        } 
-                    } 
+                    if let Some(handle) = hovered && let Some(obj) = game.world.bodies.get(handle) {
+                        position = if obj.is_static {
+                            obj.shape.find_closest_surface_point(position).0
+                        } else {
+                            obj.shape.get_center()
+                        };
+                        let rel_pos = obj.shape.get_transform().apply_inv(position);
+                        attachment = Some(Attachment { body: handle, rel_pos });
+                    }
                     joints.push(Joint::new(position, attachment));
                 }
+                game::Utility::Explode => if !interaction {
+                    game.explode(cursor_world_position, game::EXPLOSION_RADIUS, game::EXPLOSION_IMPULSE);
+                }
             }
         }
 
@@ -187,11 +188,12 @@ impl GameState for PlayingState {
                         let shape = game.projectile.body.shape.scale(game.projectile.scale);
                         let mut body = RigidBody::new(shape, game.projectile.body.material, false);
                         body.linear_velocity = velocity;
-                        game.bodies.push(Rc::new(RefCell::new(body)));
+                        game.world.bodies.insert(body);
+                        game.world.update_broadphase();
                         game.projectile.target = None;                            
                     }
                 },
-                game::Utility::String(_) => {}
+                game::Utility::String(_) | game::Utility::Explode => {}
             }
         }
 
@@ -203,23 +205,22 @@ impl GameState for PlayingState {
             }
             Utility::String(joints) => {
                 if let Some(Button::Keyboard(Key::NumPadEnter)) = e.press_args() {
-                    game.strings.push(Rc::new(RefCell::new(SoftBody::from(joints.clone()))));
+                    game.world.strings.push(Rc::new(RefCell::new(SoftBody::from(joints.clone()))));
                     joints.clear();
+                    game.world.update_broadphase();
                 }
             }
+            Utility::Explode => {}
         }
 
-        let mut player = game.player.borrow_mut();
-
-        if control_args.button_pressed(&Button::Keyboard(Key::A)) { player.linear_velocity.x -= 10.0; }
-        if control_args.button_pressed(&Button::Keyboard(Key::D)) { player.linear_velocity.x += 10.0; }
+        if let Some(player) = game.world.bodies.get_mut(game.player) {
+            if control_args.button_pressed(&Button::Keyboard(Key::A)) { player.linear_velocity.x -= 10.0; }
+            if control_args.button_pressed(&Button::Keyboard(Key::D)) { player.linear_velocity.x += 10.0; }
+            if let Some(Button::Keyboard(Key::Space)) = e.press_args() { player.linear_velocity.y -= 200.0; }
+        }
 
-        if let Some(Button::Keyboard(key)) = e.press_args() {
-            match key {
-                Key::Space => player.linear_velocity.y -= 200.0,  
-                Key::Escape => next_state = Some(Box::new(PauseState::from(&*game))),
-                _ => {}
-            }
+        if let Some(Button::Keyboard(Key::Escape)) = e.press_args() {
+            next_state = Some(Box::new(PauseState::from(&*game)));
         }
 
         next_state
@@ -231,12 +232,12 @@ impl From<&Game> for PlayingState {
         let dimensions: Vector2f<f64> = [1280.0, 720.0].into();
         let mut gravity_slider = GUISlider2D::new(Vector2f::new(1055.0, 100.0), 200.0, |value, event, game| {
             match event {
-                GUIEvent::Change => game.physics.gravity = value * 500.0,
+                GUIEvent::Change => game.world.physics.gravity = value.cast() * 500.0,
                 _ => {}
             }
             event
         });
-        gravity_slider.value = value.physics.gravity / 500.0;
+        gravity_slider.value = (value.world.physics.gravity / 500.0).cast();
 
         let rect = Rectangle::new_round_border(color::BLACK, 5.0, 1.0);
         let text = Text::new(20);
@@ -247,7 +248,7 @@ impl From<&Game> for PlayingState {
             text_box,
             |btn, event, game| {
                 if let DisplayContent::Text(_, str) = &mut btn.display.content {
-                    *str = format!("G: {:.2} m/s²", game.physics.gravity.len() / 100.0);
+                    *str = format!("G: {:.2} m/s²", game.world.physics.gravity.len() / 100.0);
                 }
                 match event {
                     GUIEvent::Hover => btn.display.rect.border = Rectangle::new_round_border(color::BLACK, 15.0, 2.0).border,
@@ -278,6 +279,7 @@ impl From<&Game> for PlayingState {
                         btn.display.content = match game.settings.utility {
                             Utility::Launch => DisplayContent::Text(Text::new(20), "L".to_string()),
                             Utility::String(_) => DisplayContent::Text(Text::new(20), "S".to_string()),
+                            Utility::Explode => DisplayContent::Text(Text::new(20), "E".to_string()),
                         }
                     }
                 }
@@ -321,6 +323,24 @@ impl From<&Game> for PlayingState {
             }
         );
 
+        let explode_button = GUIButton::new(
+            Vector2f::new(225.0, dimensions.y - 225.0), 
+            slot_size, 
+            Display::new(
+                Rectangle::new_round_border(color::BLACK, 5.0, 1.0),
+                DisplayContent::Text(Text::new(20), "E".to_string()), 
+            ), 
+            |btn, event, game| {
+                match event {
+                    GUIEvent::Hover => btn.display.rect.border = Rectangle::new_round_border(color::BLACK, 15.0, 2.0).border,
+                    GUIEvent::UnHover => btn.display.rect.border = Rectangle::new_round_border(color::BLACK, 15.0, 1.0).border,
+                    GUIEvent::Click => game.settings.utility = Utility::Explode,
+                    _ => {}
+                }
+                event
+            }
+        );
+
         // Shape selection
         let mut rect = Rectangle::new_round_border(color::BLACK, 5.0, 1.0);
         rect.color = color::GRAY;
@@ -374,13 +394,26 @@ impl From<&Game> for PlayingState {
         let shape4 = Polygon::new(verts, Vector2f::zero(), 0.0);
         shape_slot4.display.content = DisplayContent::Shape(ShapeType::Polygon(shape4));
 
+        // A hammer made of a handle and a head
+        let mut shape_slot5 = shape_slot4.clone();
+        shape_slot5.position.x += 100.0;
+        let handle = Polygon::new_rectangle(Vector2f::new(0.0, 5.0), 8.0, 45.0, 0.0);
+        let head = Polygon::new_rectangle(Vector2f::new(0.0, -22.0), 36.0, 14.0, 0.0);
+        let shape5 = Compound::new(vec![ShapeType::Polygon(handle), ShapeType::Polygon(head)], Vector2f::zero(), 0.0);
+        shape_slot5.display.content = DisplayContent::Shape(ShapeType::Compound(shape5));
+
+        let mut shape_slot6 = shape_slot5.clone();
+        shape_slot6.position.x += 100.0;
+        let shape6 = Capsule::new(Vector2f::zero(), 30.0, 12.0, -0.8);
+        shape_slot6.display.content = DisplayContent::Shape(ShapeType::Capsule(shape6));
+
         let rect = Rectangle::new_round_border(color::BLACK, 5.0, 1.0);
         let shape_button = GUIButton::new(
             Vector2f::new(25.0, 25.0), 
             slot_size, 
             Display::new(rect, DisplayContent::Body(
-                value.projectile.body.scale(value.projectile.scale), 
-                value.textures.get(&value.projectile.body.material.name).unwrap().clone())),
+                Box::new(value.projectile.body.scale(value.projectile.scale)), 
+                value.get_texture(value.projectile.body.material.name).clone())),
             |btn, event, game| {
                 match event {
                     GUIEvent::Click => return GUIEvent::Custom("shape".to_string()),
@@ -388,8 +421,8 @@ impl From<&Game> for PlayingState {
                     GUIEvent::UnHover => { btn.display.rect.border = Rectangle::new_round_border(color::BLACK, 5.0, 1.0).border },
                     _ => {
                         btn.display.content = DisplayContent::Body(
-                            game.projectile.body.scale(game.projectile.scale), 
-                            game.textures.get(&game.projectile.body.material.name).unwrap().clone(),
+                            Box::new(game.projectile.body.scale(game.projectile.scale)), 
+                            game.get_texture(game.projectile.body.material.name).clone(),
                         )
                     },
                 }
@@ -406,71 +439,35 @@ impl From<&Game> for PlayingState {
                     GUIEvent::Click => return GUIEvent::Custom("material".to_string()),
                     GUIEvent::Hover => { btn.display.rect.border = Rectangle::new_round_border(color::BLACK, 5.0, 2.0).border },
                     GUIEvent::UnHover => { btn.display.rect.border = Rectangle::new_round_border(color::BLACK, 5.0, 1.0).border },
-                    _ => btn.display.content = DisplayContent::Image(game.textures.get(&game.projectile.body.material.name).unwrap().clone()),
+                    _ => btn.display.content = DisplayContent::Image(game.get_texture(game.projectile.body.material.name).clone()),
                 }
                 event
             }
         );
    
-        let concrete_slot = GUIButton::new(
-            Vector2f::new(25.0, 225.0), 
-            slot_size, 
-            Display::new(rect, DisplayContent::Image(value.textures.get(&material::MaterialName::Concrete).unwrap().clone())),
-            |btn, event, game| {
-                match event {
-                    GUIEvent::Click => game.projectile.body.material = CONCRETE,
-                    GUIEvent::Hover => { btn.display.rect.border = Rectangle::new_round_border(color::BLACK, 5.0, 2.0).border },
-                    GUIEvent::UnHover => { btn.display.rect.border = Rectangle::new_round_border(color::BLACK, 5.0, 1.0).border },
-                    _ => {}
-                }
-                event
-            }
-        );
-
-        let ice_slot = GUIButton::new(
-            Vector2f::new(125.0, 225.0), 
-            slot_size, 
-            Display::new(rect, DisplayContent::Image(value.textures.get(&material::MaterialName::Ice).unwrap().clone())),
-            |btn, event, game| {
-                match event {
-                    GUIEvent::Click => game.projectile.body.material = ICE,
-                    GUIEvent::Hover => { btn.display.rect.border = Rectangle::new_round_border(color::BLACK, 5.0, 2.0).border },
-                    GUIEvent::UnHover => { btn.display.rect.border = Rectangle::new_round_border(color::BLACK, 5.0, 1.0).border },
-                    _ => {}
-                }
-                event
-            }
-        );
-
-        let wood_slot = GUIButton::new(
-            Vector2f::new(225.0, 225.0), 
-            slot_size, 
-            Display::new(rect, DisplayContent::Image(value.textures.get(&material::MaterialName::Wood).unwrap().clone())),
-            |btn, event, game| {
-                match event {
-                    GUIEvent::Click => game.projectile.body.material = WOOD,
-                    GUIEvent::Hover => { btn.display.rect.border = Rectangle::new_round_border(color::BLACK, 5.0, 2.0).border },
-                    GUIEvent::UnHover => { btn.display.rect.border = Rectangle::new_round_border(color::BLACK, 5.0, 1.0).border },
-                    _ => {}
-                }
-                event
-            }
-        );
-
-        let steel_slot = GUIButton::new(
-            Vector2f::new(325.0, 225.0), 
-            slot_size, 
-            Display::new(rect, DisplayContent::Image(value.textures.get(&material::MaterialName::Steel).unwrap().clone())),
-            |btn, event, game| {
-                match event {
-                    GUIEvent::Click => game.projectile.body.material = STEEL,
-                    GUIEvent::Hover => { btn.display.rect.border = Rectangle::new_round_border(color::BLACK, 5.0, 2.0).border },
-                    GUIEvent::UnHover => { btn.display.rect.border = Rectangle::new_round_border(color::BLACK, 5.0, 1.0).border },
-                    _ => {}
+        // One slot per material in the library, four to a row
+        let mut material_slots: Vec<Box<dyn GUIComponent>> = vec![];
+        for (i, def) in value.materials.definitions().iter().enumerate() {
+            let name = def.material().name;
+            let slot = GUIButton::new(
+                Vector2f::new(25.0 + (i % 4) as f64 * 100.0, 225.0 + (i / 4) as f64 * 100.0), 
+                slot_size, 
+                Display::new(rect, DisplayContent::Material(name, value.get_texture(name).clone())),
+                |btn, event, game| {
+                    match event {
+                        GUIEvent::Click => if let DisplayContent::Material(name, _) = &btn.display.content 
+                            && let Some(material) = game.materials.get(*name) {
+                            game.projectile.body.material = material;
+                        },
+                        GUIEvent::Hover => { btn.display.rect.border = Rectangle::new_round_border(color::BLACK, 5.0, 2.0).border },
+                        GUIEvent::UnHover => { btn.display.rect.border = Rectangle::new_round_border(color::BLACK, 5.0, 1.0).border },
+                        _ => {}
+                    }
+                    event
                 }
-                event
-            }
-        );
+            );
+            material_slots.push(Box::new(slot));
+        }
 
         let mut scale = GUISlider::new(
             Vector2f::new(275.0, 60.0), 
@@ -478,23 +475,23 @@ impl From<&Game> for PlayingState {
             color::RED, 
             |value, event, game| {
                 match event {
-                    GUIEvent::Change => game.projectile.scale = (value + 0.25) * 4.0 / 3.0,
+                    GUIEvent::Change => game.projectile.scale = from_f64((value + 0.25) * 4.0 / 3.0),
                     _ => {} 
                 }
                 event
             }
         );
-        scale.value = (value.projectile.scale * 3.0 / 4.0) - 0.25;
+        scale.value = to_f64(value.projectile.scale * 3.0 / 4.0) - 0.25;
 
         Self { 
             gui: GUI { components: vec![Box::new(gravity_display), Box::new(scale), Box::new(shape_button), Box::new(material_button), Box::new(utility_button)] }, 
-            shape_menu: GUI { components: vec![Box::new(shape_slot1), Box::new(shape_slot2), Box::new(shape_slot3), Box::new(shape_slot4)] }, 
+            shape_menu: GUI { components: vec![Box::new(shape_slot1), Box::new(shape_slot2), Box::new(shape_slot3), Box::new(shape_slot4), Box::new(shape_slot5), Box::new(shape_slot6)] }, 
             show_shape_menu: false,
-            material_menu: GUI { components: vec![Box::new(concrete_slot), Box::new(ice_slot), Box::new(wood_slot), Box::new(steel_slot)] },
+            material_menu: GUI { components: material_slots },
             show_material_menu: false,
             physics_menu: GUI { components: vec![Box::new(gravity_slider)] },
             show_physics_menu: false,
-            utility_menu: GUI { components: vec![Box::new(launch_button), Box::new(string_button)] },
+            utility_menu: GUI { components: vec![Box::new(launch_button), Box::new(string_button), Box::new(explode_button)] },
             show_utility_menu: false,
         }   
     }
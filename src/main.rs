@@ -14,7 +14,6 @@ use piston_window::{Filter, TextureSettings};
 use utils::vector2f::Vector2f;
 use glutin_window::GlutinWindow as Window;
 use graphics::*;
-use kira::{AudioManager, AudioManagerSettings, DefaultBackend};
 use opengl_graphics::{GlGraphics, OpenGL, Texture};
 use piston::event_loop::{EventSettings, Events};
 use piston::input::*;
@@ -23,6 +22,7 @@ use glyph_cache::rusttype::GlyphCache;
 use game::*;
 use game::game_controller::*;
 use game::game_view::*;
+use game::scenarios;
 
 static FONT: &str = "/usr/share/fonts/truetype/dejavu/DejaVuSansMono.ttf";
 
@@ -30,6 +30,11 @@ pub const WINDOW_WIDTH: f64 = 1280.0;
 pub const WINDOW_HEIGHT: f64 = 720.0;
 
 fn main() {
+    let args: Vec<_> = std::env::args().collect();
+    if args.get(1).is_some_and(|arg| arg == "--scenarios") {
+        std::process::exit(scenarios::run_suite(&args[2..]));
+    }
+
     // Change this to OpenGL::V2_1 if not working.
     let opengl = OpenGL::V3_2;
 
@@ -42,14 +47,10 @@ fn main() {
     
     let mut gl = GlGraphics::new(opengl);
 
-    let _: Vec<_> = std::env::args().collect();
-
     // Load the font
     let ts = TextureSettings::new().filter(Filter::Nearest);
     let mut glyphs: GlyphCache<'static, (), Texture> = GlyphCache::new(FONT, (), ts).unwrap();
     
-    let _ = AudioManager::<DefaultBackend>::new(AudioManagerSettings::default()).unwrap();
-
     let game = Game::default();
     let mut game_controller = GameController::new(game);
     let mut game_view = GameView::new();
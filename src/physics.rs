@@ -5,5 +5,20 @@ pub mod shape_type;
 pub mod collision;
 pub mod rigid_body;
 pub mod material;
+pub mod material_library;
 pub mod tiled_mesh;
-pub mod soft_body;
\ No newline at end of file
+pub mod soft_body;
+pub mod fluid;
+pub mod fracture;
+pub mod decomposition;
+pub mod compound;
+pub mod capsule;
+pub mod chain;
+pub mod heightfield;
+pub mod gjk;
+pub mod broadphase;
+pub mod query;
+pub mod distance;
+pub mod geometry;
+pub mod arena;
+pub mod island;
@@ -0,0 +1,165 @@
+use super::rigid_body::RigidBody;
+
+// Refers to a body in a BodyArena. The generation is bumped every time a slot is emptied, so a
+// handle to a removed body never finds the body that later takes its slot.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct BodyHandle {
+    index: u32,
+    generation: u32,
+}
+
+impl BodyHandle {
+    pub fn index(&self) -> usize {
+        self.index as usize
+    }
+}
+
+#[derive(Clone)]
+struct Slot {
+    generation: u32,
+    body: Option<RigidBody>,
+}
+
+// Owns the bodies of the world. Bodies are iterated in the order of their slots, which is the
+// order they were inserted in, until removed bodies leave slots to be reused.
+#[derive(Clone, Default)]
+pub struct BodyArena {
+    slots: Vec<Slot>,
+    free: Vec<u32>,
+    len: usize,
+}
+
+impl BodyArena {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn insert(&mut self, body: RigidBody) -> BodyHandle {
+        self.len += 1;
+        if let Some(index) = self.free.pop() {
+            let slot = &mut self.slots[index as usize];
+            slot.body = Some(body);
+            return BodyHandle { index, generation: slot.generation };
+        }
+
+        self.slots.push(Slot { generation: 0, body: Some(body) });
+        BodyHandle { index: self.slots.len() as u32 - 1, generation: 0 }
+    }
+
+    // Returns the removed body, or None if the handle was already dangling
+    pub fn remove(&mut self, handle: BodyHandle) -> Option<RigidBody> {
+        let slot = self.slots.get_mut(handle.index())?;
+        if slot.generation != handle.generation {
+            return None;
+        }
+
+        let body = slot.body.take()?;
+        slot.generation = slot.generation.wrapping_add(1);
+        self.free.push(handle.index);
+        self.len -= 1;
+        Some(body)
+    }
+
+    pub fn contains(&self, handle: BodyHandle) -> bool {
+        self.get(handle).is_some()
+    }
+
+    pub fn get(&self, handle: BodyHandle) -> Option<&RigidBody> {
+        self.slots.get(handle.index())
+            .filter(|slot| slot.generation == handle.generation)
+            .and_then(|slot| slot.body.as_ref())
+    }
+
+    pub fn get_mut(&mut self, handle: BodyHandle) -> Option<&mut RigidBody> {
+        self.slots.get_mut(handle.index())
+            .filter(|slot| slot.generation == handle.generation)
+            .and_then(|slot| slot.body.as_mut())
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    // Not used by the game, but expected next to len
+    #[allow(dead_code)]
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn handles(&self) -> Vec<BodyHandle> {
+        self.iter().map(|(handle, _)| handle).collect()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (BodyHandle, &RigidBody)> {
+        self.slots.iter().enumerate().filter_map(|(i, slot)| {
+            slot.body.as_ref().map(|body| (BodyHandle { index: i as u32, generation: slot.generation }, body))
+        })
+    }
+
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = (BodyHandle, &mut RigidBody)> {
+        self.slots.iter_mut().enumerate().filter_map(|(i, slot)| {
+            let generation = slot.generation;
+            slot.body.as_mut().map(|body| (BodyHandle { index: i as u32, generation }, body))
+        })
+    }
+
+    pub fn bodies(&self) -> impl Iterator<Item = &RigidBody> {
+        self.slots.iter().filter_map(|slot| slot.body.as_ref())
+    }
+
+    pub fn bodies_mut(&mut self) -> impl Iterator<Item = &mut RigidBody> {
+        self.slots.iter_mut().filter_map(|slot| slot.body.as_mut())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::physics::circle::Circle;
+    use crate::physics::material::WOOD;
+    use crate::physics::shape_type::ShapeType;
+    use crate::utils::real::Real;
+    use crate::Vector2f;
+
+    fn ball(x: Real) -> RigidBody {
+        RigidBody::new(ShapeType::Circle(Circle::new(Vector2f::new(x, 0.0), 10.0, 0.0)), WOOD, false)
+    }
+
+    fn x(body: &RigidBody) -> Real {
+        body.shape.get_center().x
+    }
+
+    #[test]
+    fn removed_handles_do_not_find_the_body_reusing_their_slot() {
+        let mut arena = BodyArena::new();
+        let first = arena.insert(ball(1.0));
+        let second = arena.insert(ball(2.0));
+        assert_eq!(arena.len(), 2);
+
+        assert_eq!(arena.remove(first).map(|body| x(&body)), Some(1.0));
+        assert!(arena.remove(first).is_none());
+        assert!(!arena.contains(first));
+        assert_eq!(arena.len(), 1);
+
+        // The new body takes the free slot under a new generation
+        let third = arena.insert(ball(3.0));
+        assert_eq!(third.index(), first.index());
+        assert_ne!(third, first);
+        assert!(arena.get(first).is_none());
+        assert!(arena.get_mut(first).is_none());
+        assert_eq!(arena.get(third).map(x), Some(3.0));
+        assert_eq!(arena.get(second).map(x), Some(2.0));
+    }
+
+    #[test]
+    fn iterates_in_slot_order() {
+        let mut arena = BodyArena::new();
+        let handles: Vec<BodyHandle> = (0..4).map(|i| arena.insert(ball(i as Real))).collect();
+        arena.remove(handles[1]);
+        assert_eq!(arena.bodies().map(x).collect::<Vec<_>>(), vec![0.0, 2.0, 3.0]);
+        assert_eq!(arena.handles(), vec![handles[0], handles[2], handles[3]]);
+
+        arena.insert(ball(9.0));
+        assert_eq!(arena.bodies().map(x).collect::<Vec<_>>(), vec![0.0, 9.0, 2.0, 3.0]);
+    }
+}
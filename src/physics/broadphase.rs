@@ -0,0 +1,126 @@
+use std::collections::{HashMap, HashSet};
+use std::hash::Hash;
+
+use crate::utils::real::Real;
+use crate::Vector2f;
+
+use super::collision::AABB;
+
+// A uniform grid over the bounding boxes of the items, so the items near a region or along a ray
+// can be found without testing every one of them. Items are stored in every cell their bounding
+// box touches.
+#[derive(Clone)]
+pub struct SpatialGrid<T> {
+    cell_size: Real,
+    cells: HashMap<(i32, i32), Vec<T>>,
+    // The range of cells in use, as (min_x, min_y, max_x, max_y)
+    bounds: Option<(i32, i32, i32, i32)>,
+}
+
+impl<T: Copy + Eq + Hash> SpatialGrid<T> {
+    pub fn new(cell_size: Real) -> Self {
+        Self {
+            cell_size,
+            cells: HashMap::new(),
+            bounds: None,
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.cells.clear();
+        self.bounds = None;
+    }
+
+    fn cell_of(&self, p: Vector2f<Real>) -> (i32, i32) {
+        ((p.x / self.cell_size).floor() as i32, (p.y / self.cell_size).floor() as i32)
+    }
+
+    pub fn insert(&mut self, aabb: &AABB, item: T) {
+        let (min_x, min_y) = self.cell_of(aabb.top_left);
+        let (max_x, max_y) = self.cell_of(aabb.bottom_right);
+        for x in min_x..=max_x {
+            for y in min_y..=max_y {
+                self.cells.entry((x, y)).or_default().push(item);
+            }
+        }
+
+        self.bounds = Some(match self.bounds {
+            Some((x0, y0, x1, y1)) => (x0.min(min_x), y0.min(min_y), x1.max(max_x), y1.max(max_y)),
+            None => (min_x, min_y, max_x, max_y),
+        });
+    }
+
+    // Returns every item in the cells touched by the region, without duplicates
+    pub fn query_aabb(&self, aabb: &AABB) -> Vec<T> {
+        let Some((x0, y0, x1, y1)) = self.bounds else {
+            return vec![];
+        };
+
+        let (min_x, min_y) = self.cell_of(aabb.top_left);
+        let (max_x, max_y) = self.cell_of(aabb.bottom_right);
+        let mut seen = HashSet::new();
+        let mut items = vec![];
+        for x in min_x.max(x0)..=max_x.min(x1) {
+            for y in min_y.max(y0)..=max_y.min(y1) {
+                for &item in self.cells.get(&(x, y)).into_iter().flatten() {
+                    if seen.insert(item) {
+                        items.push(item);
+                    }
+                }
+            }
+        }
+
+        items
+    }
+
+    // Returns the items in the cells crossed by the segment from the origin to origin + ray_dir,
+    // in the order the ray reaches them, without duplicates
+    // Source: http://www.cse.yorku.ca/~amana/research/grid.pdf
+    pub fn query_ray(&self, ray_origin: Vector2f<Real>, ray_dir: Vector2f<Real>) -> Vec<T> {
+        let Some((x0, y0, x1, y1)) = self.bounds else {
+            return vec![];
+        };
+
+        let (mut x, mut y) = self.cell_of(ray_origin);
+        let (end_x, end_y) = self.cell_of(ray_origin + ray_dir);
+        let step_x = if ray_dir.x >= 0.0 { 1 } else { -1 };
+        let step_y = if ray_dir.y >= 0.0 { 1 } else { -1 };
+
+        // The fraction of the ray at which the next cell border is crossed, and how far apart the borders are
+        let border = |cell: i32, step: i32, origin: Real, dir: Real| {
+            if dir == 0.0 {
+                return (Real::INFINITY, Real::INFINITY);
+            }
+            let next = (cell + step.max(0)) as Real * self.cell_size;
+            ((next - origin) / dir, self.cell_size / dir.abs())
+        };
+        let (mut t_max_x, t_delta_x) = border(x, step_x, ray_origin.x, ray_dir.x);
+        let (mut t_max_y, t_delta_y) = border(y, step_y, ray_origin.y, ray_dir.y);
+
+        let mut seen = HashSet::new();
+        let mut items = vec![];
+        loop {
+            for &item in self.cells.get(&(x, y)).into_iter().flatten() {
+                if seen.insert(item) {
+                    items.push(item);
+                }
+            }
+
+            // Stop at the end of the ray, or once it has left the used cells for good
+            let leaving = (step_x > 0 && x > x1) || (step_x < 0 && x < x0) || (step_y > 0 && y > y1) || (step_y < 0 && y < y0);
+            if (x == end_x && y == end_y) || leaving || t_max_x.min(t_max_y) > 1.0 {
+                break;
+            }
+
+            if t_max_x < t_max_y {
+                x += step_x;
+                t_max_x += t_delta_x;
+            } else {
+                y += step_y;
+                t_max_y += t_delta_y;
+            }
+        }
+
+        items
+    }
+}
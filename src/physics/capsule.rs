@@ -0,0 +1,127 @@
+use crate::utils::real::consts::PI;
+use crate::utils::real::{to_f64, Real};
+use crate::utils::transform::{Rot2, Transform2};
+use graphics::math::Matrix2d;
+use graphics::Transformed;
+
+use crate::Vector2f;
+use crate::physics::shape::Renderable;
+use crate::GlGraphics;
+use crate::physics::shape::Shape;
+
+use super::collision::{point_segment_distance, AABB};
+
+// A segment with a radius around it. The segment runs along the local x axis through the center.
+#[derive(Clone, Copy)]
+pub struct Capsule {
+    pub half_length: Real,
+    pub radius: Real,
+    pub center: Vector2f<Real>,
+    pub rotation: Real,
+}
+
+impl Renderable for Capsule {
+    fn draw(&self, transform: Matrix2d, gl: &mut GlGraphics, color: [f32; 4]) {
+        let transform = transform.trans_pos(self.center.cast::<f64>()).rot_rad(to_f64(self.rotation));
+        let (half_length, radius) = (to_f64(self.half_length), to_f64(self.radius));
+        let body = [-half_length, -radius, half_length * 2.0, radius * 2.0];
+        graphics::rectangle(color, body, transform, gl);
+        for side in [-1.0, 1.0] {
+            let cap = graphics::rectangle::centered_square(side * half_length, 0.0, radius);
+            graphics::ellipse(color, cap, transform, gl);
+        }
+    }
+}
+
+impl Shape for Capsule {
+    fn area(&self) -> Real {
+        4.0 * self.half_length * self.radius + PI * self.radius * self.radius
+    }
+
+    fn momemnt_of_inertia(&self) -> Real {
+        let (w, h, r) = (self.half_length * 2.0, self.radius * 2.0, self.radius);
+        let rectangle = w * h * (w * w + h * h) / 12.0;
+
+        // Each end is a half circle, moved out to the end of the segment with the parallel axis theorem
+        let half_area = PI * r * r / 2.0;
+        let centroid = 4.0 * r / (3.0 * PI);
+        let half_circle = PI * Real::powi(r, 4) / 4.0 - half_area * centroid * centroid;
+        let distance = self.half_length + centroid;
+
+        rectangle + 2.0 * (half_circle + half_area * distance * distance)
+    }
+
+    fn get_aabb(&self) -> AABB {
+        let (a, b) = self.get_segment();
+        let dims = Vector2f::new(self.radius, self.radius);
+        AABB {
+            top_left: Vector2f::new(a.x.min(b.x), a.y.min(b.y)) - dims,
+            bottom_right: Vector2f::new(a.x.max(b.x), a.y.max(b.y)) + dims,
+        }
+    }
+
+    fn contains_point(&self, point: Vector2f<Real>) -> bool {
+        let (a, b) = self.get_segment();
+        point_segment_distance(point, a, b).0 <= self.radius * self.radius
+    }
+
+    fn find_closest_surface_point(&self, point: Vector2f<Real>) -> (Vector2f<Real>, Vector2f<Real>) {
+        let (a, b) = self.get_segment();
+        let (dist_sq, cp) = point_segment_distance(point, a, b);
+        let normal = if dist_sq > Real::EPSILON {
+            (point - cp).normalize()
+        } else {
+            (b - a).perpendicular().normalize()
+        };
+
+        (cp + normal * self.radius, normal)
+    }
+
+    fn support(&self, direction: Vector2f<Real>) -> Vector2f<Real> {
+        let (a, b) = self.get_segment();
+        let end = if a.dot(direction) > b.dot(direction) { a } else { b };
+        if direction.len_squared() <= Real::EPSILON {
+            return end;
+        }
+        end + direction.normalize() * self.radius
+    }
+}
+
+impl Capsule {
+    pub fn new(center: Vector2f<Real>, length: Real, radius: Real, rotation: Real) -> Self {
+        Self {
+            half_length: length / 2.0,
+            radius,
+            center,
+            rotation,
+        }
+    }
+
+    pub fn from_segment(a: Vector2f<Real>, b: Vector2f<Real>, radius: Real) -> Self {
+        let ab = b - a;
+        Self::new((a + b) / 2.0, ab.len(), radius, ab.y.atan2(ab.x))
+    }
+
+    // Returns the world space end points of the segment
+    pub fn get_segment(&self) -> (Vector2f<Real>, Vector2f<Real>) {
+        let half = Rot2::new(self.rotation).apply(Vector2f::new(self.half_length, 0.0));
+        (self.center - half, self.center + half)
+    }
+
+    // Returns the outline in local space, with each end approximated by the given number of sides
+    pub fn get_local_outline(&self, sides_per_end: u32) -> Vec<Vector2f<Real>> {
+        let mut verts = vec![];
+        for (end, start_angle) in [(self.half_length, -PI / 2.0), (-self.half_length, PI / 2.0)] {
+            for i in 0..=sides_per_end {
+                let angle = start_angle + PI * i as Real / sides_per_end as Real;
+                verts.push(Vector2f::new(end + self.radius * angle.cos(), self.radius * angle.sin()));
+            }
+        }
+        verts
+    }
+
+    pub fn get_outline(&self, sides_per_end: u32) -> Vec<Vector2f<Real>> {
+        let transform = Transform2::new(self.center, self.rotation);
+        self.get_local_outline(sides_per_end).iter().map(|&v| transform.apply(v)).collect()
+    }
+}
@@ -0,0 +1,135 @@
+use graphics::math::Matrix2d;
+
+use crate::utils::real::Real;
+use crate::utils::transform::Transform2;
+use crate::Vector2f;
+use crate::physics::shape::Renderable;
+use crate::GlGraphics;
+use crate::physics::shape::Shape;
+
+use super::collision::{point_segment_distance, AABB};
+
+const CHAIN_LINE_WIDTH: f64 = 2.0;
+
+// A static chain of line segments used for terrain. Looped chains connect the last vertex back to
+// the first. The chain has no area, so it can only be used for static bodies.
+#[derive(Clone)]
+pub struct Chain {
+    pub local_vertices: Vec<Vector2f<Real>>,
+    pub center: Vector2f<Real>,
+    pub rotation: Real,
+    pub looped: bool,
+}
+
+// A segment of the chain along with its neighbouring (ghost) vertices, which are used to tell
+// whether a contact on the end of the segment belongs to the next segment instead
+#[derive(Clone, Copy)]
+pub struct ChainSegment {
+    pub a: Vector2f<Real>,
+    pub b: Vector2f<Real>,
+    pub prev: Option<Vector2f<Real>>,
+    pub next: Option<Vector2f<Real>>,
+}
+
+impl Renderable for Chain {
+    fn draw(&self, transform: Matrix2d, gl: &mut GlGraphics, color: [f32; 4]) {
+        for segment in self.get_segments() {
+            let (a, b) = (segment.a.cast::<f64>(), segment.b.cast::<f64>());
+            let l = [a.x, a.y, b.x, b.y];
+            graphics::line(color, CHAIN_LINE_WIDTH, l, transform, gl);
+        }
+    }
+}
+
+impl Shape for Chain {
+    fn area(&self) -> Real {
+        0.0
+    }
+
+    fn momemnt_of_inertia(&self) -> Real {
+        0.0
+    }
+
+    fn get_aabb(&self) -> AABB {
+        let mut min = Vector2f::new(Real::INFINITY, Real::INFINITY);
+        let mut max = Vector2f::new(Real::NEG_INFINITY, Real::NEG_INFINITY);
+        for v in self.get_transformed_vertices() {
+            min = Vector2f::new(min.x.min(v.x), min.y.min(v.y));
+            max = Vector2f::new(max.x.max(v.x), max.y.max(v.y));
+        }
+
+        AABB { top_left: min, bottom_right: max }
+    }
+
+    fn contains_point(&self, _: Vector2f<Real>) -> bool {
+        false
+    }
+
+    fn find_closest_surface_point(&self, point: Vector2f<Real>) -> (Vector2f<Real>, Vector2f<Real>) {
+        let mut closest_point = Vector2f::zero();
+        let mut normal = Vector2f::zero();
+        let mut distance = Real::INFINITY;
+        for segment in self.get_segments() {
+            let (dist, cp) = point_segment_distance(point, segment.a, segment.b);
+            if dist < distance {
+                closest_point = cp;
+                distance = dist;
+
+                // The chain is two sided, so the normal faces the point
+                normal = (segment.b - segment.a).perpendicular().normalize();
+                if normal.dot(point - segment.a) < 0.0 {
+                    normal = -normal;
+                }
+            }
+        }
+
+        (closest_point, normal)
+    }
+
+    fn support(&self, direction: Vector2f<Real>) -> Vector2f<Real> {
+        self.get_transformed_vertices().into_iter()
+            .max_by(|a, b| a.dot(direction).total_cmp(&b.dot(direction)))
+            .unwrap_or(self.center)
+    }
+}
+
+impl Chain {
+    // Creates a chain from world space vertices
+    pub fn new(vertices: Vec<Vector2f<Real>>, looped: bool) -> Self {
+        let center = vertices.iter().fold(Vector2f::zero(), |sum, &v| sum + v) / vertices.len() as Real;
+
+        Self {
+            local_vertices: vertices.iter().map(|&v| v - center).collect(),
+            center,
+            rotation: 0.0,
+            looped,
+        }
+    }
+
+    pub fn get_transformed_vertices(&self) -> Vec<Vector2f<Real>> {
+        let transform = Transform2::new(self.center, self.rotation);
+        self.local_vertices.iter().map(|&v| transform.apply(v)).collect()
+    }
+
+    pub fn get_segments(&self) -> Vec<ChainSegment> {
+        let verts = self.get_transformed_vertices();
+        let n = verts.len();
+        let vertex = |i: isize| -> Option<Vector2f<Real>> {
+            if self.looped {
+                Some(verts[i.rem_euclid(n as isize) as usize])
+            } else if i >= 0 && (i as usize) < n {
+                Some(verts[i as usize])
+            } else {
+                None
+            }
+        };
+
+        let n_segments = if self.looped { n } else { n.saturating_sub(1) };
+        (0..n_segments as isize).map(|i| ChainSegment {
+            a: vertex(i).unwrap(),
+            b: vertex(i + 1).unwrap(),
+            prev: vertex(i - 1),
+            next: vertex(i + 2),
+        }).collect()
+    }
+}
@@ -1,4 +1,5 @@
-use std::f64::consts::PI;
+use crate::utils::real::consts::PI;
+use crate::utils::real::{to_f64, Real};
 use graphics::math::Matrix2d;
 use graphics::Transformed;
 
@@ -11,27 +12,27 @@ use super::collision::AABB;
 
 #[derive(Clone, Copy)]
 pub struct Circle {
-    pub radius: f64,
-    pub center: Vector2f<f64>,
-    pub rotation: f64,
+    pub radius: Real,
+    pub center: Vector2f<Real>,
+    pub rotation: Real,
 }
 
 impl Renderable for Circle {
     fn draw(&self, transform: Matrix2d, gl: &mut GlGraphics, color: [f32; 4]) {
-        let square = graphics::rectangle::centered_square(0.0, 0.0, self.radius);
-        let transform = transform.trans_pos(self.center).rot_rad(self.rotation);
+        let square = graphics::rectangle::centered_square(0.0, 0.0, to_f64(self.radius));
+        let transform = transform.trans_pos(self.center.cast::<f64>()).rot_rad(to_f64(self.rotation));
 
         graphics::ellipse(color, square, transform, gl);
     }
 }
 
 impl Shape for Circle {
-    fn area(&self) -> f64 {
+    fn area(&self) -> Real {
         self.radius * self.radius * PI
     }
 
-    fn momemnt_of_inertia(&self) -> f64 {
-        PI * f64::powi(self.radius, 4) / 4.0
+    fn momemnt_of_inertia(&self) -> Real {
+        PI * Real::powi(self.radius, 4) / 4.0
     }
 
     fn get_aabb(&self) -> AABB {
@@ -39,19 +40,26 @@ impl Shape for Circle {
         AABB { top_left: self.center - dims, bottom_right: self.center + dims}
     }
 
-    fn contains_point(&self, point: Vector2f<f64>) -> bool {
+    fn contains_point(&self, point: Vector2f<Real>) -> bool {
         (self.center - point).len() <= self.radius
     }
 
-    fn find_closest_surface_point(&self, point: Vector2f<f64>) -> (Vector2f<f64>, Vector2f<f64>) {
+    fn find_closest_surface_point(&self, point: Vector2f<Real>) -> (Vector2f<Real>, Vector2f<Real>) {
         let cp = self.center + (point - self.center).normalize() * self.radius;
         let normal = (cp - self.center).normalize();
         (cp, normal)
     }
+
+    fn support(&self, direction: Vector2f<Real>) -> Vector2f<Real> {
+        if direction.len_squared() <= Real::EPSILON {
+            return self.center;
+        }
+        self.center + direction.normalize() * self.radius
+    }
 }
 
 impl Circle {
-    pub fn new(center: Vector2f<f64>, radius: f64, rotation: f64) -> Self {
+    pub fn new(center: Vector2f<Real>, radius: Real, rotation: Real) -> Self {
         Self { 
             radius, 
             center, 
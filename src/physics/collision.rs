@@ -1,13 +1,19 @@
-use core::f64;
 use std::vec;
 
+use crate::utils::real::Real;
 use crate::utils::helpers::nearly_equal;
 use crate::Vector2f;
 use crate::physics::circle::Circle;
+use crate::physics::capsule::Capsule;
+use crate::physics::chain::{Chain, ChainSegment};
+use crate::physics::heightfield::Heightfield;
+use crate::physics::gjk::{gjk_epa, support_contact};
 use crate::physics::polygon::Polygon;
+use crate::physics::shape::Shape;
+use crate::physics::shape_type::ShapeType;
 
 // Returns distance squared and cp
-pub fn point_segment_distance(p: Vector2f<f64>, a: Vector2f<f64>, b: Vector2f<f64>) -> (f64, Vector2f<f64>) {
+pub fn point_segment_distance(p: Vector2f<Real>, a: Vector2f<Real>, b: Vector2f<Real>) -> (Real, Vector2f<Real>) {
     let ab = b - a;
     let ap = p - a;
 
@@ -24,9 +30,32 @@ pub fn point_segment_distance(p: Vector2f<f64>, a: Vector2f<f64>, b: Vector2f<f6
 }
 
 
-pub fn contact_poly_circle(p: &Polygon, c: &Circle) -> Vec<Vector2f<f64>> {
+// Returns distance squared and the closest points on the segments (a1, a2) and (b1, b2)
+pub fn segment_segment_distance(a1: Vector2f<Real>, a2: Vector2f<Real>, b1: Vector2f<Real>, b2: Vector2f<Real>) -> (Real, Vector2f<Real>, Vector2f<Real>) {
+    if let Some(t) = ray_intersect_segment(a1, a2 - a1, b1, b2) && t <= 1.0 {
+        let p = a1 + (a2 - a1) * t;
+        return (0.0, p, p);
+    }
+
+    let candidates = [
+        { let (d, cp) = point_segment_distance(a1, b1, b2); (d, a1, cp) },
+        { let (d, cp) = point_segment_distance(a2, b1, b2); (d, a2, cp) },
+        { let (d, cp) = point_segment_distance(b1, a1, a2); (d, cp, b1) },
+        { let (d, cp) = point_segment_distance(b2, a1, a2); (d, cp, b2) },
+    ];
+
+    candidates.into_iter().min_by(|a, b| a.0.total_cmp(&b.0)).unwrap()
+}
+
+// Returns the unit vector from a to b, or the fallback when the points are on top of each other
+fn direction_or(a: Vector2f<Real>, b: Vector2f<Real>, fallback: Vector2f<Real>) -> Vector2f<Real> {
+    let ab = b - a;
+    if ab.len_squared() > Real::EPSILON { ab.normalize() } else { fallback }
+}
+
+pub fn contact_poly_circle(p: &Polygon, c: &Circle) -> Vec<Vector2f<Real>> {
     let verts = p.get_transformed_vertices();
-    let mut min_dist_sq = f64::INFINITY;
+    let mut min_dist_sq = Real::INFINITY;
     let mut cp = Vector2f::zero();
     for i in 0..verts.len() {
         let va = verts[i];
@@ -43,13 +72,15 @@ pub fn contact_poly_circle(p: &Polygon, c: &Circle) -> Vec<Vector2f<f64>> {
     vec![cp]
 }
 
-fn find_contacts(a_verts: &Vec<Vector2f<f64>>, b_verts: &Vec<Vector2f<f64>>) -> Vec<Vector2f<f64>> {
+// Returns the squared distance between the closest features along with up to two contact points 
+// and how many of them were found
+fn find_contacts(a_verts: &[Vector2f<Real>], b_verts: &[Vector2f<Real>]) -> (Real, [Vector2f<Real>; 2], usize) {
     let mut contact_count = 0;
     let mut contact1 = Vector2f::zero();
     let mut contact2 = Vector2f::zero();
-    let mut min_dist_sq = f64::INFINITY;
+    let mut min_dist_sq = Real::INFINITY;
 
-    let mut iteration = |verts1: &Vec<Vector2f<f64>>, verts2: &Vec<Vector2f<f64>>| {
+    let mut iteration = |verts1: &[Vector2f<Real>], verts2: &[Vector2f<Real>]| {
         for p in verts1 {
             for i in 0..verts2.len() {
                 let va = verts2[i];
@@ -73,38 +104,131 @@ fn find_contacts(a_verts: &Vec<Vector2f<f64>>, b_verts: &Vec<Vector2f<f64>>) ->
         }
     };
 
-    iteration(&a_verts, &b_verts);
-    iteration(&b_verts, &a_verts);
+    iteration(a_verts, b_verts);
+    iteration(b_verts, a_verts);
+
+    (min_dist_sq, [contact1, contact2], contact_count)
+}
+
+// Concave polygons are tested part by part, keeping the contacts of every pair of parts that is as 
+// close as the closest pair
+pub fn contact_poly_poly(a: &Polygon, b: &Polygon) -> Vec<Vector2f<Real>> {
+    let a_parts = a.get_transformed_parts();
+    let b_parts = b.get_transformed_parts();
+
+    let mut contacts: Vec<Vector2f<Real>> = Vec::with_capacity(2);
+    // Convex polygons are a single part, which is by far the most common case
+    if a_parts.len() == 1 && b_parts.len() == 1 {
+        let (_, cps, count) = find_contacts(&a_parts[0], &b_parts[0]);
+        push_unique(&mut contacts, &cps[..count]);
+        return contacts;
+    }
+
+    let mut pairs = Vec::with_capacity(a_parts.len() * b_parts.len());
+    for a_verts in a_parts {
+        for b_verts in b_parts {
+            pairs.push(find_contacts(a_verts, b_verts));
+        }
+    }
 
-    if contact_count > 1 {
-        return vec![contact1, contact2];
-    } else if contact_count > 0 {
-        return vec![contact1];
+    let min_dist_sq = pairs.iter().map(|(dist_sq, _, _)| *dist_sq).fold(Real::INFINITY, Real::min);
+    for (dist_sq, cps, count) in pairs {
+        if nearly_equal(dist_sq, min_dist_sq, 0.05) {
+            push_unique(&mut contacts, &cps[..count]);
+        }
     }
 
-    return vec![];
+    contacts
 }
 
-pub fn contact_poly_poly(a: &Polygon, b: &Polygon) -> Vec<Vector2f<f64>> {
-    let a_verts = a.get_transformed_vertices();
-    let b_verts = b.get_transformed_vertices();
+// Parts share their inner edges, so the same contact can be found more than once
+fn push_unique(contacts: &mut Vec<Vector2f<Real>>, cps: &[Vector2f<Real>]) {
+    for &cp in cps {
+        if !contacts.iter().any(|c| c.nearly_equal(cp, 0.05)) {
+            contacts.push(cp);
+        }
+    }
+}
 
-    find_contacts(&a_verts, &b_verts)
+pub fn contact_capsule_circle(cap: &Capsule, c: &Circle) -> Vec<Vector2f<Real>> {
+    let (a, b) = cap.get_segment();
+    let (_, cp) = point_segment_distance(c.center, a, b);
+    vec![cp + direction_or(cp, c.center, (b - a).perpendicular().normalize()) * cap.radius]
 }
 
-pub fn contact_poly_segment(p: &Polygon, a: Vector2f<f64>, b: Vector2f<f64>) -> Vec<Vector2f<f64>> {
-    let a_verts = p.get_transformed_vertices();
-    let b_verts = vec![a, b];
+// A capsule resting along another one touches it along a line, so the contacts found from each 
+// end point are kept when they are as close as the closest one
+pub fn contact_capsule_capsule(a: &Capsule, b: &Capsule) -> Vec<Vector2f<Real>> {
+    let (a1, a2) = a.get_segment();
+    let (b1, b2) = b.get_segment();
+    let (min_dist_sq, pa, _) = segment_segment_distance(a1, a2, b1, b2);
+    if min_dist_sq <= Real::EPSILON {
+        return vec![pa];
+    }
 
-    find_contacts(&a_verts, &b_verts)
+    let candidates = [
+        { let (d, cp) = point_segment_distance(a1, b1, b2); (d, a1, cp) },
+        { let (d, cp) = point_segment_distance(a2, b1, b2); (d, a2, cp) },
+        { let (d, cp) = point_segment_distance(b1, a1, a2); (d, cp, b1) },
+        { let (d, cp) = point_segment_distance(b2, a1, a2); (d, cp, b2) },
+    ];
+
+    let mut contacts: Vec<Vector2f<Real>> = vec![];
+    for (dist_sq, pa, pb) in candidates {
+        if !nearly_equal(dist_sq.sqrt(), min_dist_sq.sqrt(), 0.5) {
+            continue;
+        }
+
+        let contact = pa + direction_or(pa, pb, Vector2f::zero()) * a.radius;
+        if !contacts.iter().any(|c| c.nearly_equal(contact, 0.05)) {
+            contacts.push(contact);
+        }
+    }
+
+    contacts
+}
+
+// The contacts are the points on the polygon surface closest to the capsule segment
+pub fn contact_poly_capsule(p: &Polygon, cap: &Capsule) -> Vec<Vector2f<Real>> {
+    let (a, b) = cap.get_segment();
+    let mut candidates = vec![];
+    for verts in p.get_transformed_parts() {
+        for i in 0..verts.len() {
+            let va = verts[i];
+            let vb = verts[(i + 1) % verts.len()];
+            for end in [a, b] {
+                let (dist_sq, cp) = point_segment_distance(end, va, vb);
+                candidates.push((dist_sq.sqrt(), cp));
+            }
+            candidates.push((point_segment_distance(va, a, b).0.sqrt(), va));
+        }
+    }
+
+    let min_dist = candidates.iter().map(|(dist, _)| *dist).fold(Real::INFINITY, Real::min);
+    let mut contacts: Vec<Vector2f<Real>> = vec![];
+    for (dist, cp) in candidates {
+        if nearly_equal(dist, min_dist, 0.5) && !contacts.iter().any(|c| c.nearly_equal(cp, 0.05)) {
+            contacts.push(cp);
+        }
+    }
+
+    contacts
+}
+
+pub fn contact_poly_segment(p: &Polygon, a: Vector2f<Real>, b: Vector2f<Real>) -> Vec<Vector2f<Real>> {
+    let (_, cps, count) = find_contacts(p.get_transformed_vertices(), &[a, b]);
+    cps[..count].to_vec()
 }
 
 
 #[derive(Clone)]
 pub struct CollisionData {
-    pub sep_or_t: f64,
-    pub normal: Vector2f<f64>,
-    pub contacts: Vec<Vector2f<f64>>,
+    pub sep_or_t: Real,
+    pub normal: Vector2f<Real>,
+    pub contacts: Vec<Vector2f<Real>>,
+    // The child shapes that were hit on compound bodies, always 0 for simple shapes
+    pub child_a: usize,
+    pub child_b: usize,
 }
 
 
@@ -117,14 +241,14 @@ pub fn circle_vs_circle(a: &Circle, b: &Circle) -> Option<CollisionData> {
     let seperation = delta_dist.len() - sum_radius;
     if seperation < 0.0 {
         let normal = delta_dist.normalize();
-        return Some(CollisionData { sep_or_t: seperation, normal, contacts: vec![] });
+        return Some(CollisionData { sep_or_t: seperation, normal, contacts: vec![], child_a: 0, child_b: 0 });
     }
 
     None
 }
 
 // With normal pointing towards segment
-pub fn circle_vs_segment(c: &Circle, a: Vector2f<f64>, b: Vector2f<f64>) -> Option<CollisionData> {
+pub fn circle_vs_segment(c: &Circle, a: Vector2f<Real>, b: Vector2f<Real>) -> Option<CollisionData> {
     let ab = b - a;
     let ac = c.center - a;
 
@@ -136,20 +260,23 @@ pub fn circle_vs_segment(c: &Circle, a: Vector2f<f64>, b: Vector2f<f64>) -> Opti
     let dist_sq = cd.len_squared();
 
     if dist_sq <= c.radius * c.radius { 
-        let seperation = f64::sqrt(dist_sq) - c.radius; 
+        let seperation = Real::sqrt(dist_sq) - c.radius; 
         let normal = cd.normalize();
-        Some(CollisionData { sep_or_t: seperation, normal, contacts: vec![c.center + normal * c.radius] }) 
+        Some(CollisionData { sep_or_t: seperation, normal, contacts: vec![c.center + normal * c.radius], child_a: 0, child_b: 0 }) 
     } else {
         None
     }
 }
 
-fn find_min_seperation(a_verts: &Vec<Vector2f<f64>>, b_verts: &Vec<Vector2f<f64>>) -> Option<CollisionData> {
-    let mut result = CollisionData { sep_or_t: f64::NEG_INFINITY, normal: Vector2f::zero(), contacts: vec![] };
-    for i in 0..a_verts.len() {
-        let edge = a_verts[i] - a_verts[(i + 1) % a_verts.len()];
-        let normal = edge.perpendicular().normalize();
-        let mut min_sep = f64::INFINITY;
+// The normals of both sides of a segment, as if it was a polygon with two vertices
+fn segment_normals(a: Vector2f<Real>, b: Vector2f<Real>) -> [Vector2f<Real>; 2] {
+    [(a - b).perpendicular().normalize(), (b - a).perpendicular().normalize()]
+}
+
+fn find_min_seperation(a_verts: &[Vector2f<Real>], a_normals: &[Vector2f<Real>], b_verts: &[Vector2f<Real>]) -> Option<CollisionData> {
+    let mut result = CollisionData { sep_or_t: Real::NEG_INFINITY, normal: Vector2f::zero(), contacts: vec![], child_a: 0, child_b: 0 };
+    for (i, &normal) in a_normals.iter().enumerate() {
+        let mut min_sep = Real::INFINITY;
 
         for vb in b_verts {
             let sep = (*vb - a_verts[i]).dot(normal);
@@ -169,13 +296,25 @@ fn find_min_seperation(a_verts: &Vec<Vector2f<f64>>, b_verts: &Vec<Vector2f<f64>
     Some(result)
 }
 
+// Returns the collision with the smallest sep_or_t, which is the overlap that needs the largest
+// push to be resolved, or the hit with the earliest time of impact for swept tests
+fn min_sep_or_t(collisions: impl Iterator<Item = CollisionData>) -> Option<CollisionData> {
+    collisions.min_by(|a, b| a.sep_or_t.total_cmp(&b.sep_or_t))
+}
+
 // normal always points towards b
 pub fn polygon_vs_polygon(a: &Polygon, b: &Polygon) -> Option<CollisionData> {
-    let a_verts = a.get_transformed_vertices();
-    let b_verts = b.get_transformed_vertices();
-    
-    if let Some(a_res) = find_min_seperation(&a_verts, &b_verts) {
-        if let Some(mut b_res) = find_min_seperation(&b_verts, &a_verts) {
+    let a_parts = a.get_transformed_parts().iter().zip(a.get_part_normals());
+    let b_parts = b.get_transformed_parts().iter().zip(b.get_part_normals());
+
+    min_sep_or_t(a_parts.flat_map(|(a_verts, a_normals)| {
+        b_parts.clone().filter_map(move |(b_verts, b_normals)| convex_vs_convex(a_verts, a_normals, b_verts, b_normals))
+    }))
+}
+
+fn convex_vs_convex(a_verts: &[Vector2f<Real>], a_normals: &[Vector2f<Real>], b_verts: &[Vector2f<Real>], b_normals: &[Vector2f<Real>]) -> Option<CollisionData> {
+    if let Some(a_res) = find_min_seperation(a_verts, a_normals, b_verts) {
+        if let Some(mut b_res) = find_min_seperation(b_verts, b_normals, a_verts) {
             b_res.normal = -b_res.normal; 
             return if a_res.sep_or_t > b_res.sep_or_t { Some(a_res) } else { Some(b_res) };
         }
@@ -185,31 +324,32 @@ pub fn polygon_vs_polygon(a: &Polygon, b: &Polygon) -> Option<CollisionData> {
 }
 
 // normal always points towards the segment
-pub fn polygon_vs_segment(p: &Polygon, a: Vector2f<f64>, b: Vector2f<f64>) -> Option<CollisionData> {
-    let poly_verts = p.get_transformed_vertices();
-    let segment = vec![a, b];
-
-    if let Some(poly_res) = find_min_seperation(&poly_verts, &segment) {
-        if let Some(mut seg_res) = find_min_seperation(&segment, &poly_verts) {
-            seg_res.normal = -seg_res.normal; 
-            let res = if poly_res.sep_or_t > seg_res.sep_or_t { poly_res } else { seg_res };
-            let contacts = contact_poly_segment(p, a, b);
-            return Some(CollisionData { contacts, ..res });
-        }
-    }
-
-    None
+pub fn polygon_vs_segment(p: &Polygon, a: Vector2f<Real>, b: Vector2f<Real>) -> Option<CollisionData> {
+    let segment = [a, b];
+    let normals = segment_normals(a, b);
+
+    min_sep_or_t(p.get_transformed_parts().iter().zip(p.get_part_normals()).filter_map(|(poly_verts, poly_normals)| {
+        convex_vs_convex(poly_verts, poly_normals, &segment, &normals)
+    })).map(|res| {
+        let contacts = contact_poly_segment(p, a, b);
+        CollisionData { contacts, ..res }
+    })
 }
 
 pub fn polygon_vs_circle(p: &Polygon, c: &Circle) -> Option<CollisionData> {
-    let mut result = CollisionData { sep_or_t: f64::NEG_INFINITY, normal: Vector2f::zero(), contacts: vec![] };
-    let poly_verts = p.get_transformed_vertices();
+    min_sep_or_t(p.get_transformed_parts().iter().zip(p.get_part_normals()).filter_map(|(poly_verts, poly_normals)| {
+        convex_vs_circle(poly_verts, poly_normals, c)
+    }))
+}
+
+fn convex_vs_circle(poly_verts: &[Vector2f<Real>], poly_normals: &[Vector2f<Real>], c: &Circle) -> Option<CollisionData> {
+    let mut result = CollisionData { sep_or_t: Real::NEG_INFINITY, normal: Vector2f::zero(), contacts: vec![], child_a: 0, child_b: 0 };
     let mut closest_point = Vector2f::zero();
-    let mut distance = f64::INFINITY;
+    let mut distance = Real::INFINITY;
     for i in 0..poly_verts.len() {
         let a = poly_verts[i];
         let b = poly_verts[(i + 1) % poly_verts.len()];
-        let normal = (a - b).perpendicular().normalize();
+        let normal = poly_normals[i];
         let sep = (c.center - a).dot(normal) - c.radius;
 
         if sep > 0.0 {
@@ -242,11 +382,306 @@ pub fn polygon_vs_circle(p: &Polygon, c: &Circle) -> Option<CollisionData> {
     Some(result)
 }
 
+// normal always points towards the circle
+pub fn capsule_vs_circle(cap: &Capsule, c: &Circle) -> Option<CollisionData> {
+    let (a, b) = cap.get_segment();
+    let (dist_sq, cp) = point_segment_distance(c.center, a, b);
+    let sum_radius = cap.radius + c.radius;
+    if dist_sq >= sum_radius * sum_radius {
+        return None;
+    }
+
+    let normal = direction_or(cp, c.center, (b - a).perpendicular().normalize());
+    Some(CollisionData { sep_or_t: dist_sq.sqrt() - sum_radius, normal, contacts: vec![], child_a: 0, child_b: 0 })
+}
+
+// normal always points towards b
+pub fn capsule_vs_capsule(a: &Capsule, b: &Capsule) -> Option<CollisionData> {
+    let (a1, a2) = a.get_segment();
+    let (b1, b2) = b.get_segment();
+    let (dist_sq, pa, pb) = segment_segment_distance(a1, a2, b1, b2);
+    let sum_radius = a.radius + b.radius;
+    if dist_sq >= sum_radius * sum_radius {
+        return None;
+    }
+
+    // Crossing segments have no closest direction, so push out across the segment of a
+    let mut fallback = (a2 - a1).perpendicular().normalize();
+    if fallback.dot(b.center - a.center) < 0.0 {
+        fallback = -fallback;
+    }
+
+    let normal = direction_or(pa, pb, fallback);
+    Some(CollisionData { sep_or_t: dist_sq.sqrt() - sum_radius, normal, contacts: vec![], child_a: 0, child_b: 0 })
+}
+
+// normal always points towards the capsule
+pub fn polygon_vs_capsule(p: &Polygon, cap: &Capsule) -> Option<CollisionData> {
+    min_sep_or_t(p.get_transformed_parts().iter().zip(p.get_part_normals()).filter_map(|(poly_verts, poly_normals)| {
+        convex_vs_capsule(poly_verts, poly_normals, cap)
+    }))
+}
+
+fn convex_vs_capsule(poly_verts: &[Vector2f<Real>], poly_normals: &[Vector2f<Real>], cap: &Capsule) -> Option<CollisionData> {
+    let (a, b) = cap.get_segment();
+
+    // When the segment itself overlaps the polygon, the overlap is found like for any two polygons
+    if let Some(collision) = convex_vs_convex(poly_verts, poly_normals, &[a, b], &segment_normals(a, b)) {
+        return Some(CollisionData { sep_or_t: collision.sep_or_t - cap.radius, ..collision });
+    }
+
+    let mut min_dist_sq = Real::INFINITY;
+    let (mut poly_point, mut seg_point) = (Vector2f::zero(), Vector2f::zero());
+    for i in 0..poly_verts.len() {
+        let va = poly_verts[i];
+        let vb = poly_verts[(i + 1) % poly_verts.len()];
+        let (dist_sq, pp, sp) = segment_segment_distance(va, vb, a, b);
+        if dist_sq < min_dist_sq {
+            min_dist_sq = dist_sq;
+            poly_point = pp;
+            seg_point = sp;
+        }
+    }
+
+    if min_dist_sq >= cap.radius * cap.radius {
+        return None;
+    }
+
+    let normal = direction_or(poly_point, seg_point, Vector2f::zero());
+    Some(CollisionData { sep_or_t: min_dist_sq.sqrt() - cap.radius, normal, contacts: vec![], child_a: 0, child_b: 0 })
+}
+
+// normal always points towards the segment
+pub fn capsule_vs_segment(cap: &Capsule, a: Vector2f<Real>, b: Vector2f<Real>) -> Option<CollisionData> {
+    let (c1, c2) = cap.get_segment();
+    let (dist_sq, pc, ps) = segment_segment_distance(c1, c2, a, b);
+    if dist_sq > cap.radius * cap.radius {
+        return None;
+    }
+
+    let normal = direction_or(pc, ps, (b - a).perpendicular().normalize());
+    Some(CollisionData { sep_or_t: dist_sq.sqrt() - cap.radius, normal, contacts: vec![pc + normal * cap.radius], child_a: 0, child_b: 0 })
+}
+
+// normal always points towards b
+pub fn shape_vs_shape(a: &ShapeType, b: &ShapeType) -> Option<CollisionData> {
+    match (a, b) {
+        (ShapeType::Compound(compound), _) => {
+            let b_aabb = b.get_aabb();
+            min_sep_or_t(compound.get_transformed_children().iter().enumerate()
+                .filter(|(_, child)| child.get_aabb().overlap(&b_aabb))
+                .filter_map(|(i, child)| shape_vs_shape(child, b).map(|collision| CollisionData { child_a: i, ..collision })))
+        }
+        (_, ShapeType::Compound(compound)) => {
+            let a_aabb = a.get_aabb();
+            min_sep_or_t(compound.get_transformed_children().iter().enumerate()
+                .filter(|(_, child)| child.get_aabb().overlap(&a_aabb))
+                .filter_map(|(i, child)| shape_vs_shape(a, child).map(|collision| CollisionData { child_b: i, ..collision })))
+        }
+        (ShapeType::Chain(_) | ShapeType::Heightfield(_), ShapeType::Chain(_) | ShapeType::Heightfield(_)) => None,
+        (_, ShapeType::Chain(chain)) => shape_vs_chain(a, chain),
+        (ShapeType::Chain(chain), _) => {
+            shape_vs_chain(b, chain).map(|collision| CollisionData { normal: -collision.normal, child_a: collision.child_b, child_b: 0, ..collision })
+        }
+        (_, ShapeType::Heightfield(heightfield)) => shape_vs_heightfield(a, heightfield),
+        (ShapeType::Heightfield(heightfield), _) => {
+            shape_vs_heightfield(b, heightfield).map(|collision| CollisionData { normal: -collision.normal, child_a: collision.child_b, child_b: 0, ..collision })
+        }
+        (ShapeType::Circle(a), ShapeType::Circle(b)) => circle_vs_circle(a, b),
+        (ShapeType::Circle(c), ShapeType::Polygon(p)) => {
+            polygon_vs_circle(p, c).map(|collision| CollisionData { normal: -collision.normal, ..collision })
+        }
+        (ShapeType::Polygon(p), ShapeType::Circle(c)) => polygon_vs_circle(p, c),
+        (ShapeType::Polygon(a), ShapeType::Polygon(b)) => polygon_vs_polygon(a, b),
+        (ShapeType::Capsule(a), ShapeType::Capsule(b)) => capsule_vs_capsule(a, b),
+        (ShapeType::Capsule(cap), ShapeType::Circle(c)) => capsule_vs_circle(cap, c),
+        (ShapeType::Circle(c), ShapeType::Capsule(cap)) => {
+            capsule_vs_circle(cap, c).map(|collision| CollisionData { normal: -collision.normal, ..collision })
+        }
+        (ShapeType::Polygon(p), ShapeType::Capsule(cap)) => polygon_vs_capsule(p, cap),
+        (ShapeType::Capsule(cap), ShapeType::Polygon(p)) => {
+            polygon_vs_capsule(p, cap).map(|collision| CollisionData { normal: -collision.normal, ..collision })
+        }
+        // The routines above are fast paths, any convex pair without one falls back to GJK and EPA.
+        // Every current pair has a routine, so this only catches shapes added later.
+        #[allow(unreachable_patterns)]
+        (a, b) => gjk_epa(a, b),
+    }
+}
+
+// Returns the contact points of two shapes that have been pushed apart
+pub fn contact_shape_shape(a: &ShapeType, b: &ShapeType, collision: &CollisionData) -> Vec<Vector2f<Real>> {
+    match (a, b) {
+        (ShapeType::Compound(compound), _) => contact_shape_shape(compound.get_transformed_child(collision.child_a), b, collision),
+        (_, ShapeType::Compound(compound)) => contact_shape_shape(a, compound.get_transformed_child(collision.child_b), collision),
+        (ShapeType::Chain(_) | ShapeType::Heightfield(_), ShapeType::Chain(_) | ShapeType::Heightfield(_)) => vec![],
+        (_, ShapeType::Chain(chain)) => contact_shape_chain_segment(a, &chain.get_segments()[collision.child_b]),
+        (ShapeType::Chain(chain), _) => contact_shape_chain_segment(b, &chain.get_segments()[collision.child_a]),
+        (_, ShapeType::Heightfield(heightfield)) => contact_shape_chain_segment(a, &heightfield.get_segment(collision.child_b)),
+        (ShapeType::Heightfield(heightfield), _) => contact_shape_chain_segment(b, &heightfield.get_segment(collision.child_a)),
+        (ShapeType::Circle(a), ShapeType::Circle(_)) => vec![a.center + collision.normal * a.radius],
+        (ShapeType::Circle(a), ShapeType::Polygon(b)) => contact_poly_circle(b, a),
+        (ShapeType::Polygon(a), ShapeType::Circle(b)) => contact_poly_circle(a, b),
+        (ShapeType::Polygon(a), ShapeType::Polygon(b)) => contact_poly_poly(a, b),
+        (ShapeType::Capsule(a), ShapeType::Capsule(b)) => contact_capsule_capsule(a, b),
+        (ShapeType::Capsule(cap), ShapeType::Circle(c)) | (ShapeType::Circle(c), ShapeType::Capsule(cap)) => contact_capsule_circle(cap, c),
+        (ShapeType::Polygon(p), ShapeType::Capsule(cap)) | (ShapeType::Capsule(cap), ShapeType::Polygon(p)) => contact_poly_capsule(p, cap),
+        // Pairs that collided through GJK and EPA
+        #[allow(unreachable_patterns)]
+        (a, b) => support_contact(a, b, collision.normal),
+    }
+}
+
+// normal always points towards the segment
+pub fn shape_vs_segment(shape: &ShapeType, a: Vector2f<Real>, b: Vector2f<Real>) -> Option<CollisionData> {
+    match shape {
+        ShapeType::Circle(c) => circle_vs_segment(c, a, b),
+        ShapeType::Polygon(p) => polygon_vs_segment(p, a, b),
+        ShapeType::Capsule(cap) => capsule_vs_segment(cap, a, b),
+        ShapeType::Compound(compound) => min_sep_or_t(compound.get_transformed_children().iter().enumerate()
+            .filter_map(|(i, child)| shape_vs_segment(child, a, b).map(|collision| CollisionData { child_a: i, ..collision }))),
+        ShapeType::Chain(chain) => chain_vs_segment(chain, a, b),
+        ShapeType::Heightfield(heightfield) => heightfield_vs_segment(heightfield, a, b),
+    }
+}
+
+// The segment has no thickness, so it only touches the chain where it crosses one of the chain 
+// segments. The normal is the face of that segment on the side of the middle of the segment, and 
+// child_b holds its index.
+pub fn chain_vs_segment(chain: &Chain, a: Vector2f<Real>, b: Vector2f<Real>) -> Option<CollisionData> {
+    let aabb = AABB::from_segment(a, b);
+    let segments = chain.get_segments().into_iter().enumerate().filter(|(_, seg)| {
+        AABB::from_segment(seg.a, seg.b).expand_by(Vector2f::new(1.0, 1.0)).overlap(&aabb)
+    });
+
+    segments_vs_segment(segments, a, b, false)
+}
+
+// Same as the chain, but the ground below the surface is solid, so a segment that sank below it 
+// is pushed back up
+pub fn heightfield_vs_segment(heightfield: &Heightfield, a: Vector2f<Real>, b: Vector2f<Real>) -> Option<CollisionData> {
+    let segments = heightfield.get_segments_in_range(a.x.min(b.x), a.x.max(b.x));
+
+    segments_vs_segment(segments.into_iter(), a, b, true)
+}
+
+fn segments_vs_segment(segments: impl Iterator<Item = (usize, ChainSegment)>, a: Vector2f<Real>, b: Vector2f<Real>, solid_below: bool) -> Option<CollisionData> {
+    let middle = (a + b) / 2.0;
+
+    min_sep_or_t(segments.filter_map(|(i, seg)| {
+        let mut face = (seg.b - seg.a).perpendicular().normalize();
+        if solid_below {
+            face = Heightfield::surface_normal(&seg);
+        } else if face.dot(middle - seg.a) < 0.0 {
+            face = -face;
+        }
+
+        let (dist_sq, contact, _) = segment_segment_distance(seg.a, seg.b, a, b);
+        let below = (middle - seg.a).dot(face) < 0.0 && middle.x >= seg.a.x && middle.x <= seg.b.x;
+        if dist_sq > Real::EPSILON && !(solid_below && below) {
+            return None;
+        }
+
+        let seperation = (a - seg.a).dot(face).min((b - seg.a).dot(face)).min(0.0);
+        Some(CollisionData { sep_or_t: seperation, normal: face, contacts: vec![contact], child_a: 0, child_b: i })
+    }))
+}
+
+// Returns how far the shape is from the line through the point along the normal, negative when
+// the shape reaches past the line
+fn seperation_along(shape: &ShapeType, point: Vector2f<Real>, normal: Vector2f<Real>) -> Real {
+    match shape {
+        ShapeType::Circle(c) => (c.center - point).dot(normal) - c.radius,
+        ShapeType::Capsule(cap) => {
+            let (a, b) = cap.get_segment();
+            (a - point).dot(normal).min((b - point).dot(normal)) - cap.radius
+        }
+        _ => shape.get_convex_outlines().iter().flatten()
+            .map(|&v| (v - point).dot(normal))
+            .fold(Real::INFINITY, Real::min),
+    }
+}
+
+// The shape is tested against every segment of the chain. A shape sliding across the joint between
+// two segments could catch on the end of one of them, so when the neighbouring (ghost) vertex shows
+// that the joint is flat or a valley, the collision is forced onto the face normal of the segment.
+// The normal points towards the chain, and child_b holds the index of the segment that was hit.
+pub fn shape_vs_chain(shape: &ShapeType, chain: &Chain) -> Option<CollisionData> {
+    let aabb = shape.get_aabb();
+    let segments = chain.get_segments().into_iter().enumerate().filter(|(_, seg)| {
+        AABB::from_segment(seg.a, seg.b).expand_by(Vector2f::new(1.0, 1.0)).overlap(&aabb)
+    });
+
+    shape_vs_segments(shape, segments, false)
+}
+
+// Same as the chain, but only the columns under the shape are tested, and the ground below the 
+// surface is solid, so shapes that sank below it are pushed back up
+pub fn shape_vs_heightfield(shape: &ShapeType, heightfield: &Heightfield) -> Option<CollisionData> {
+    let aabb = shape.get_aabb();
+    let segments = heightfield.get_segments_in_range(aabb.top_left.x, aabb.bottom_right.x);
+
+    shape_vs_segments(shape, segments.into_iter(), true)
+}
+
+fn shape_vs_segments(shape: &ShapeType, segments: impl Iterator<Item = (usize, ChainSegment)>, solid_below: bool) -> Option<CollisionData> {
+    let center = shape.get_center();
+
+    min_sep_or_t(segments.filter_map(|(i, seg)| {
+        // The face normal on the outer side
+        let mut face = (seg.b - seg.a).perpendicular().normalize();
+        if solid_below {
+            face = Heightfield::surface_normal(&seg);
+        } else if face.dot(center - seg.a) < 0.0 {
+            face = -face;
+        }
+
+        let below = (center - seg.a).dot(face) < 0.0 && center.x >= seg.a.x && center.x <= seg.b.x;
+        let collision = match shape_vs_segment(shape, seg.a, seg.b) {
+            Some(collision) => collision,
+            None if solid_below && below => CollisionData { sep_or_t: 0.0, normal: face, contacts: vec![], child_a: 0, child_b: 0 },
+            None => return None,
+        };
+
+        if -collision.normal.dot(face) > 1.0 - 1e-6 {
+            return Some(CollisionData { child_b: i, ..collision });
+        }
+
+        let (end, ghost) = if (center - seg.a).len_squared() < (center - seg.b).len_squared() {
+            (seg.a, seg.prev)
+        } else {
+            (seg.b, seg.next)
+        };
+
+        let flat_or_valley = ghost.is_some_and(|ghost| (ghost - end).dot(face) >= -1e-6);
+        if flat_or_valley || (solid_below && collision.normal.dot(face) > 0.0) {
+            let seperation = seperation_along(shape, seg.a, face);
+            if seperation >= 0.0 {
+                return None;
+            }
+            return Some(CollisionData { sep_or_t: seperation, normal: -face, child_b: i, ..collision });
+        }
+
+        // At a convex corner the round normal of the corner is kept
+        Some(CollisionData { child_b: i, ..collision })
+    }))
+}
+
+// Returns the contact points of a shape resting against a segment of a chain
+fn contact_shape_chain_segment(shape: &ShapeType, seg: &ChainSegment) -> Vec<Vector2f<Real>> {
+    match shape {
+        ShapeType::Circle(c) => vec![point_segment_distance(c.center, seg.a, seg.b).1],
+        ShapeType::Polygon(p) => contact_poly_segment(p, seg.a, seg.b),
+        ShapeType::Capsule(cap) => contact_capsule_capsule(cap, &Capsule::from_segment(seg.a, seg.b, 0.0)),
+        _ => vec![],
+    }
+}
+
 // =======================
 // Ray collision detection
 // =======================
 
-fn ray_intersect_circle(ray_origin: Vector2f<f64>, ray_dir: Vector2f<f64>, center: Vector2f<f64>, radius: f64) -> Option<f64> {
+fn ray_intersect_circle(ray_origin: Vector2f<Real>, ray_dir: Vector2f<Real>, center: Vector2f<Real>, radius: Real) -> Option<Real> {
     let oc = ray_origin - center;
 
     let a = ray_dir.dot(ray_dir);
@@ -275,12 +710,12 @@ fn ray_intersect_circle(ray_origin: Vector2f<f64>, ray_dir: Vector2f<f64>, cente
     }
 }
 
-pub fn ray_vs_circle(ray_origin: Vector2f<f64>, ray_dir: Vector2f<f64>, c: &Circle) -> Option<CollisionData> {
+pub fn ray_vs_circle(ray_origin: Vector2f<Real>, ray_dir: Vector2f<Real>, c: &Circle) -> Option<CollisionData> {
     if let Some(t) = ray_intersect_circle(ray_origin, ray_dir, c.center, c.radius) {
         if t <= 1.0 {
             let cp = ray_origin + ray_dir * t;
             let normal = (cp - c.center).normalize();
-            Some(CollisionData { sep_or_t: t, normal, contacts: vec![cp] })
+            Some(CollisionData { sep_or_t: t, normal, contacts: vec![cp], child_a: 0, child_b: 0 })
         } else {
             None
         }
@@ -289,7 +724,7 @@ pub fn ray_vs_circle(ray_origin: Vector2f<f64>, ray_dir: Vector2f<f64>, c: &Circ
     }
 }
 
-fn ray_intersect_capsule(ray_origin: Vector2f<f64>, ray_dir: Vector2f<f64>, a: Vector2f<f64>, b: Vector2f<f64>, radius: f64) -> Option<f64> {
+fn ray_intersect_capsule(ray_origin: Vector2f<Real>, ray_dir: Vector2f<Real>, a: Vector2f<Real>, b: Vector2f<Real>, radius: Real) -> Option<Real> {
     let t1 = ray_intersect_circle(ray_origin, ray_dir, a, radius);
     let t2 = ray_intersect_circle(ray_origin, ray_dir, b, radius);
 
@@ -300,22 +735,24 @@ fn ray_intersect_capsule(ray_origin: Vector2f<f64>, ray_dir: Vector2f<f64>, a: V
         return t1.or(t2);
     }
 
+    // The closest wall is on the same side of the segment as the ray origin
     let dist = (a - ray_origin).dot(normal);
-    let t_wall = (dist - radius) / d_proj;
+    let offset = if dist > 0.0 { radius } else { -radius };
+    let t_wall = (dist - offset) / d_proj;
 
     if t_wall >= 0.0 {
         let hit_point = ray_origin + ray_dir * t_wall;
         let seg_len = seg.len();
         let seg_proj = (hit_point - a).dot(seg / seg_len);
         if seg_proj >= 0.0 && seg_proj <= seg_len {
-            return Some(t_wall.min(t1.unwrap_or(f64::INFINITY)).min(t2.unwrap_or(f64::INFINITY)));
+            return Some(t_wall.min(t1.unwrap_or(Real::INFINITY)).min(t2.unwrap_or(Real::INFINITY)));
         }
     }
 
     t1.or(t2)
 }
 
-pub fn swept_circle_vs_segment(c: &Circle, ray_dir: Vector2f<f64>, a: Vector2f<f64>, b: Vector2f<f64>) -> Option<CollisionData> {
+pub fn swept_circle_vs_segment(c: &Circle, ray_dir: Vector2f<Real>, a: Vector2f<Real>, b: Vector2f<Real>) -> Option<CollisionData> {
     if let Some(t) = ray_intersect_capsule(c.center, ray_dir, a, b, c.radius) {
         if t <= 1.0 {
             let mut normal = (a - b).perpendicular().normalize();
@@ -323,7 +760,7 @@ pub fn swept_circle_vs_segment(c: &Circle, ray_dir: Vector2f<f64>, a: Vector2f<f
                 normal = -normal;
             }
             let contact = c.center + ray_dir + normal * c.radius;
-            Some(CollisionData { sep_or_t: t, normal: normal, contacts: vec![contact] })
+            Some(CollisionData { sep_or_t: t, normal: normal, contacts: vec![contact], child_a: 0, child_b: 0 })
         } else {
             None
         }
@@ -332,11 +769,31 @@ pub fn swept_circle_vs_segment(c: &Circle, ray_dir: Vector2f<f64>, a: Vector2f<f
     }
 }
 
-pub fn swept_polygon_vs_segment(p: &Polygon, ray_dir: Vector2f<f64>, a: Vector2f<f64>, b: Vector2f<f64>) -> Option<CollisionData> {
-    let poly_verts = p.get_transformed_vertices();
-    let mut min_t = f64::INFINITY;
+pub fn ray_vs_capsule(ray_origin: Vector2f<Real>, ray_dir: Vector2f<Real>, cap: &Capsule) -> Option<CollisionData> {
+    let (a, b) = cap.get_segment();
+    if let Some(t) = ray_intersect_capsule(ray_origin, ray_dir, a, b, cap.radius) {
+        if t <= 1.0 {
+            let cp = ray_origin + ray_dir * t;
+            let (_, closest) = point_segment_distance(cp, a, b);
+            let normal = direction_or(closest, cp, -ray_dir.normalize());
+            Some(CollisionData { sep_or_t: t, normal, contacts: vec![cp], child_a: 0, child_b: 0 })
+        } else {
+            None
+        }
+    } else {
+        None
+    }
+}
+
+pub fn swept_polygon_vs_segment(p: &Polygon, ray_dir: Vector2f<Real>, a: Vector2f<Real>, b: Vector2f<Real>) -> Option<CollisionData> {
+    swept_vertices_vs_segment(p.get_transformed_vertices(), ray_dir, a, b)
+}
+
+// The vertices move along the ray, the first one to cross the segment is the hit
+fn swept_vertices_vs_segment(verts: &[Vector2f<Real>], ray_dir: Vector2f<Real>, a: Vector2f<Real>, b: Vector2f<Real>) -> Option<CollisionData> {
+    let mut min_t = Real::INFINITY;
     let mut corner = Vector2f::zero();
-    for vert in poly_verts {
+    for &vert in verts {
         if let Some(t) = ray_intersect_segment(vert, ray_dir, a, b) {
             if t < min_t {
                 min_t = t;
@@ -351,15 +808,15 @@ pub fn swept_polygon_vs_segment(p: &Polygon, ray_dir: Vector2f<f64>, a: Vector2f
             normal = -normal;
         }
 
-        return Some(CollisionData { sep_or_t: min_t, normal, contacts: vec![corner + ray_dir] });
+        return Some(CollisionData { sep_or_t: min_t, normal, contacts: vec![corner + ray_dir], child_a: 0, child_b: 0 });
     }
 
     None
 }
 
-pub fn ray_vs_polygon(ray_origin: Vector2f<f64>, ray_dir: Vector2f<f64>, p: &Polygon) -> Option<CollisionData> {
+pub fn ray_vs_polygon(ray_origin: Vector2f<Real>, ray_dir: Vector2f<Real>, p: &Polygon) -> Option<CollisionData> {
     let poly_verts = p.get_transformed_vertices();
-    let mut min_t = f64::INFINITY;
+    let mut min_t = Real::INFINITY;
     let n = poly_verts.len();
     let mut edge = Vector2f::zero();
 
@@ -377,13 +834,81 @@ pub fn ray_vs_polygon(ray_origin: Vector2f<f64>, ray_dir: Vector2f<f64>, p: &Pol
 
     if min_t <= 1.0 {
         let normal = edge.perpendicular().normalize();
-        Some(CollisionData { sep_or_t: min_t, normal, contacts: vec![ray_origin + ray_dir * min_t] })
+        Some(CollisionData { sep_or_t: min_t, normal, contacts: vec![ray_origin + ray_dir * min_t], child_a: 0, child_b: 0 })
     } else {
         None
     }
 }
 
-fn ray_intersect_segment(ray_origin: Vector2f<f64>, ray_dir: Vector2f<f64>, p1: Vector2f<f64>, p2: Vector2f<f64>) -> Option<f64> {
+// The chain is two sided, so the normal faces the ray origin
+pub fn ray_vs_chain(ray_origin: Vector2f<Real>, ray_dir: Vector2f<Real>, chain: &Chain) -> Option<CollisionData> {
+    min_sep_or_t(chain.get_segments().iter().enumerate().filter_map(|(i, seg)| {
+        let t = ray_intersect_segment(ray_origin, ray_dir, seg.a, seg.b)?;
+        if t > 1.0 {
+            return None;
+        }
+
+        let mut normal = (seg.b - seg.a).perpendicular().normalize();
+        if normal.dot(ray_dir) > 0.0 {
+            normal = -normal;
+        }
+        Some(CollisionData { sep_or_t: t, normal, contacts: vec![ray_origin + ray_dir * t], child_a: i, child_b: 0 })
+    }))
+}
+
+// Only the columns the ray passes over are tested, and the surface always faces up
+pub fn ray_vs_heightfield(ray_origin: Vector2f<Real>, ray_dir: Vector2f<Real>, heightfield: &Heightfield) -> Option<CollisionData> {
+    let end = ray_origin + ray_dir;
+    let segments = heightfield.get_segments_in_range(ray_origin.x.min(end.x), ray_origin.x.max(end.x));
+
+    min_sep_or_t(segments.into_iter().filter_map(|(i, seg)| {
+        let t = ray_intersect_segment(ray_origin, ray_dir, seg.a, seg.b)?;
+        if t > 1.0 {
+            return None;
+        }
+
+        let normal = Heightfield::surface_normal(&seg);
+        Some(CollisionData { sep_or_t: t, normal, contacts: vec![ray_origin + ray_dir * t], child_a: i, child_b: 0 })
+    }))
+}
+
+pub fn ray_vs_shape(ray_origin: Vector2f<Real>, ray_dir: Vector2f<Real>, shape: &ShapeType) -> Option<CollisionData> {
+    match shape {
+        ShapeType::Circle(c) => ray_vs_circle(ray_origin, ray_dir, c),
+        ShapeType::Polygon(p) => ray_vs_polygon(ray_origin, ray_dir, p),
+        ShapeType::Capsule(cap) => ray_vs_capsule(ray_origin, ray_dir, cap),
+        ShapeType::Compound(compound) => min_sep_or_t(compound.get_transformed_children().iter().enumerate()
+            .filter_map(|(i, child)| ray_vs_shape(ray_origin, ray_dir, child).map(|collision| CollisionData { child_a: i, ..collision }))),
+        ShapeType::Chain(chain) => ray_vs_chain(ray_origin, ray_dir, chain),
+        ShapeType::Heightfield(heightfield) => ray_vs_heightfield(ray_origin, ray_dir, heightfield),
+    }
+}
+
+pub fn swept_shape_vs_segment(shape: &ShapeType, ray_dir: Vector2f<Real>, a: Vector2f<Real>, b: Vector2f<Real>) -> Option<CollisionData> {
+    match shape {
+        ShapeType::Circle(c) => swept_circle_vs_segment(c, ray_dir, a, b),
+        ShapeType::Polygon(p) => swept_polygon_vs_segment(p, ray_dir, a, b),
+        ShapeType::Capsule(cap) => {
+            // Only the round ends can hit the segment first
+            let (c1, c2) = cap.get_segment();
+            min_sep_or_t([c1, c2].into_iter().filter_map(|end| {
+                swept_circle_vs_segment(&Circle::new(end, cap.radius, 0.0), ray_dir, a, b)
+            }))
+        }
+        ShapeType::Compound(compound) => min_sep_or_t(compound.get_transformed_children().iter().enumerate()
+            .filter_map(|(i, child)| swept_shape_vs_segment(child, ray_dir, a, b).map(|collision| CollisionData { child_a: i, ..collision }))),
+        ShapeType::Chain(chain) => swept_vertices_vs_segment(&chain.get_transformed_vertices(), ray_dir, a, b),
+        ShapeType::Heightfield(heightfield) => {
+            // Only the columns the segment is in during the sweep can reach it
+            let (min_x, max_x) = (a.x.min(b.x), a.x.max(b.x));
+            let segments = heightfield.get_segments_in_range(min_x - ray_dir.x.max(0.0), max_x - ray_dir.x.min(0.0));
+            let verts: Vec<Vector2f<Real>> = segments.iter().flat_map(|(_, seg)| [seg.a, seg.b]).collect();
+            swept_vertices_vs_segment(&verts, ray_dir, a, b)
+        }
+    }
+}
+
+fn ray_intersect_segment(ray_origin: Vector2f<Real>, ray_dir: Vector2f<Real>, p1: Vector2f<Real>, p2: Vector2f<Real>) -> Option<Real> {
     let v1 = ray_origin - p1;
     let v2 = p2 - p1;
     let v3 = ray_dir.perpendicular();
@@ -405,11 +930,18 @@ fn ray_intersect_segment(ray_origin: Vector2f<f64>, ray_dir: Vector2f<f64>, p1:
 
 #[derive(Clone, Copy, Debug)]
 pub struct AABB {
-    pub top_left: Vector2f<f64>,
-    pub bottom_right: Vector2f<f64>
+    pub top_left: Vector2f<Real>,
+    pub bottom_right: Vector2f<Real>
 }
 
 impl AABB {
+    pub fn from_segment(a: Vector2f<Real>, b: Vector2f<Real>) -> AABB {
+        AABB {
+            top_left: Vector2f::new(a.x.min(b.x), a.y.min(b.y)),
+            bottom_right: Vector2f::new(a.x.max(b.x), a.y.max(b.y)),
+        }
+    }
+
     pub fn overlap(&self, other: &AABB) -> bool {
         self.top_left.x < other.bottom_right.x &&
         self.bottom_right.x > other.top_left.x &&
@@ -417,15 +949,15 @@ impl AABB {
         self.bottom_right.y > other.top_left.y
     }
 
-    pub fn width(&self) -> f64 {
+    pub fn width(&self) -> Real {
         self.bottom_right.x - self.top_left.x
     }
 
-    pub fn height(&self) -> f64 {
+    pub fn height(&self) -> Real {
         self.bottom_right.y - self.top_left.y
     }
 
-    pub fn expand_by(&self, v: Vector2f<f64>) -> AABB {
+    pub fn expand_by(&self, v: Vector2f<Real>) -> AABB {
         let mut aabb = self.clone();
         if v.x > 0.0 {
             aabb.bottom_right.x += v.x;
@@ -441,15 +973,20 @@ impl AABB {
         aabb
     }
 
+    pub fn grow(&self, margin: Real) -> AABB {
+        let margin = Vector2f::new(margin, margin);
+        AABB { top_left: self.top_left - margin, bottom_right: self.bottom_right + margin }
+    }
+
     #[allow(dead_code)]
-    pub fn contains_point(&self, p: Vector2f<f64>) -> bool {
+    pub fn contains_point(&self, p: Vector2f<Real>) -> bool {
         p.x >= self.top_left.x && p.x <= self.bottom_right.x &&
         p.y >= self.top_left.y && p.y <= self.bottom_right.y
     }
 }
 
 #[allow(dead_code)]
-pub fn ray_intersects_aabb(ray_origin: Vector2f<f64>, ray_dir: Vector2f<f64>, aabb: &AABB) -> Option<f64> {
+pub fn ray_intersects_aabb(ray_origin: Vector2f<Real>, ray_dir: Vector2f<Real>, aabb: &AABB) -> Option<Real> {
     let inv_dir = Vector2f::new(1.0 / ray_dir.x, 1.0 / ray_dir.y);
 
     let mut txmin = (aabb.top_left.x - ray_origin.x) * inv_dir.x;
@@ -478,4 +1015,63 @@ pub fn ray_intersects_aabb(ray_origin: Vector2f<f64>, ray_dir: Vector2f<f64>, aa
     }
 
     Some(txmin.max(0.0)) // Return distance to intersection
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn flat_chain() -> ShapeType {
+        ShapeType::Chain(Chain::new(vec![Vector2f::new(0.0, 0.0), Vector2f::new(50.0, 0.0), Vector2f::new(100.0, 0.0)], false))
+    }
+
+    // Level ground at y = 50 from x = 0 to 100
+    fn flat_heightfield() -> ShapeType {
+        ShapeType::Heightfield(Heightfield { heights: vec![50.0; 6], spacing: 20.0, origin: Vector2f::new(0.0, 100.0) })
+    }
+
+    #[test]
+    fn segment_crossing_a_chain_is_pushed_to_its_middle_side() {
+        let collision = shape_vs_segment(&flat_chain(), Vector2f::new(50.0, -10.0), Vector2f::new(60.0, 5.0)).unwrap();
+        assert!(collision.normal.nearly_equal(Vector2f::new(0.0, -1.0), 1e-6));
+        assert!((collision.sep_or_t + 5.0).abs() < 1e-4);
+        assert!(collision.contacts[0].nearly_equal(Vector2f::new(50.0 + 10.0 * 10.0 / 15.0, 0.0), 1e-3));
+
+        // The other way around it is pushed down
+        let collision = shape_vs_segment(&flat_chain(), Vector2f::new(50.0, 10.0), Vector2f::new(60.0, -5.0)).unwrap();
+        assert!(collision.normal.nearly_equal(Vector2f::new(0.0, 1.0), 1e-6));
+    }
+
+    #[test]
+    fn segment_next_to_a_chain_does_not_collide() {
+        assert!(shape_vs_segment(&flat_chain(), Vector2f::new(50.0, -10.0), Vector2f::new(60.0, -2.0)).is_none());
+        assert!(shape_vs_segment(&flat_chain(), Vector2f::new(110.0, -10.0), Vector2f::new(120.0, 10.0)).is_none());
+    }
+
+    #[test]
+    fn segment_in_a_heightfield_is_pushed_up() {
+        let heightfield = flat_heightfield();
+        let crossing = shape_vs_segment(&heightfield, Vector2f::new(30.0, 40.0), Vector2f::new(35.0, 60.0)).unwrap();
+        assert!(crossing.normal.nearly_equal(Vector2f::new(0.0, -1.0), 1e-6));
+        assert!((crossing.sep_or_t + 10.0).abs() < 1e-4);
+
+        // Sunk below the surface without touching it
+        let below = shape_vs_segment(&heightfield, Vector2f::new(30.0, 70.0), Vector2f::new(50.0, 75.0)).unwrap();
+        assert!(below.normal.nearly_equal(Vector2f::new(0.0, -1.0), 1e-6));
+        assert!((below.sep_or_t + 25.0).abs() < 1e-4);
+
+        assert!(shape_vs_segment(&heightfield, Vector2f::new(30.0, 40.0), Vector2f::new(50.0, 45.0)).is_none());
+    }
+
+    #[test]
+    fn moving_chain_hits_segment() {
+        let (a, b) = (Vector2f::new(20.0, -10.0), Vector2f::new(80.0, -10.0));
+        let collision = swept_shape_vs_segment(&flat_chain(), Vector2f::new(0.0, -20.0), a, b).unwrap();
+        assert!((collision.sep_or_t - 0.5).abs() < 1e-6);
+        assert!(collision.normal.nearly_equal(Vector2f::new(0.0, -1.0), 1e-6));
+        assert!(swept_shape_vs_segment(&flat_chain(), Vector2f::new(0.0, -5.0), a, b).is_none());
+
+        // The heightfield columns are found over the whole sweep
+        let collision = swept_shape_vs_segment(&flat_heightfield(), Vector2f::new(-50.0, -20.0), Vector2f::new(0.0, 35.0), Vector2f::new(10.0, 35.0)).unwrap();
+        assert!((collision.sep_or_t - 0.75).abs() < 1e-6);
+    }
+}
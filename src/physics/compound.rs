@@ -0,0 +1,248 @@
+use graphics::math::Matrix2d;
+
+use crate::utils::real::Real;
+use crate::utils::transform::Transform2;
+use crate::Vector2f;
+use crate::physics::shape::Renderable;
+use crate::GlGraphics;
+use crate::physics::shape::Shape;
+
+use super::collision::AABB;
+use super::shape_type::ShapeType;
+
+// A shape made of several circles and polygons that move as one. The children are kept in the
+// local space of the compound, with the centroid of the compound at the origin.
+#[derive(Clone)]
+pub struct Compound {
+    children: Vec<ShapeType>,
+    // Only changed through the setters, which keep the world space children up to date
+    center: Vector2f<Real>,
+    rotation: Real,
+    area: Real,
+    intertia: Real,
+    // The children in world space. They are moved in place every time the compound moves, so the
+    // collision tests can borrow them without cloning anything.
+    world_children: Vec<ShapeType>,
+}
+
+impl Renderable for Compound {
+    fn draw(&self, transform: Matrix2d, gl: &mut GlGraphics, color: [f32; 4]) {
+        for child in self.get_transformed_children() {
+            child.draw(transform, gl, color);
+        }
+    }
+}
+
+impl Shape for Compound {
+    fn area(&self) -> Real {
+        self.area
+    }
+
+    fn momemnt_of_inertia(&self) -> Real {
+        self.intertia
+    }
+
+    fn get_aabb(&self) -> AABB {
+        let mut min = Vector2f::new(Real::INFINITY, Real::INFINITY);
+        let mut max = Vector2f::new(Real::NEG_INFINITY, Real::NEG_INFINITY);
+        for child in self.get_transformed_children() {
+            let aabb = child.get_aabb();
+            min = Vector2f::new(min.x.min(aabb.top_left.x), min.y.min(aabb.top_left.y));
+            max = Vector2f::new(max.x.max(aabb.bottom_right.x), max.y.max(aabb.bottom_right.y));
+        }
+
+        AABB { top_left: min, bottom_right: max }
+    }
+
+    fn contains_point(&self, point: Vector2f<Real>) -> bool {
+        self.get_transformed_children().iter().any(|child| child.contains_point(point))
+    }
+
+    fn find_closest_surface_point(&self, point: Vector2f<Real>) -> (Vector2f<Real>, Vector2f<Real>) {
+        let mut closest = (Vector2f::zero(), Vector2f::zero());
+        let mut distance = Real::INFINITY;
+        for child in self.get_transformed_children() {
+            let (cp, normal) = child.find_closest_surface_point(point);
+            let dist = (cp - point).len_squared();
+            if dist < distance {
+                closest = (cp, normal);
+                distance = dist;
+            }
+        }
+
+        closest
+    }
+
+    fn support(&self, direction: Vector2f<Real>) -> Vector2f<Real> {
+        self.get_transformed_children().iter()
+            .map(|child| child.support(direction))
+            .max_by(|a, b| a.dot(direction).total_cmp(&b.dot(direction)))
+            .unwrap_or(self.center)
+    }
+}
+
+impl Compound {
+    // The children are given relative to any origin, the compound is then placed with its
+    // centroid at the center
+    pub fn new(children: Vec<ShapeType>, center: Vector2f<Real>, rotation: Real) -> Self {
+        // Nested compounds are flattened into their children
+        let mut children: Vec<ShapeType> = children.into_iter().flat_map(|child| match child {
+            ShapeType::Compound(c) => c.world_children,
+            child => vec![child],
+        }).collect();
+
+        let area: Real = children.iter().map(|child| child.area()).sum();
+        let centroid = Self::compute_centroid(&children, area);
+
+        // Move the inertia of every child to the centroid with the parallel axis theorem
+        let mut intertia = 0.0;
+        for child in children.iter_mut() {
+            child.translate(-centroid);
+            intertia += child.momemnt_of_inertia() + child.area() * child.get_center().len_squared();
+        }
+
+        let mut compound = Self {
+            world_children: children.clone(),
+            children,
+            center,
+            rotation,
+            area,
+            intertia,
+        };
+        compound.update_world();
+        compound
+    }
+
+    // The area weighted centroid of the children, falling back to the average of their centers
+    // when they have no area, and to the origin when there are none
+    fn compute_centroid(children: &[ShapeType], area: Real) -> Vector2f<Real> {
+        if area > Real::EPSILON {
+            children.iter().fold(Vector2f::zero(), |sum, child| sum + child.get_center() * child.area()) / area
+        } else if !children.is_empty() {
+            children.iter().fold(Vector2f::zero(), |sum, child| sum + child.get_center()) / children.len() as Real
+        } else {
+            Vector2f::zero()
+        }
+    }
+
+    pub fn get_center(&self) -> Vector2f<Real> {
+        self.center
+    }
+
+    pub fn get_rotation(&self) -> Real {
+        self.rotation
+    }
+
+    // Near zero, since the children are moved onto their centroid when the compound is made
+    pub fn local_centroid(&self) -> Vector2f<Real> {
+        Self::compute_centroid(&self.children, self.area)
+    }
+
+    pub fn get_transform(&self) -> Transform2<Real> {
+        Transform2::new(self.center, self.rotation)
+    }
+
+    pub fn set_center(&mut self, center: Vector2f<Real>) {
+        self.center = center;
+        self.update_world();
+    }
+
+    pub fn set_rotation(&mut self, rotation: Real) {
+        self.rotation = rotation;
+        self.update_world();
+    }
+
+    pub fn set_transform(&mut self, center: Vector2f<Real>, rotation: Real) {
+        self.center = center;
+        self.rotation = rotation;
+        self.update_world();
+    }
+
+    pub fn translate(&mut self, translation: Vector2f<Real>) {
+        self.set_center(self.center + translation);
+    }
+
+    pub fn rotate(&mut self, radians: Real) {
+        self.set_rotation(self.rotation + radians);
+    }
+
+    // Moves the world space children to where the local ones are seen from the compound
+    fn update_world(&mut self) {
+        let transform = self.get_transform();
+        for (world, local) in self.world_children.iter_mut().zip(self.children.iter()) {
+            world.set_transform(transform.apply(local.get_center()), local.get_rotation() + self.rotation);
+        }
+    }
+
+    // The children in the local space of the compound
+    pub fn get_children(&self) -> &[ShapeType] {
+        &self.children
+    }
+
+    pub fn get_transformed_child(&self, index: usize) -> &ShapeType {
+        &self.world_children[index]
+    }
+
+    pub fn get_transformed_children(&self) -> &[ShapeType] {
+        &self.world_children
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::physics::circle::Circle;
+    use crate::physics::polygon::Polygon;
+    use crate::utils::real::consts::FRAC_PI_2;
+
+    fn is_finite(compound: &Compound) -> bool {
+        compound.area().is_finite() && compound.momemnt_of_inertia().is_finite()
+            && compound.get_children().iter().all(|child| child.get_center().x.is_finite() && child.get_center().y.is_finite())
+    }
+
+    #[test]
+    fn children_are_centered_on_the_centroid() {
+        let big = Polygon::new_square(Vector2f::new(0.0, 0.0), 20.0, 0.0);
+        let small = Polygon::new_square(Vector2f::new(30.0, 0.0), 10.0, 0.0);
+        let compound = Compound::new(vec![ShapeType::Polygon(big), ShapeType::Polygon(small)], Vector2f::new(100.0, 50.0), 0.0);
+
+        // 400 units of area at x = 0 and 100 at x = 30
+        assert!(compound.get_children()[0].get_center().nearly_equal(Vector2f::new(-6.0, 0.0), 1e-4));
+        assert!(compound.get_transformed_child(1).get_center().nearly_equal(Vector2f::new(124.0, 50.0), 1e-4));
+        assert!((compound.area() - 500.0).abs() < 1e-4);
+    }
+
+    #[test]
+    fn world_children_follow_the_compound() {
+        let bar = Polygon::new_rectangle(Vector2f::new(-10.0, 0.0), 4.0, 4.0, 0.0);
+        let other = Polygon::new_rectangle(Vector2f::new(10.0, 0.0), 4.0, 4.0, 0.0);
+        let mut shape = ShapeType::Compound(Compound::new(vec![ShapeType::Polygon(bar), ShapeType::Polygon(other)], Vector2f::zero(), 0.0));
+        shape.translate(Vector2f::new(5.0, 5.0));
+        shape.rotate(FRAC_PI_2);
+
+        let ShapeType::Compound(compound) = &shape else { unreachable!() };
+        let ShapeType::Polygon(bar) = compound.get_transformed_child(0) else { unreachable!() };
+        assert!(bar.get_center().nearly_equal(Vector2f::new(5.0, -5.0), 1e-4));
+        assert!((bar.get_rotation() - FRAC_PI_2).abs() < 1e-6);
+        assert!(bar.get_transformed_vertices().iter().all(|v| (v.x - 5.0).abs() <= 2.0 + 1e-4));
+        assert!(compound.get_transformed_child(1).get_center().nearly_equal(Vector2f::new(5.0, 15.0), 1e-4));
+    }
+
+    #[test]
+    fn empty_compound_stays_finite() {
+        let compound = Compound::new(vec![], Vector2f::new(10.0, 10.0), 0.0);
+        assert!(is_finite(&compound));
+        assert_eq!(compound.area(), 0.0);
+    }
+
+    #[test]
+    fn zero_area_children_stay_finite() {
+        let children = vec![
+            ShapeType::Circle(Circle::new(Vector2f::new(-10.0, 0.0), 0.0, 0.0)),
+            ShapeType::Circle(Circle::new(Vector2f::new(10.0, 4.0), 0.0, 0.0)),
+        ];
+        let compound = Compound::new(children, Vector2f::zero(), 0.0);
+        assert!(is_finite(&compound));
+        assert!(compound.get_children()[0].get_center().nearly_equal(Vector2f::new(-10.0, -2.0), 1e-4));
+    }
+}
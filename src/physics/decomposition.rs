@@ -0,0 +1,159 @@
+use crate::utils::real::Real;
+use crate::Vector2f;
+
+// Splits a simple polygon into convex parts using the Hertel-Mehlhorn algorithm. The polygon is
+// first triangulated by ear clipping, after which the diagonals that are not needed to keep the
+// parts convex are removed. The vertices are expected to be wound with a positive area, and the
+// parts keep the same winding.
+// Source: https://en.wikipedia.org/wiki/Polygon_partition
+pub fn decompose(vertices: &[Vector2f<Real>]) -> Vec<Vec<Vector2f<Real>>> {
+    if vertices.len() <= 3 || is_convex(vertices) {
+        return vec![vertices.to_vec()];
+    }
+
+    let Some(triangles) = triangulate(vertices) else {
+        // Not a simple polygon, so fall back to treating it as convex
+        return vec![vertices.to_vec()];
+    };
+
+    let mut parts: Vec<Vec<usize>> = triangles.iter().map(|t| t.to_vec()).collect();
+    let mut merged = true;
+    while merged {
+        merged = false;
+        'search: for i in 0..parts.len() {
+            for j in (i + 1)..parts.len() {
+                if let Some(part) = merge(&parts[i], &parts[j]) {
+                    let corners: Vec<Vector2f<Real>> = part.iter().map(|&k| vertices[k]).collect();
+                    if is_convex(&corners) {
+                        parts[i] = part;
+                        parts.swap_remove(j);
+                        merged = true;
+                        break 'search;
+                    }
+                }
+            }
+        }
+    }
+
+    parts.iter().map(|part| part.iter().map(|&k| vertices[k]).collect()).collect()
+}
+
+pub fn signed_area(vertices: &[Vector2f<Real>]) -> Real {
+    let n = vertices.len();
+    let mut sum = 0.0;
+    for i in 0..n {
+        sum += vertices[i].cross(vertices[(i + 1) % n]);
+    }
+    sum / 2.0
+}
+
+pub fn is_convex(vertices: &[Vector2f<Real>]) -> bool {
+    let n = vertices.len();
+    (0..n).all(|i| turn(vertices[(i + n - 1) % n], vertices[i], vertices[(i + 1) % n]) >= -1e-9)
+}
+
+fn turn(prev: Vector2f<Real>, curr: Vector2f<Real>, next: Vector2f<Real>) -> Real {
+    (curr - prev).cross(next - curr)
+}
+
+fn in_triangle(p: Vector2f<Real>, a: Vector2f<Real>, b: Vector2f<Real>, c: Vector2f<Real>) -> bool {
+    (b - a).cross(p - a) >= 0.0 && (c - b).cross(p - b) >= 0.0 && (a - c).cross(p - c) >= 0.0
+}
+
+// Ear clipping, returns the triangles as indices into the vertices
+fn triangulate(vertices: &[Vector2f<Real>]) -> Option<Vec<[usize; 3]>> {
+    let mut remaining: Vec<usize> = (0..vertices.len()).collect();
+    let mut triangles = vec![];
+
+    while remaining.len() > 3 {
+        let n = remaining.len();
+        let ear = (0..n).find(|&i| {
+            let (prev, curr, next) = (remaining[(i + n - 1) % n], remaining[i], remaining[(i + 1) % n]);
+            let (a, b, c) = (vertices[prev], vertices[curr], vertices[next]);
+            // Outlines with holes bridged into them visit some points twice, those copies of the
+            // corners do not block the ear
+            turn(a, b, c) > 0.0 && remaining.iter()
+                .filter(|&&k| k != prev && k != curr && k != next)
+                .filter(|&&k| ![a, b, c].iter().any(|&corner| (vertices[k] - corner).len_squared() <= Real::EPSILON))
+                .all(|&k| !in_triangle(vertices[k], a, b, c))
+        })?;
+
+        triangles.push([remaining[(ear + n - 1) % n], remaining[ear], remaining[(ear + 1) % n]]);
+        remaining.remove(ear);
+    }
+    triangles.push([remaining[0], remaining[1], remaining[2]]);
+
+    Some(triangles)
+}
+
+// Joins two parts along their shared edge, if they have one
+fn merge(a: &[usize], b: &[usize]) -> Option<Vec<usize>> {
+    let (n, m) = (a.len(), b.len());
+    for i in 0..n {
+        let (u, v) = (a[i], a[(i + 1) % n]);
+        if let Some(j) = (0..m).find(|&j| b[j] == v && b[(j + 1) % m] == u) {
+            // Walk a from v around to u, then b from u around to v, skipping the shared corners
+            let mut part: Vec<usize> = (1..=n).map(|k| a[(i + k) % n]).collect();
+            part.extend((2..m).map(|k| b[(j + k) % m]));
+            return Some(part);
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::physics::geometry::difference;
+
+    fn v(x: Real, y: Real) -> Vector2f<Real> {
+        Vector2f::new(x, y)
+    }
+
+    fn assert_valid_partition(vertices: &[Vector2f<Real>], parts: &[Vec<Vector2f<Real>>]) {
+        let total: Real = parts.iter().map(|part| signed_area(part)).sum();
+        assert!((total - signed_area(vertices)).abs() < 1e-6, "parts cover {} instead of {}", total, signed_area(vertices));
+        for part in parts {
+            assert!(part.len() >= 3);
+            assert!(is_convex(part));
+            assert!(signed_area(part) > 0.0);
+            // Diagonals only run between the corners of the polygon
+            assert!(part.iter().all(|p| vertices.iter().any(|q| p.nearly_equal(*q, 1e-9))));
+        }
+    }
+
+    #[test]
+    fn convex_polygon_is_one_part() {
+        let square = vec![v(0.0, 0.0), v(10.0, 0.0), v(10.0, 10.0), v(0.0, 10.0)];
+        assert_eq!(decompose(&square).len(), 1);
+    }
+
+    #[test]
+    fn l_shape_splits_in_two() {
+        let l = vec![v(0.0, 0.0), v(60.0, 0.0), v(60.0, 20.0), v(20.0, 20.0), v(20.0, 60.0), v(0.0, 60.0)];
+        let parts = decompose(&l);
+        assert_eq!(parts.len(), 2);
+        assert_valid_partition(&l, &parts);
+    }
+
+    #[test]
+    fn comb_is_split_into_convex_parts() {
+        // Three teeth pointing down from a bar
+        let comb = vec![
+            v(0.0, 0.0), v(50.0, 0.0), v(50.0, 40.0), v(40.0, 40.0), v(40.0, 10.0), v(30.0, 10.0),
+            v(30.0, 40.0), v(20.0, 40.0), v(20.0, 10.0), v(10.0, 10.0), v(10.0, 40.0), v(0.0, 40.0),
+        ];
+        let parts = decompose(&comb);
+        assert!(parts.len() >= 3);
+        assert_valid_partition(&comb, &parts);
+    }
+
+    #[test]
+    fn bridged_hole_is_split_around_the_hole() {
+        let frame = difference(&[v(0.0, 0.0), v(10.0, 0.0), v(10.0, 10.0), v(0.0, 10.0)], &[v(3.0, 3.0), v(7.0, 3.0), v(7.0, 7.0), v(3.0, 7.0)]);
+        let outline = frame[0].to_simple();
+        let parts = decompose(&outline);
+        assert!(parts.len() >= 4);
+        assert_valid_partition(&outline, &parts);
+    }
+}
@@ -0,0 +1,112 @@
+use crate::utils::real::Real;
+use crate::Vector2f;
+
+use super::collision::{contact_shape_shape, shape_vs_shape};
+use super::gjk::closest_points;
+use super::shape_type::ShapeType;
+
+#[allow(dead_code)]
+#[derive(Clone, Copy, Debug)]
+pub struct DistanceResult {
+    // Zero when the shapes touch or overlap
+    pub distance: Real,
+    pub point_a: Vector2f<Real>,
+    pub point_b: Vector2f<Real>,
+}
+
+// A convex piece of a shape, made of the core vertices grown outwards by the radius. Circles and
+// capsules are a point and a segment with a radius, which keeps them exact in the distance test.
+struct Piece {
+    vertices: Vec<Vector2f<Real>>,
+    radius: Real,
+}
+
+impl Piece {
+    fn support(&self, direction: Vector2f<Real>) -> Vector2f<Real> {
+        *self.vertices.iter().max_by(|a, b| a.dot(direction).total_cmp(&b.dot(direction))).unwrap()
+    }
+
+    // Smallest distance between the bounding boxes of the pieces, used to skip pairs that can not
+    // be closer than the best pair found so far
+    fn gap(&self, other: &Piece) -> Real {
+        let bounds = |piece: &Piece| piece.vertices.iter().fold(
+            (Vector2f::new(Real::INFINITY, Real::INFINITY), Vector2f::new(Real::NEG_INFINITY, Real::NEG_INFINITY)),
+            |(min, max), v| (Vector2f::new(min.x.min(v.x), min.y.min(v.y)), Vector2f::new(max.x.max(v.x), max.y.max(v.y))),
+        );
+        let ((min_a, max_a), (min_b, max_b)) = (bounds(self), bounds(other));
+        let dx = (min_b.x - max_a.x).max(min_a.x - max_b.x).max(0.0);
+        let dy = (min_b.y - max_a.y).max(min_a.y - max_b.y).max(0.0);
+        (dx * dx + dy * dy).sqrt() - self.radius - other.radius
+    }
+}
+
+// Returns how far apart the shapes are when placed at the given positions and rotations, along
+// with the closest point on each of them
+#[allow(dead_code)]
+pub fn distance(a: &ShapeType, position_a: Vector2f<Real>, rotation_a: Real, b: &ShapeType, position_b: Vector2f<Real>, rotation_b: Real) -> DistanceResult {
+    let place = |shape: &ShapeType, position, rotation| {
+        let mut shape = shape.clone();
+        shape.set_center(position);
+        shape.set_rotation(rotation);
+        shape
+    };
+
+    shape_distance(&place(a, position_a, rotation_a), &place(b, position_b, rotation_b))
+}
+
+// Same as distance, but for shapes that are already in place
+pub fn shape_distance(a: &ShapeType, b: &ShapeType) -> DistanceResult {
+    let (pieces_a, pieces_b) = (get_pieces(a), get_pieces(b));
+    let mut closest: Option<DistanceResult> = None;
+    for piece_a in &pieces_a {
+        for piece_b in &pieces_b {
+            if closest.is_some_and(|closest| piece_a.gap(piece_b) >= closest.distance) {
+                continue;
+            }
+
+            let Some((core_a, core_b)) = closest_points(|d| piece_a.support(d), |d| piece_b.support(d)) else {
+                return overlapping(a, b);
+            };
+
+            let normal = (core_b - core_a).normalize();
+            let distance = (core_b - core_a).len() - piece_a.radius - piece_b.radius;
+            if distance <= 0.0 {
+                return overlapping(a, b);
+            }
+
+            if closest.is_none_or(|closest| distance < closest.distance) {
+                closest = Some(DistanceResult {
+                    distance,
+                    point_a: core_a + normal * piece_a.radius,
+                    point_b: core_b - normal * piece_b.radius,
+                });
+            }
+        }
+    }
+
+    closest.unwrap_or_else(|| overlapping(a, b))
+}
+
+// Touching shapes share their closest point, which is taken from the contact between them
+fn overlapping(a: &ShapeType, b: &ShapeType) -> DistanceResult {
+    let point = shape_vs_shape(a, b)
+        .map(|collision| contact_shape_shape(a, b, &collision))
+        .and_then(|contacts| contacts.first().copied())
+        .unwrap_or((a.get_center() + b.get_center()) / 2.0);
+
+    DistanceResult { distance: 0.0, point_a: point, point_b: point }
+}
+
+fn get_pieces(shape: &ShapeType) -> Vec<Piece> {
+    match shape {
+        ShapeType::Circle(c) => vec![Piece { vertices: vec![c.center], radius: c.radius }],
+        ShapeType::Capsule(c) => {
+            let (a, b) = c.get_segment();
+            vec![Piece { vertices: vec![a, b], radius: c.radius }]
+        }
+        ShapeType::Polygon(p) => p.get_transformed_parts().iter().map(|vertices| Piece { vertices: vertices.clone(), radius: 0.0 }).collect(),
+        ShapeType::Compound(c) => c.get_transformed_children().iter().flat_map(get_pieces).collect(),
+        ShapeType::Chain(c) => c.get_segments().into_iter().map(|seg| Piece { vertices: vec![seg.a, seg.b], radius: 0.0 }).collect(),
+        ShapeType::Heightfield(_) => shape.get_convex_outlines().into_iter().map(|vertices| Piece { vertices, radius: 0.0 }).collect(),
+    }
+}
@@ -0,0 +1,147 @@
+use graphics::math::Matrix2d;
+use opengl_graphics::GlGraphics;
+
+use crate::utils::real::Real;
+use crate::Vector2f;
+use crate::game::PhysicsData;
+use super::collision::AABB;
+use super::rigid_body::RigidBody;
+use super::shape::Shape;
+use super::shape_type::ShapeType;
+use super::tiled_mesh::suth_hodg_clip;
+
+pub const WATER_DENSITY: Real = 1.0;
+const BASE_LINEAR_DRAG: Real = 1.5;
+const BASE_ANGULAR_DRAG: Real = 2.0;
+const FLUID_COLOR: [f32; 4] = [0.2, 0.45, 0.9, 0.4];
+
+// A convex volume of fluid applying buoyancy, drag and current to submerged bodies
+#[derive(Clone)]
+pub struct Fluid {
+    pub vertices: Vec<Vector2f<Real>>,
+    pub density: Real, // g/cm³
+    pub linear_drag: Real,
+    pub angular_drag: Real,
+    pub current: Vector2f<Real>,
+}
+
+impl Fluid {
+    pub fn new(mut vertices: Vec<Vector2f<Real>>, density: Real) -> Self {
+        // The clipping expects the fluid outline to be wound with a positive area
+        if signed_area(&vertices) < 0.0 {
+            vertices.reverse();
+        }
+
+        Self {
+            vertices,
+            density,
+            linear_drag: BASE_LINEAR_DRAG,
+            angular_drag: BASE_ANGULAR_DRAG,
+            current: Vector2f::zero(),
+        }
+    }
+
+    pub fn new_water(center: Vector2f<Real>, width: Real, height: Real) -> Self {
+        let half_width = width / 2.0;
+        let half_height = height / 2.0;
+        let vertices = vec![
+            center + Vector2f::new(-half_width, -half_height), // Top left
+            center + Vector2f::new(half_width, -half_height), // Top right
+            center + Vector2f::new(half_width, half_height), // Bottom right
+            center + Vector2f::new(-half_width, half_height), // Bottom left
+        ];
+
+        Self::new(vertices, WATER_DENSITY)
+    }
+
+    pub fn get_aabb(&self) -> AABB {
+        let mut min = Vector2f::new(Real::INFINITY, Real::INFINITY);
+        let mut max = Vector2f::new(Real::NEG_INFINITY, Real::NEG_INFINITY);
+        for v in self.vertices.as_slice() {
+            min = Vector2f::new(min.x.min(v.x), min.y.min(v.y));
+            max = Vector2f::new(max.x.max(v.x), max.y.max(v.y));
+        }
+
+        AABB { top_left: min, bottom_right: max }
+    }
+
+    // Returns the submerged area of the shape and the centroid of that area
+    pub fn submerged_area(&self, shape: &ShapeType) -> Option<(Real, Vector2f<Real>)> {
+        let mut total_area = 0.0;
+        let mut weighted_centroid = Vector2f::zero();
+        for mut submerged in shape.get_convex_outlines() {
+            suth_hodg_clip(&mut submerged, &self.vertices);
+            if submerged.len() < 3 {
+                continue;
+            }
+
+            let (area, centroid) = area_and_centroid(&submerged);
+            total_area += area;
+            weighted_centroid += centroid * area;
+        }
+
+        if total_area <= Real::EPSILON {
+            return None;
+        }
+
+        Some((total_area, weighted_centroid / total_area))
+    }
+
+    pub fn apply_forces(&self, body: &mut RigidBody, physics: &PhysicsData) {
+        if body.is_static || !self.get_aabb().overlap(&body.shape.get_aabb()) {
+            return;
+        }
+
+        if let Some((area, centroid)) = self.submerged_area(&body.shape) {
+            let dt = physics.dt;
+            let fraction = (area / body.shape.area()).min(1.0);
+            let inv_mass = body.get_inv_mass();
+            let inv_inertia = body.get_inv_inertia();
+
+            // The weight of the displaced fluid pushes against gravity at the submerged centroid
+            let impulse = -physics.gravity * self.density * area * dt;
+            let r = centroid - body.shape.get_center();
+            body.linear_velocity += body.lock_axes(impulse) * inv_mass;
+            body.angular_velocity += r.cross(impulse) * inv_inertia;
+
+            // Drag pulls the body towards the velocity of the current
+            let relative_velocity = body.linear_velocity - self.current;
+            body.linear_velocity -= body.lock_axes(relative_velocity) * (self.linear_drag * fraction * dt).min(1.0);
+            body.angular_velocity *= 1.0 - (self.angular_drag * fraction * dt).min(1.0);
+        }
+    }
+
+    pub fn draw(&self, transform: Matrix2d, gl: &mut GlGraphics) {
+        let verts: Vec<[f64; 2]> = self.vertices.iter().map(|&v| v.cast().into()).collect();
+        graphics::polygon(FLUID_COLOR, &verts, transform, gl);
+    }
+}
+
+fn signed_area(vertices: &[Vector2f<Real>]) -> Real {
+    let n = vertices.len();
+    let mut sum = 0.0;
+    for i in 0..n {
+        sum += vertices[i].cross(vertices[(i + 1) % n]);
+    }
+    sum / 2.0
+}
+
+fn area_and_centroid(vertices: &[Vector2f<Real>]) -> (Real, Vector2f<Real>) {
+    let n = vertices.len();
+    let mut sum_area = 0.0;
+    let mut sum_centroid = Vector2f::zero();
+    for i in 0..n {
+        let p1 = vertices[i];
+        let p2 = vertices[(i + 1) % n];
+        let cross = p1.cross(p2);
+        sum_area += cross;
+        sum_centroid += (p1 + p2) * cross;
+    }
+
+    let area = sum_area / 2.0;
+    if area.abs() <= Real::EPSILON {
+        return (0.0, vertices[0]);
+    }
+
+    (area.abs(), sum_centroid / (6.0 * area))
+}
@@ -0,0 +1,170 @@
+use crate::utils::real::{from_f64, Real};
+use crate::Vector2f;
+use super::polygon::Polygon;
+use super::rigid_body::RigidBody;
+use super::shape::Shape;
+use super::shape_type::ShapeType;
+use super::tiled_mesh::clip;
+
+// Bodies smaller than this are too small to break any further
+pub const MIN_FRACTURE_AREA: Real = 400.0;
+const MIN_FRAGMENTS: usize = 3;
+const MAX_FRAGMENTS: usize = 8;
+// How far the seeds spread from the impact point, relative to the radius of the body
+const SEED_SPREAD: Real = 0.6;
+const GOLDEN_ANGLE: Real = from_f64(2.399963229728653);
+
+// The strongest contact impulse a body received during a step
+#[derive(Clone, Copy)]
+pub struct Impact {
+    pub impulse: Real,
+    pub point: Vector2f<Real>,
+}
+
+// Returns how many times the impact exceeded the toughness of the body, if it is able to break at all
+pub fn impact_ratio(body: &RigidBody, impact: &Impact) -> Option<Real> {
+    if body.is_static || !body.material.toughness.is_finite() {
+        return None;
+    }
+
+    let ShapeType::Polygon(poly) = &body.shape else {
+        return None;
+    };
+
+    let area = poly.area();
+    if area < MIN_FRACTURE_AREA {
+        return None;
+    }
+
+    let ratio = impact.impulse / (body.material.toughness * area);
+    if ratio > 1.0 { Some(ratio) } else { None }
+}
+
+// Splits the body into convex fragments if the impact was too strong for it. The fragments are
+// the voronoi cells of seeds scattered around the impact point, clipped to the polygon.
+pub fn fracture(body: &RigidBody, impact: &Impact) -> Option<Vec<RigidBody>> {
+    let ratio = impact_ratio(body, impact)?;
+    let ShapeType::Polygon(poly) = &body.shape else {
+        return None;
+    };
+
+    // Harder impacts shatter the body into more pieces
+    let n_seeds = ((MIN_FRAGMENTS as Real * ratio.sqrt()).round() as usize).clamp(MIN_FRAGMENTS, MAX_FRAGMENTS);
+    let transform = poly.get_transform();
+    let local_impact = transform.apply_inv(impact.point);
+    let radius = poly.local_vertices.iter().map(|v| v.len()).fold(0.0, Real::max);
+    let seeds: Vec<Vector2f<Real>> = (0..n_seeds).map(|k| {
+        // Fermat spiral, so the cells get smaller towards the impact
+        let r = radius * SEED_SPREAD * (k as Real / n_seeds as Real).sqrt();
+        let angle = k as Real * GOLDEN_ANGLE;
+        local_impact + Vector2f::new(r * angle.cos(), r * angle.sin())
+    }).enumerate().filter(|&(k, seed)| {
+        // Seeds outside the body would only cut off slivers along the edges
+        k == 0 || poly.contains_point(transform.apply(seed))
+    }).map(|(_, seed)| seed).collect();
+
+    // Concave polygons are cut part by part, so every fragment stays convex
+    let cells = poly.get_parts().iter().flat_map(|part| seeds.iter().enumerate().map(move |(i, &seed)| (part, i, seed)));
+
+    let area = poly.area();
+    let moment = poly.momemnt_of_inertia();
+    let mut fragments = vec![];
+    for (part, i, seed) in cells {
+        let mut cell = part.clone();
+        for (j, &other) in seeds.iter().enumerate() {
+            let d = other - seed;
+            if i == j || d.len_squared() <= Real::EPSILON {
+                continue;
+            }
+
+            // Keep the half of the cell closest to the seed
+            let mid = (seed + other) / 2.0;
+            clip(&mut cell, mid, mid + d.perpendicular());
+        }
+
+        if cell.len() < 3 {
+            continue;
+        }
+
+        let mut shape = Polygon::new(cell.clone(), poly.get_center(), poly.get_rotation());
+        if shape.area() <= Real::EPSILON {
+            continue;
+        }
+
+        // The center of the fragment in the local space of the original body
+        let offset = cell[0] - shape.local_vertices[0];
+        let r = transform.rotation.apply(offset);
+        shape.set_center(poly.get_center() + r);
+
+        // Overridden masses are shared out by area, so the fragments weigh as much as the body.
+        // The inertia is shared out by how much of the moment of the body the fragment has about
+        // its own center, which keeps the ratio between the override and the moment of the shape.
+        let (fragment_area, fragment_moment) = (shape.area(), shape.momemnt_of_inertia());
+        let mut fragment = RigidBody::new(ShapeType::Polygon(shape), body.material, false);
+        fragment.mesh = body.mesh.fragment(&cell, offset);
+        fragment.mass_override = body.mass_override.map(|mass| mass * fragment_area / area);
+        fragment.inertia_override = body.inertia_override.map(|inertia| inertia * fragment_moment / moment);
+        fragment.locks = body.locks;
+        fragment.linear_velocity = fragment.lock_axes(body.linear_velocity + r.perpendicular() * body.angular_velocity);
+        fragment.angular_velocity = if body.locks.rotation { 0.0 } else { body.angular_velocity };
+        fragment.linear_damping = body.linear_damping;
+        fragment.angular_damping = body.angular_damping;
+        fragments.push(fragment);
+    }
+
+    if fragments.len() < 2 {
+        return None;
+    }
+
+    // The fragments carry the same momentum as the body they broke off from
+    let mass = body.mass_properties().mass;
+    let mut fragments_mass = 0.0;
+    let mut fragments_momentum = Vector2f::zero();
+    for fragment in fragments.as_slice() {
+        let m = fragment.mass_properties().mass;
+        fragments_mass += m;
+        fragments_momentum += fragment.linear_velocity * m;
+    }
+    let correction = (body.linear_velocity * mass - fragments_momentum) / fragments_mass;
+    for fragment in fragments.as_mut_slice() {
+        fragment.linear_velocity += fragment.lock_axes(correction);
+    }
+
+    Some(fragments)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::physics::material::GLASS;
+    use crate::physics::rigid_body::MotionLocks;
+
+    #[test]
+    fn fragments_share_the_overrides_and_locks_of_the_body() {
+        let square = Polygon::new_square(Vector2f::new(100.0, 100.0), 60.0, 0.4);
+        let mut body = RigidBody::new(ShapeType::Polygon(square), GLASS, false);
+        body.mass_override = Some(5000.0);
+        body.inertia_override = Some(4.0e6);
+        body.locks = MotionLocks { rotation: true, x: true, y: false };
+        body.linear_velocity = Vector2f::new(0.0, 30.0);
+
+        let impact = Impact { impulse: 1.0e9, point: Vector2f::new(110.0, 90.0) };
+        let fragments = fracture(&body, &impact).expect("the impact should break the body");
+        assert!(fragments.len() >= 2);
+
+        let mass: Real = fragments.iter().map(|f| f.mass_properties().mass).sum();
+        assert!((mass - 5000.0).abs() < 1e-6 * 5000.0, "fragments weigh {mass}");
+
+        let momentum = fragments.iter().fold(Vector2f::zero(), |sum, f| sum + f.linear_velocity * f.mass_properties().mass);
+        assert!(momentum.nearly_equal(body.linear_velocity * 5000.0, 1e-3 * 5000.0), "{:?}", momentum);
+
+        let ratio = 4.0e6 / body.shape.momemnt_of_inertia();
+        for fragment in &fragments {
+            assert_eq!((fragment.locks.rotation, fragment.locks.x, fragment.locks.y), (true, true, false));
+            assert_eq!(fragment.linear_velocity.x, 0.0);
+            assert_eq!(fragment.angular_velocity, 0.0);
+            let inertia = fragment.inertia_override.unwrap();
+            assert!((inertia / fragment.shape.momemnt_of_inertia() - ratio).abs() < 1e-6 * ratio);
+        }
+    }
+}
@@ -0,0 +1,502 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::utils::real::Real;
+use crate::Vector2f;
+
+use super::decomposition::signed_area;
+use super::polygon::Polygon;
+
+// Points closer than this are welded together when the edges are linked back into rings
+const WELD_PRECISION: Real = 1e6;
+const EPSILON: Real = 1e-9;
+
+#[allow(dead_code)]
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum BooleanOp {
+    Union,
+    Intersection,
+    Difference,
+}
+
+// A connected piece of the result of a boolean operation. The outer ring has a positive signed
+// area and the holes a negative one, so the solid part is always on the same side of the edges.
+#[derive(Clone, Debug)]
+pub struct Region {
+    pub outer: Vec<Vector2f<Real>>,
+    pub holes: Vec<Vec<Vector2f<Real>>>,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum EdgeSide {
+    Inside,
+    Outside,
+    // The edge lies on an edge of the other polygon running the same or the opposite way
+    SharedSame,
+    SharedOpposite,
+}
+
+// The welded start and end of an edge
+type EdgeKey = ((i64, i64), (i64, i64));
+
+#[derive(Clone, Copy)]
+struct Edge {
+    start: Vector2f<Real>,
+    end: Vector2f<Real>,
+}
+
+#[allow(dead_code)]
+pub fn union(a: &[Vector2f<Real>], b: &[Vector2f<Real>]) -> Vec<Region> {
+    boolean(a, b, BooleanOp::Union)
+}
+
+#[allow(dead_code)]
+pub fn intersection(a: &[Vector2f<Real>], b: &[Vector2f<Real>]) -> Vec<Region> {
+    boolean(a, b, BooleanOp::Intersection)
+}
+
+#[allow(dead_code)]
+pub fn difference(a: &[Vector2f<Real>], b: &[Vector2f<Real>]) -> Vec<Region> {
+    boolean(a, b, BooleanOp::Difference)
+}
+
+// Combines two simple polygons, which can be concave and wound either way. Both outlines are split
+// where they cross, every piece of an edge is sorted by whether it lies inside the other polygon,
+// and the pieces belonging to the result are linked back up into rings.
+pub fn boolean(a: &[Vector2f<Real>], b: &[Vector2f<Real>], op: BooleanOp) -> Vec<Region> {
+    let (a, b) = (normalize(a), normalize(b));
+    if a.len() < 3 || b.len() < 3 {
+        return match op {
+            BooleanOp::Union | BooleanOp::Difference if a.len() >= 3 => vec![Region { outer: a, holes: vec![] }],
+            BooleanOp::Union if b.len() >= 3 => vec![Region { outer: b, holes: vec![] }],
+            _ => vec![],
+        };
+    }
+
+    let (edges_a, edges_b) = split_edges(&a, &b);
+    let keys_b: HashSet<EdgeKey> = edges_b.iter().map(|e| (key(e.start), key(e.end))).collect();
+    let keys_a: HashSet<EdgeKey> = edges_a.iter().map(|e| (key(e.start), key(e.end))).collect();
+
+    let mut selected = vec![];
+    for edge in edges_a {
+        let keep = matches!(
+            (classify(&edge, &b, &keys_b), op),
+            (EdgeSide::Outside, BooleanOp::Union | BooleanOp::Difference)
+                | (EdgeSide::Inside, BooleanOp::Intersection)
+                | (EdgeSide::SharedSame, BooleanOp::Union | BooleanOp::Intersection)
+                | (EdgeSide::SharedOpposite, BooleanOp::Difference)
+        );
+        if keep {
+            selected.push(edge);
+        }
+    }
+
+    // Shared edges were already handled along with the edges of a
+    for edge in edges_b {
+        match (classify(&edge, &a, &keys_a), op) {
+            (EdgeSide::Outside, BooleanOp::Union) | (EdgeSide::Inside, BooleanOp::Intersection) => selected.push(edge),
+            // The edges of b become the walls of the cut, facing the other way
+            (EdgeSide::Inside, BooleanOp::Difference) => selected.push(Edge { start: edge.end, end: edge.start }),
+            _ => {}
+        }
+    }
+
+    group_rings(link_rings(selected))
+}
+
+impl Region {
+    // Joins every hole to the outer ring with a pair of overlapping bridge edges, which gives a
+    // single outline that can be decomposed into convex parts like any other simple polygon
+    pub fn to_simple(&self) -> Vec<Vector2f<Real>> {
+        let mut outline = self.outer.clone();
+        let mut holes = self.holes.clone();
+
+        // Holes furthest to the right first, so the bridges of later holes can not cross them
+        holes.sort_by(|h1, h2| max_x(h2).total_cmp(&max_x(h1)));
+        for (i, hole) in holes.iter().enumerate() {
+            let start = (0..hole.len()).max_by(|&j, &k| hole[j].x.total_cmp(&hole[k].x)).unwrap();
+            let from = hole[start];
+
+            let blocked = |to: Vector2f<Real>| {
+                [&outline].into_iter().chain(holes.iter().skip(i)).any(|ring| {
+                    (0..ring.len()).any(|j| {
+                        let (p, q) = (ring[j], ring[(j + 1) % ring.len()]);
+                        crosses(from, to, p, q)
+                    })
+                })
+            };
+            let Some(target) = (0..outline.len())
+                .filter(|&j| !blocked(outline[j]))
+                .min_by(|&j, &k| (outline[j] - from).len_squared().total_cmp(&(outline[k] - from).len_squared()))
+            else {
+                continue;
+            };
+
+            // Walk the outline up to the target, around the hole and back across the bridge
+            let mut merged = outline[..=target].to_vec();
+            merged.extend((0..=hole.len()).map(|j| hole[(start + j) % hole.len()]));
+            merged.extend_from_slice(&outline[target..]);
+            outline = merged;
+        }
+
+        outline
+    }
+
+    // Returns the region as a polygon placed in world space
+    pub fn to_polygon(&self) -> Polygon {
+        let vertices = self.to_simple();
+        let mut polygon = Polygon::new(vertices.clone(), Vector2f::zero(), 0.0);
+        polygon.set_center(vertices[0] - polygon.local_vertices[0]);
+        polygon
+    }
+}
+
+#[allow(dead_code)]
+pub fn to_polygons(regions: &[Region]) -> Vec<Polygon> {
+    regions.iter().map(|region| region.to_polygon()).collect()
+}
+
+// Removes repeated points and winds the outline with a positive area
+fn normalize(vertices: &[Vector2f<Real>]) -> Vec<Vector2f<Real>> {
+    let mut outline: Vec<Vector2f<Real>> = vec![];
+    for &v in vertices {
+        if outline.last().is_none_or(|&last| key(last) != key(v)) {
+            outline.push(v);
+        }
+    }
+    if outline.len() > 1 && key(outline[0]) == key(outline[outline.len() - 1]) {
+        outline.pop();
+    }
+
+    if signed_area(&outline) < 0.0 {
+        outline.reverse();
+    }
+    outline
+}
+
+fn key(p: Vector2f<Real>) -> (i64, i64) {
+    ((p.x * WELD_PRECISION).round() as i64, (p.y * WELD_PRECISION).round() as i64)
+}
+
+fn max_x(ring: &[Vector2f<Real>]) -> Real {
+    ring.iter().fold(Real::NEG_INFINITY, |max, v| max.max(v.x))
+}
+
+// Whether the segments cross at a point that is not an end of the first one
+fn crosses(p1: Vector2f<Real>, p2: Vector2f<Real>, q1: Vector2f<Real>, q2: Vector2f<Real>) -> bool {
+    let (r, s) = (p2 - p1, q2 - q1);
+    let denom = r.cross(s);
+    if denom.abs() <= EPSILON {
+        return false;
+    }
+
+    let t = (q1 - p1).cross(s) / denom;
+    let u = (q1 - p1).cross(r) / denom;
+    t > EPSILON && t < 1.0 - EPSILON && (-EPSILON..=1.0 + EPSILON).contains(&u)
+}
+
+// Splits the edges of both outlines at every point where they meet
+fn split_edges(a: &[Vector2f<Real>], b: &[Vector2f<Real>]) -> (Vec<Edge>, Vec<Edge>) {
+    let mut splits_a: Vec<Vec<Vector2f<Real>>> = vec![vec![]; a.len()];
+    let mut splits_b: Vec<Vec<Vector2f<Real>>> = vec![vec![]; b.len()];
+
+    for i in 0..a.len() {
+        let (p1, p2) = (a[i], a[(i + 1) % a.len()]);
+        let r = p2 - p1;
+        for j in 0..b.len() {
+            let (q1, q2) = (b[j], b[(j + 1) % b.len()]);
+            let s = q2 - q1;
+            let denom = r.cross(s);
+
+            if denom.abs() > EPSILON * r.len() * s.len() {
+                let t = (q1 - p1).cross(s) / denom;
+                let u = (q1 - p1).cross(r) / denom;
+                let range = -EPSILON..=1.0 + EPSILON;
+                if !range.contains(&t) || !range.contains(&u) {
+                    continue;
+                }
+
+                // Snap to an existing vertex when the edges meet at one, so both sides agree
+                let point = if t.abs() <= EPSILON {
+                    p1
+                } else if (t - 1.0).abs() <= EPSILON {
+                    p2
+                } else if u.abs() <= EPSILON {
+                    q1
+                } else if (u - 1.0).abs() <= EPSILON {
+                    q2
+                } else {
+                    p1 + r * t
+                };
+                splits_a[i].push(point);
+                splits_b[j].push(point);
+            } else if (q1 - p1).cross(r).abs() <= EPSILON * r.len().max(1.0) {
+                // Overlapping edges are split at the ends of each other
+                let on = |p: Vector2f<Real>, from: Vector2f<Real>, dir: Vector2f<Real>| {
+                    let t = (p - from).dot(dir) / dir.len_squared();
+                    t > EPSILON && t < 1.0 - EPSILON
+                };
+                splits_a[i].extend([q1, q2].into_iter().filter(|&q| on(q, p1, r)));
+                splits_b[j].extend([p1, p2].into_iter().filter(|&p| on(p, q1, s)));
+            }
+        }
+    }
+
+    (cut(a, splits_a), cut(b, splits_b))
+}
+
+fn cut(outline: &[Vector2f<Real>], splits: Vec<Vec<Vector2f<Real>>>) -> Vec<Edge> {
+    let mut edges = vec![];
+    for (i, mut points) in splits.into_iter().enumerate() {
+        let (start, end) = (outline[i], outline[(i + 1) % outline.len()]);
+        let dir = end - start;
+        points.sort_by(|p, q| (*p - start).dot(dir).total_cmp(&(*q - start).dot(dir)));
+
+        let mut from = start;
+        for to in points.into_iter().chain([end]) {
+            if key(from) != key(to) {
+                edges.push(Edge { start: from, end: to });
+                from = to;
+            }
+        }
+    }
+    edges
+}
+
+fn classify(edge: &Edge, other: &[Vector2f<Real>], other_keys: &HashSet<EdgeKey>) -> EdgeSide {
+    let (start, end) = (key(edge.start), key(edge.end));
+    if other_keys.contains(&(start, end)) {
+        EdgeSide::SharedSame
+    } else if other_keys.contains(&(end, start)) {
+        EdgeSide::SharedOpposite
+    } else if contains_point(other, (edge.start + edge.end) / 2.0) {
+        EdgeSide::Inside
+    } else {
+        EdgeSide::Outside
+    }
+}
+
+// Even-odd test, works for concave outlines
+// Source: https://wrfranklin.org/Research/Short_Notes/pnpoly.html
+fn contains_point(outline: &[Vector2f<Real>], p: Vector2f<Real>) -> bool {
+    let n = outline.len();
+    let mut inside = false;
+    for i in 0..n {
+        let (a, b) = (outline[i], outline[(i + n - 1) % n]);
+        if (a.y > p.y) != (b.y > p.y) && p.x < (b.x - a.x) * (p.y - a.y) / (b.y - a.y) + a.x {
+            inside = !inside;
+        }
+    }
+    inside
+}
+
+// Follows the edges from end to start until every ring is closed. Where several edges leave the
+// same point the one turning furthest towards the solid side is taken, which keeps regions that
+// only touch at a point apart.
+fn link_rings(edges: Vec<Edge>) -> Vec<Vec<Vector2f<Real>>> {
+    let mut outgoing: HashMap<(i64, i64), Vec<usize>> = HashMap::new();
+    for (i, edge) in edges.iter().enumerate() {
+        outgoing.entry(key(edge.start)).or_default().push(i);
+    }
+
+    let mut used = vec![false; edges.len()];
+    let mut rings = vec![];
+    for first in 0..edges.len() {
+        if used[first] {
+            continue;
+        }
+
+        let mut ring = vec![];
+        let mut current = first;
+        let closed = loop {
+            used[current] = true;
+            ring.push(edges[current].start);
+
+            let edge = edges[current];
+            if key(edge.end) == key(edges[first].start) {
+                break true;
+            }
+
+            let incoming = edge.end - edge.start;
+            let next = outgoing.get(&key(edge.end)).into_iter().flatten()
+                .filter(|&&i| !used[i])
+                .max_by(|&&i, &&j| {
+                    let turn = |k: usize| {
+                        let out = edges[k].end - edges[k].start;
+                        incoming.cross(out).atan2(incoming.dot(out))
+                    };
+                    turn(i).total_cmp(&turn(j))
+                });
+
+            match next {
+                Some(&i) => current = i,
+                None => break false,
+            }
+        };
+
+        if closed {
+            let ring = remove_collinear(ring);
+            if ring.len() >= 3 && signed_area(&ring).abs() > EPSILON {
+                rings.push(ring);
+            }
+        }
+    }
+
+    rings
+}
+
+// Joins the pieces of edges that were split but ended up in the result together
+fn remove_collinear(ring: Vec<Vector2f<Real>>) -> Vec<Vector2f<Real>> {
+    let n = ring.len();
+    (0..n).filter(|&i| {
+        let (prev, curr, next) = (ring[(i + n - 1) % n], ring[i], ring[(i + 1) % n]);
+        let (d1, d2) = (curr - prev, next - curr);
+        d1.cross(d2).abs() > EPSILON * d1.len() * d2.len() || d1.dot(d2) < 0.0
+    }).map(|i| ring[i]).collect()
+}
+
+// Gives every hole to the smallest outer ring around it
+fn group_rings(rings: Vec<Vec<Vector2f<Real>>>) -> Vec<Region> {
+    let (outers, holes): (Vec<_>, Vec<_>) = rings.into_iter().partition(|ring| signed_area(ring) > 0.0);
+    let mut regions: Vec<Region> = outers.into_iter().map(|outer| Region { outer, holes: vec![] }).collect();
+
+    for hole in holes {
+        // A point just off the longest edge of the hole, on the solid side
+        let i = (0..hole.len()).max_by(|&i, &j| {
+            let len = |k: usize| (hole[(k + 1) % hole.len()] - hole[k]).len_squared();
+            len(i).total_cmp(&len(j))
+        }).unwrap();
+        let (a, b) = (hole[i], hole[(i + 1) % hole.len()]);
+        let probe = (a + b) / 2.0 + (b - a).perpendicular().normalize() * 1e-4;
+
+        let owner = regions.iter_mut()
+            .filter(|region| contains_point(&region.outer, probe))
+            .min_by(|r1, r2| signed_area(&r1.outer).total_cmp(&signed_area(&r2.outer)));
+        if let Some(region) = owner {
+            region.holes.push(hole);
+        }
+    }
+
+    regions
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::physics::shape::Shape;
+
+    fn square(x: Real, y: Real, size: Real) -> Vec<Vector2f<Real>> {
+        vec![Vector2f::new(x, y), Vector2f::new(x + size, y), Vector2f::new(x + size, y + size), Vector2f::new(x, y + size)]
+    }
+
+    // The holes have a negative area, so they are taken off the outer rings
+    fn area(regions: &[Region]) -> Real {
+        regions.iter().map(|r| signed_area(&r.outer) + r.holes.iter().map(|h| signed_area(h)).sum::<Real>()).sum()
+    }
+
+    fn assert_area(regions: &[Region], expected: Real) {
+        assert!((area(regions) - expected).abs() < 1e-6, "area {} instead of {}", area(regions), expected);
+    }
+
+    #[test]
+    fn overlapping_squares() {
+        let (a, b) = (square(0.0, 0.0, 10.0), square(5.0, 5.0, 10.0));
+        let union = union(&a, &b);
+        assert_eq!(union.len(), 1);
+        assert_eq!(union[0].outer.len(), 8);
+        assert_area(&union, 175.0);
+
+        let intersection = intersection(&a, &b);
+        assert_eq!(intersection.len(), 1);
+        assert_eq!(intersection[0].outer.len(), 4);
+        assert_area(&intersection, 25.0);
+
+        let difference = difference(&a, &b);
+        assert_eq!(difference.len(), 1);
+        assert_area(&difference, 75.0);
+        assert!(difference[0].holes.is_empty());
+    }
+
+    #[test]
+    fn disjoint_squares() {
+        let (a, b) = (square(0.0, 0.0, 10.0), square(20.0, 0.0, 10.0));
+        let union = union(&a, &b);
+        assert_eq!(union.len(), 2);
+        assert_area(&union, 200.0);
+
+        assert!(intersection(&a, &b).is_empty());
+
+        let difference = difference(&a, &b);
+        assert_eq!(difference.len(), 1);
+        assert_area(&difference, 100.0);
+    }
+
+    #[test]
+    fn contained_square() {
+        let (outer, inner) = (square(0.0, 0.0, 10.0), square(3.0, 3.0, 4.0));
+        let union = union(&outer, &inner);
+        assert_eq!(union.len(), 1);
+        assert!(union[0].holes.is_empty());
+        assert_area(&union, 100.0);
+
+        assert_area(&intersection(&outer, &inner), 16.0);
+        assert_area(&intersection(&inner, &outer), 16.0);
+        assert!(difference(&inner, &outer).is_empty());
+
+        // Cutting the inner square out leaves a hole
+        let frame = difference(&outer, &inner);
+        assert_eq!(frame.len(), 1);
+        assert_eq!(frame[0].holes.len(), 1);
+        assert!(signed_area(&frame[0].holes[0]) < 0.0);
+        assert_area(&frame, 84.0);
+    }
+
+    #[test]
+    fn squares_sharing_an_edge() {
+        let (a, b) = (square(0.0, 0.0, 10.0), square(10.0, 0.0, 10.0));
+        let union = union(&a, &b);
+        assert_eq!(union.len(), 1);
+        // The shared edge is gone and the collinear pieces are joined again
+        assert_eq!(union[0].outer.len(), 4);
+        assert_area(&union, 200.0);
+
+        assert_area(&intersection(&a, &b), 0.0);
+        assert_area(&difference(&a, &b), 100.0);
+    }
+
+    #[test]
+    fn winding_does_not_matter() {
+        let a = square(0.0, 0.0, 10.0);
+        let mut b = square(5.0, 5.0, 10.0);
+        b.reverse();
+        assert_area(&union(&a, &b), 175.0);
+        assert_area(&intersection(&a, &b), 25.0);
+        assert_area(&difference(&a, &b), 75.0);
+    }
+
+    #[test]
+    fn union_closing_a_ring_leaves_a_hole() {
+        let u = vec![
+            Vector2f::new(0.0, 0.0), Vector2f::new(30.0, 0.0), Vector2f::new(30.0, 30.0), Vector2f::new(20.0, 30.0),
+            Vector2f::new(20.0, 10.0), Vector2f::new(10.0, 10.0), Vector2f::new(10.0, 30.0), Vector2f::new(0.0, 30.0),
+        ];
+        let bar = vec![Vector2f::new(0.0, 25.0), Vector2f::new(30.0, 25.0), Vector2f::new(30.0, 35.0), Vector2f::new(0.0, 35.0)];
+        let ring = union(&u, &bar);
+        assert_eq!(ring.len(), 1);
+        assert_eq!(ring[0].holes.len(), 1);
+        assert_area(&ring, 900.0);
+    }
+
+    #[test]
+    fn holes_are_bridged_into_one_outline() {
+        let frame = difference(&square(0.0, 0.0, 10.0), &square(3.0, 3.0, 4.0));
+        let outline = frame[0].to_simple();
+        assert_eq!(outline.len(), 4 + 4 + 2);
+        assert!((signed_area(&outline) - 84.0).abs() < 1e-6);
+
+        let polygon = frame[0].to_polygon();
+        assert!((polygon.area() - 84.0).abs() < 1e-4);
+        assert!(polygon.contains_point(Vector2f::new(1.5, 5.0)));
+        assert!(!polygon.contains_point(Vector2f::new(5.0, 5.0)));
+        assert!(!polygon.contains_point(Vector2f::new(12.0, 5.0)));
+    }
+}
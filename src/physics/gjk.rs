@@ -0,0 +1,284 @@
+use crate::utils::real::Real;
+use crate::Vector2f;
+
+use super::collision::CollisionData;
+use super::shape::Shape;
+
+const MAX_GJK_ITERATIONS: usize = 32;
+const MAX_EPA_ITERATIONS: usize = 64;
+const EPA_TOLERANCE: Real = 1e-6;
+const DISTANCE_TOLERANCE: Real = 1e-9;
+
+// Overlap test for any two convex shapes, only needing the support function of each. Concave
+// shapes are treated as their convex hull, so they should be split into convex pieces first.
+// The normal points towards b, like the hand-written routines in the collision module.
+// Pairs without a hand-written routine are routed here by shape_vs_shape.
+// Source: https://dyn4j.org/2010/04/gjk-gilbert-johnson-keerthi/
+pub fn gjk_epa<A: Shape + ?Sized, B: Shape + ?Sized>(a: &A, b: &B) -> Option<CollisionData> {
+    let simplex = gjk(a, b)?;
+    let (normal, depth) = epa(a, b, simplex)?;
+
+    Some(CollisionData { sep_or_t: -depth, normal, contacts: vec![], child_a: 0, child_b: 0 })
+}
+
+// The contact point of two convex shapes that have been pushed apart along the normal, taken
+// halfway between the deepest points of each shape
+pub fn support_contact<A: Shape + ?Sized, B: Shape + ?Sized>(a: &A, b: &B, normal: Vector2f<Real>) -> Vec<Vector2f<Real>> {
+    vec![(a.support(normal) + b.support(-normal)) / 2.0]
+}
+
+// Returns the closest points of two separated convex sets, which are only given by their support
+// functions. Returns None when the sets touch or overlap. The simplex keeps the support point of
+// each set next to their difference, so the closest points can be rebuilt from the same weights
+// that give the point of the minkowski difference closest to the origin.
+// Source: https://box2d.org/files/ErinCatto_GJK_GDC2010.pdf
+pub fn closest_points(support_a: impl Fn(Vector2f<Real>) -> Vector2f<Real>, support_b: impl Fn(Vector2f<Real>) -> Vector2f<Real>) -> Option<(Vector2f<Real>, Vector2f<Real>)> {
+    let vertex = |direction: Vector2f<Real>| {
+        let (a, b) = (support_a(direction), support_b(-direction));
+        (a, b, a - b)
+    };
+
+    let mut simplex = vec![vertex(Vector2f::new(1.0, 0.0))];
+    let mut weights = vec![1.0];
+    for _ in 0..MAX_GJK_ITERATIONS {
+        (simplex, weights) = closest_feature(&simplex)?;
+        let closest = simplex.iter().zip(&weights).fold(Vector2f::zero(), |sum, (v, &w)| sum + v.2 * w);
+        if closest.len_squared() <= Real::EPSILON {
+            return None;
+        }
+
+        // Stop once the new support point gets no closer to the origin, or has been seen before
+        let next = vertex(-closest);
+        let progress = closest.len_squared() - closest.dot(next.2);
+        if progress <= DISTANCE_TOLERANCE * closest.len_squared() || simplex.iter().any(|v| (v.2 - next.2).len_squared() <= Real::EPSILON) {
+            break;
+        }
+        simplex.push(next);
+    }
+
+    let (simplex, weights) = closest_feature(&simplex)?;
+    Some(simplex.iter().zip(&weights).fold((Vector2f::zero(), Vector2f::zero()), |(a, b), (v, &w)| (a + v.0 * w, b + v.1 * w)))
+}
+
+type SimplexVertex = (Vector2f<Real>, Vector2f<Real>, Vector2f<Real>);
+
+// Reduces the simplex to the vertices of the feature closest to the origin, along with the weight
+// of each vertex in the closest point. Returns None when the origin is inside a triangle.
+fn closest_feature(simplex: &[SimplexVertex]) -> Option<(Vec<SimplexVertex>, Vec<Real>)> {
+    let on_segment = |v1: SimplexVertex, v2: SimplexVertex| {
+        let edge = v2.2 - v1.2;
+        let t = if edge.len_squared() > Real::EPSILON { (-v1.2.dot(edge) / edge.len_squared()).clamp(0.0, 1.0) } else { 0.0 };
+        let distance = (v1.2 + edge * t).len_squared();
+        if t <= 0.0 {
+            (distance, vec![v1], vec![1.0])
+        } else if t >= 1.0 {
+            (distance, vec![v2], vec![1.0])
+        } else {
+            (distance, vec![v1, v2], vec![1.0 - t, t])
+        }
+    };
+
+    match simplex.len() {
+        1 => Some((simplex.to_vec(), vec![1.0])),
+        2 => {
+            let (_, vertices, weights) = on_segment(simplex[0], simplex[1]);
+            Some((vertices, weights))
+        }
+        _ => {
+            let (p1, p2, p3) = (simplex[0].2, simplex[1].2, simplex[2].2);
+            let sides = [(p2 - p1).cross(-p1), (p3 - p2).cross(-p2), (p1 - p3).cross(-p3)];
+            if sides.iter().all(|&s| s >= 0.0) || sides.iter().all(|&s| s <= 0.0) {
+                return None;
+            }
+
+            let (_, vertices, weights) = [(0, 1), (1, 2), (2, 0)].into_iter()
+                .map(|(i, j)| on_segment(simplex[i], simplex[j]))
+                .min_by(|x, y| x.0.total_cmp(&y.0))
+                .unwrap();
+            Some((vertices, weights))
+        }
+    }
+}
+
+// Support point of the minkowski difference a - b
+fn support<A: Shape + ?Sized, B: Shape + ?Sized>(a: &A, b: &B, direction: Vector2f<Real>) -> Vector2f<Real> {
+    a.support(direction) - b.support(-direction)
+}
+
+// Returns a triangle of the minkowski difference enclosing the origin, if the shapes overlap
+fn gjk<A: Shape + ?Sized, B: Shape + ?Sized>(a: &A, b: &B) -> Option<Vec<Vector2f<Real>>> {
+    let mut direction = Vector2f::new(1.0, 0.0);
+    let mut simplex = vec![support(a, b, direction)];
+    direction = -simplex[0];
+
+    for _ in 0..MAX_GJK_ITERATIONS {
+        if direction.len_squared() <= Real::EPSILON {
+            // The origin is on the edge of the simplex, so the shapes are only touching
+            return None;
+        }
+
+        let point = support(a, b, direction);
+        if point.dot(direction) <= 0.0 {
+            return None;
+        }
+
+        simplex.push(point);
+        if update_simplex(&mut simplex, &mut direction) {
+            return Some(simplex);
+        }
+    }
+
+    None
+}
+
+// Reduces the simplex to the feature closest to the origin and points the direction towards the
+// origin from it. Returns true once the simplex encloses the origin.
+fn update_simplex(simplex: &mut Vec<Vector2f<Real>>, direction: &mut Vector2f<Real>) -> bool {
+    let a = *simplex.last().unwrap();
+    let ao = -a;
+
+    if simplex.len() == 2 {
+        let ab = simplex[0] - a;
+        if ab.dot(ao) > 0.0 {
+            *direction = towards(ab.perpendicular(), ao);
+        } else {
+            *simplex = vec![a];
+            *direction = ao;
+        }
+        return false;
+    }
+
+    let (b, c) = (simplex[1], simplex[0]);
+    let (ab, ac) = (b - a, c - a);
+    let ab_perp = towards(ab.perpendicular(), -ac);
+    let ac_perp = towards(ac.perpendicular(), -ab);
+
+    if ab_perp.dot(ao) > 0.0 {
+        *simplex = vec![b, a];
+        *direction = ab_perp;
+        false
+    } else if ac_perp.dot(ao) > 0.0 {
+        *simplex = vec![c, a];
+        *direction = ac_perp;
+        false
+    } else {
+        true
+    }
+}
+
+// Flips v to point the same way as the target
+fn towards(v: Vector2f<Real>, target: Vector2f<Real>) -> Vector2f<Real> {
+    if v.dot(target) < 0.0 { -v } else { v }
+}
+
+// Expands the simplex towards the edge of the minkowski difference closest to the origin, which
+// gives the direction and depth of the smallest push that separates the shapes
+// Source: https://dyn4j.org/2010/05/epa-expanding-polytope-algorithm/
+fn epa<A: Shape + ?Sized, B: Shape + ?Sized>(a: &A, b: &B, simplex: Vec<Vector2f<Real>>) -> Option<(Vector2f<Real>, Real)> {
+    let mut polytope = simplex;
+    let mut closest = None;
+
+    for _ in 0..MAX_EPA_ITERATIONS {
+        let n = polytope.len();
+        let (index, normal, distance) = (0..n).filter_map(|i| {
+            let (p1, p2) = (polytope[i], polytope[(i + 1) % n]);
+            let edge = p2 - p1;
+            if edge.len_squared() <= Real::EPSILON {
+                return None;
+            }
+
+            // The origin is inside the polytope, so the outward normal points away from it
+            let normal = towards(edge.perpendicular().normalize(), p1);
+            Some((i, normal, normal.dot(p1)))
+        }).min_by(|x, y| x.2.total_cmp(&y.2))?;
+
+        closest = Some((normal, distance));
+        let point = support(a, b, normal);
+        if point.dot(normal) - distance < EPA_TOLERANCE {
+            break;
+        }
+
+        polytope.insert(index + 1, point);
+    }
+
+    closest
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::physics::capsule::Capsule;
+    use crate::physics::circle::Circle;
+    use crate::physics::collision::{capsule_vs_capsule, circle_vs_circle, polygon_vs_capsule, polygon_vs_circle, polygon_vs_polygon};
+    use crate::physics::polygon::Polygon;
+    use crate::utils::real::consts::FRAC_PI_2;
+
+    fn assert_same(gjk: Option<CollisionData>, sat: Option<CollisionData>) {
+        let (gjk, sat) = (gjk.expect("GJK missed the overlap"), sat.expect("SAT missed the overlap"));
+        assert!((gjk.sep_or_t - sat.sep_or_t).abs() < 1e-3, "depth {} against {}", gjk.sep_or_t, sat.sep_or_t);
+        assert!(gjk.normal.nearly_equal(sat.normal, 1e-3), "normal {:?} against {:?}", gjk.normal, sat.normal);
+    }
+
+    #[test]
+    fn matches_polygon_vs_polygon() {
+        let a = Polygon::new_square(Vector2f::zero(), 40.0, 0.0);
+        for (center, rotation) in [(Vector2f::new(35.0, 3.0), 0.0), (Vector2f::new(10.0, -30.0), 0.3), (Vector2f::new(-25.0, 20.0), 0.7)] {
+            let b = Polygon::new_regular_polygon(6, 20.0, center, rotation);
+            assert_same(gjk_epa(&a, &b), polygon_vs_polygon(&a, &b));
+        }
+    }
+
+    #[test]
+    fn matches_polygon_vs_circle() {
+        let p = Polygon::new_regular_polygon(5, 30.0, Vector2f::zero(), 0.2);
+        for center in [Vector2f::new(35.0, 0.0), Vector2f::new(-5.0, 33.0), Vector2f::new(20.0, -20.0)] {
+            let c = Circle::new(center, 12.0, 0.0);
+            assert_same(gjk_epa(&p, &c), polygon_vs_circle(&p, &c));
+        }
+    }
+
+    #[test]
+    fn matches_circle_vs_circle() {
+        let a = Circle::new(Vector2f::zero(), 20.0, 0.0);
+        let b = Circle::new(Vector2f::new(18.0, 24.0), 15.0, 0.0);
+        assert_same(gjk_epa(&a, &b), circle_vs_circle(&a, &b));
+    }
+
+    #[test]
+    fn matches_capsule_vs_capsule() {
+        // Parallel capsules resting on each other overlap by the sum of the radii minus the gap
+        let a = Capsule::new(Vector2f::zero(), 40.0, 10.0, 0.0);
+        let b = Capsule::new(Vector2f::new(15.0, 18.0), 40.0, 10.0, 0.0);
+        let collision = capsule_vs_capsule(&a, &b).unwrap();
+        assert!((collision.sep_or_t + 2.0).abs() < 1e-3);
+        assert!(collision.normal.nearly_equal(Vector2f::new(0.0, 1.0), 1e-3));
+        assert_same(gjk_epa(&a, &b), Some(collision));
+
+        // Standing on the other one only the cap overlaps
+        let c = Capsule::new(Vector2f::new(0.0, 35.0), 40.0, 10.0, FRAC_PI_2);
+        let collision = capsule_vs_capsule(&a, &c).unwrap();
+        assert!((collision.sep_or_t + 5.0).abs() < 1e-3);
+        assert!(collision.normal.nearly_equal(Vector2f::new(0.0, 1.0), 1e-3));
+        assert_same(gjk_epa(&a, &c), Some(collision));
+
+        let d = Capsule::new(Vector2f::new(30.0, 10.0), 30.0, 8.0, 0.6);
+        assert_same(gjk_epa(&a, &d), capsule_vs_capsule(&a, &d));
+    }
+
+    #[test]
+    fn matches_polygon_vs_capsule() {
+        let p = Polygon::new_regular_polygon(6, 30.0, Vector2f::zero(), 0.1);
+        for (center, rotation) in [(Vector2f::new(0.0, 36.0), 0.0), (Vector2f::new(35.0, 10.0), 1.2)] {
+            let cap = Capsule::new(center, 30.0, 10.0, rotation);
+            assert_same(gjk_epa(&p, &cap), polygon_vs_capsule(&p, &cap));
+        }
+    }
+
+    #[test]
+    fn separated_shapes_do_not_collide() {
+        let a = Polygon::new_square(Vector2f::zero(), 40.0, 0.0);
+        let b = Circle::new(Vector2f::new(40.0, 0.0), 15.0, 0.0);
+        assert!(gjk_epa(&a, &b).is_none());
+        assert!(polygon_vs_circle(&a, &b).is_none());
+    }
+}
@@ -0,0 +1,268 @@
+use graphics::math::Matrix2d;
+use graphics::Transformed;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+use crate::utils::real::Real;
+use crate::Vector2f;
+use crate::physics::shape::Renderable;
+use crate::GlGraphics;
+use crate::physics::shape::Shape;
+
+use super::chain::ChainSegment;
+use super::collision::{point_segment_distance, AABB};
+
+const PERLIN_GRADIENTS: usize = 256;
+
+// Static terrain made of evenly spaced columns. The heights go up from the base, which runs
+// horizontally through the origin, and everything between the base and the surface is solid.
+#[derive(Clone)]
+pub struct Heightfield {
+    pub heights: Vec<Real>,
+    pub spacing: Real,
+    // World position of the base under the first column
+    pub origin: Vector2f<Real>,
+}
+
+impl Renderable for Heightfield {
+    fn draw(&self, transform: Matrix2d, gl: &mut GlGraphics, color: [f32; 4]) {
+        let transform = transform.trans_pos(self.origin.cast::<f64>());
+        for column in self.get_local_columns() {
+            let points: Vec<[f64; 2]> = column.iter().map(|&v| v.cast().into()).collect();
+            graphics::polygon(color, &points, transform, gl);
+        }
+    }
+}
+
+impl Shape for Heightfield {
+    fn area(&self) -> Real {
+        self.heights.windows(2).map(|h| (h[0] + h[1]) / 2.0 * self.spacing).sum()
+    }
+
+    // Heightfields are always static
+    fn momemnt_of_inertia(&self) -> Real {
+        0.0
+    }
+
+    fn get_aabb(&self) -> AABB {
+        let max_height = self.heights.iter().fold(0.0, |max: Real, &h| max.max(h));
+        AABB {
+            top_left: self.origin - Vector2f::new(0.0, max_height),
+            bottom_right: self.origin + Vector2f::new(self.width(), 0.0),
+        }
+    }
+
+    fn contains_point(&self, point: Vector2f<Real>) -> bool {
+        self.surface_at(point.x).is_some_and(|surface| point.y >= surface && point.y <= self.origin.y)
+    }
+
+    fn find_closest_surface_point(&self, point: Vector2f<Real>) -> (Vector2f<Real>, Vector2f<Real>) {
+        // The closest point can not be further away than the surface straight above or below
+        let reach = self.surface_at(point.x).map_or(self.width(), |surface| (point.y - surface).abs()) + self.spacing;
+
+        let mut closest = (point, Vector2f::new(0.0, -1.0));
+        let mut distance = Real::INFINITY;
+        for (_, segment) in self.get_segments_in_range(point.x - reach, point.x + reach) {
+            let (dist, cp) = point_segment_distance(point, segment.a, segment.b);
+            if dist < distance {
+                closest = (cp, Heightfield::surface_normal(&segment));
+                distance = dist;
+            }
+        }
+
+        closest
+    }
+
+    fn support(&self, direction: Vector2f<Real>) -> Vector2f<Real> {
+        let base_end = self.origin + Vector2f::new(self.width(), 0.0);
+        (0..self.heights.len()).map(|i| self.get_point(i)).chain([self.origin, base_end])
+            .max_by(|a, b| a.dot(direction).total_cmp(&b.dot(direction)))
+            .unwrap()
+    }
+}
+
+impl Heightfield {
+    // The generators below are applied on top of a flat heightfield, and can be chained
+    pub fn flat(origin: Vector2f<Real>, spacing: Real, columns: usize, height: Real) -> Self {
+        Self {
+            heights: vec![height; columns],
+            spacing,
+            origin,
+        }
+    }
+
+    // Adds rolling hills from layered 1D perlin noise. Each octave has twice the frequency and
+    // half the amplitude of the one before it.
+    pub fn with_perlin(mut self, amplitude: Real, wavelength: Real, octaves: u32, seed: u64) -> Self {
+        let mut rng = StdRng::seed_from_u64(seed);
+        let gradients: Vec<Real> = (0..PERLIN_GRADIENTS).map(|_| rng.random_range(-1.0..=1.0)).collect();
+        let noise = |x: Real| {
+            let i = x.floor() as usize;
+            let t = x - x.floor();
+            let (g0, g1) = (gradients[i % PERLIN_GRADIENTS], gradients[(i + 1) % PERLIN_GRADIENTS]);
+            let fade = t * t * t * (t * (t * 6.0 - 15.0) + 10.0);
+            2.0 * (g0 * t + fade * (g1 * (t - 1.0) - g0 * t))
+        };
+
+        let total_weight: Real = (0..octaves).map(|octave| (0.5 as Real).powi(octave as i32)).sum();
+        for (i, height) in self.heights.iter_mut().enumerate() {
+            let x = i as Real * self.spacing / wavelength;
+            let sum: Real = (0..octaves).map(|octave| {
+                let frequency = (2.0 as Real).powi(octave as i32);
+                noise(x * frequency) / frequency
+            }).sum();
+            *height = (*height + amplitude * sum / total_weight).max(0.0);
+        }
+
+        self
+    }
+
+    // Adds jagged terrain made by repeatedly displacing the midpoint between known heights. The
+    // displacement is scaled by the roughness at every level, so lower values give smoother terrain.
+    #[allow(dead_code)]
+    pub fn with_midpoint_displacement(mut self, amplitude: Real, roughness: Real, seed: u64) -> Self {
+        let mut rng = StdRng::seed_from_u64(seed);
+        let mut size = 1;
+        while size + 1 < self.heights.len() {
+            size *= 2;
+        }
+
+        let mut offsets = vec![0.0; size + 1];
+        offsets[0] = rng.random_range(-1.0..=1.0) * amplitude;
+        offsets[size] = rng.random_range(-1.0..=1.0) * amplitude;
+
+        let mut step = size;
+        let mut displacement = amplitude;
+        while step > 1 {
+            let half = step / 2;
+            for i in (half..size).step_by(step) {
+                offsets[i] = (offsets[i - half] + offsets[i + half]) / 2.0 + rng.random_range(-1.0..=1.0) * displacement;
+            }
+            displacement *= roughness;
+            step = half;
+        }
+
+        for (height, offset) in self.heights.iter_mut().zip(offsets) {
+            *height = (*height + offset).max(0.0);
+        }
+
+        self
+    }
+
+    // Blasts bowl shaped craters of random size into the surface
+    #[allow(dead_code)]
+    pub fn with_craters(mut self, count: usize, min_radius: Real, max_radius: Real, seed: u64) -> Self {
+        let mut rng = StdRng::seed_from_u64(seed);
+        for _ in 0..count {
+            let x = self.origin.x + rng.random_range(0.0..=self.width());
+            let radius = rng.random_range(min_radius..=max_radius);
+            let surface = self.surface_at(x).unwrap_or(self.origin.y);
+            self.deform(Vector2f::new(x, surface - radius * 0.4), radius);
+        }
+
+        self
+    }
+
+    pub fn width(&self) -> Real {
+        self.heights.len().saturating_sub(1) as Real * self.spacing
+    }
+
+    // Returns the world position of the surface at the given column
+    pub fn get_point(&self, index: usize) -> Vector2f<Real> {
+        self.origin + Vector2f::new(index as Real * self.spacing, -self.heights[index])
+    }
+
+    // Returns the height of the surface in world space at x, if x is within the heightfield
+    pub fn surface_at(&self, x: Real) -> Option<Real> {
+        let local_x = x - self.origin.x;
+        if self.heights.len() < 2 || local_x < 0.0 || local_x > self.width() {
+            return None;
+        }
+
+        let i = ((local_x / self.spacing) as usize).min(self.heights.len() - 2);
+        let t = local_x / self.spacing - i as Real;
+        Some(self.origin.y - (self.heights[i] + (self.heights[i + 1] - self.heights[i]) * t))
+    }
+
+    pub fn get_segment(&self, index: usize) -> ChainSegment {
+        ChainSegment {
+            a: self.get_point(index),
+            b: self.get_point(index + 1),
+            prev: index.checked_sub(1).map(|i| self.get_point(i)),
+            next: if index + 2 < self.heights.len() { Some(self.get_point(index + 2)) } else { None },
+        }
+    }
+
+    // Returns the surface segments overlapping the horizontal range along with their column index.
+    // The columns are evenly spaced, so the range is found directly without searching.
+    pub fn get_segments_in_range(&self, min_x: Real, max_x: Real) -> Vec<(usize, ChainSegment)> {
+        if self.heights.len() < 2 || max_x < self.origin.x || min_x > self.origin.x + self.width() {
+            return vec![];
+        }
+
+        let last = self.heights.len() - 2;
+        let first = (((min_x - self.origin.x) / self.spacing).floor().max(0.0) as usize).min(last);
+        let end = (((max_x - self.origin.x) / self.spacing).floor().max(0.0) as usize).min(last);
+        (first..=end).map(|i| (i, self.get_segment(i))).collect()
+    }
+
+    // The surface faces up, the solid ground is below it
+    pub fn surface_normal(segment: &ChainSegment) -> Vector2f<Real> {
+        -(segment.b - segment.a).perpendicular().normalize()
+    }
+
+    // Returns the solid part under every segment in local space, skipping the columns with no height
+    // The area weighted centroid of the columns, relative to the origin
+    pub fn local_centroid(&self) -> Vector2f<Real> {
+        let mut moment = Vector2f::zero();
+        let mut area = 0.0;
+        for (i, h) in self.heights.windows(2).enumerate() {
+            let sum = h[0] + h[1];
+            if sum <= 0.0 {
+                continue;
+            }
+
+            // The centroid of the trapezoid between the base and the surface
+            let x = (i as Real + (h[0] + 2.0 * h[1]) / (3.0 * sum)) * self.spacing;
+            let y = -(h[0] * h[0] + h[0] * h[1] + h[1] * h[1]) / (3.0 * sum);
+            let column_area = sum / 2.0 * self.spacing;
+            moment += Vector2f::new(x, y) * column_area;
+            area += column_area;
+        }
+
+        if area > 0.0 { moment / area } else { Vector2f::new(self.width() / 2.0, 0.0) }
+    }
+
+    pub fn get_local_columns(&self) -> Vec<Vec<Vector2f<Real>>> {
+        self.heights.windows(2).enumerate().filter(|(_, h)| h[0] > 0.0 || h[1] > 0.0).map(|(i, h)| {
+            let (x0, x1) = (i as Real * self.spacing, (i + 1) as Real * self.spacing);
+            vec![
+                Vector2f::new(x0, -h[0]),
+                Vector2f::new(x1, -h[1]),
+                Vector2f::new(x1, 0.0),
+                Vector2f::new(x0, 0.0),
+            ]
+        }).collect()
+    }
+
+    // Carves a circle out of the terrain, everything above the lower edge of the circle is
+    // removed. Returns whether any of the heights changed.
+    pub fn deform(&mut self, center: Vector2f<Real>, radius: Real) -> bool {
+        let mut changed = false;
+        for i in 0..self.heights.len() {
+            let dx = self.origin.x + i as Real * self.spacing - center.x;
+            if dx.abs() >= radius {
+                continue;
+            }
+
+            let bottom = center.y + (radius * radius - dx * dx).sqrt();
+            let height = (self.origin.y - bottom).max(0.0);
+            if height < self.heights[i] {
+                self.heights[i] = height;
+                changed = true;
+            }
+        }
+
+        changed
+    }
+}
@@ -0,0 +1,327 @@
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+
+use crate::utils::real::Real;
+use super::arena::{BodyArena, BodyHandle};
+use super::collision::CollisionData;
+use super::material::MaterialTable;
+use super::rigid_body::{ContactBody, RigidBody};
+use super::shape::Shape;
+
+// The margin for bodies resting on each other, which only sink in by what gravity adds in a step
+const MIN_PAIR_MARGIN: Real = 4.0;
+
+// How far apart bodies can be at the start of a step and still be paired. The pairs are found
+// before any body is pushed out, so the pairs a push brings together must already be among them.
+// A push only undoes the overlap two bodies moved into during the last step, which is at most
+// how far they travelled towards each other, so the margin covers twice the furthest any point
+// of a body travels in a step. Bodies that were placed inside each other can be pushed further,
+// and may then reach a body they were not paired with a step late.
+pub fn pair_margin(bodies: &BodyArena, dt: Real) -> Real {
+    let max_travel = bodies.bodies().filter(|body| !body.is_static).map(|body| {
+        let aabb = body.shape.get_aabb();
+        let radius = (aabb.bottom_right - aabb.top_left).len() / 2.0;
+        (body.linear_velocity.len() + body.angular_velocity.abs() * radius) * dt
+    }).fold(0.0, Real::max);
+
+    MIN_PAIR_MARGIN + 2.0 * max_travel
+}
+
+// The pairs of bodies that can touch within the step, in the order of the handles. The pairs
+// are found on the thread pool with the parallel feature.
+pub fn find_pairs(bodies: &BodyArena, dt: Real) -> Vec<(BodyHandle, BodyHandle)> {
+    let handles = &bodies.handles();
+    let margin = pair_margin(bodies, dt);
+    let pairs_of = |i: usize| {
+        let a = bodies.get(handles[i]).unwrap();
+        handles[i + 1..].iter()
+            .filter(move |&&b| a.may_collide(bodies.get(b).unwrap(), dt, margin))
+            .map(move |&b| (handles[i], b))
+    };
+
+    #[cfg(feature = "parallel")]
+    {
+        use rayon::prelude::*;
+        (0..handles.len()).into_par_iter().flat_map_iter(pairs_of).collect()
+    }
+    #[cfg(not(feature = "parallel"))]
+    {
+        (0..handles.len()).flat_map(pairs_of).collect()
+    }
+}
+
+// Runs the task on every island, on the thread pool with the parallel feature. The islands
+// share no dynamic bodies, so the results are the same however the tasks are scheduled.
+pub fn for_each_island<T: Send>(islands: &mut [Island], task: impl Fn(&mut Island) -> T + Sync + Send) -> Vec<T> {
+    #[cfg(feature = "parallel")]
+    {
+        use rayon::prelude::*;
+        islands.par_iter_mut().map(task).collect()
+    }
+    #[cfg(not(feature = "parallel"))]
+    {
+        islands.iter_mut().map(task).collect()
+    }
+}
+
+#[derive(Clone, Copy)]
+enum Member<'a> {
+    // Index into the bodies of the island
+    Dynamic(usize),
+    Static(&'a RigidBody),
+}
+
+// Dynamic bodies connected by the pairs between them. Static bodies do not join islands
+// together, since they are only read while solving.
+pub struct Island<'a> {
+    bodies: Vec<&'a mut RigidBody>,
+    pairs: Vec<(Member<'a>, Member<'a>)>,
+    // The index of every pair in the pairs of the whole world
+    pair_indices: Vec<usize>,
+    // The colliding pairs, as indices into the pairs
+    pub collisions: Vec<(usize, CollisionData)>,
+    // The order to solve the collisions in during the current iteration
+    order: Vec<usize>,
+}
+
+impl<'a> Island<'a> {
+    // Splits the bodies of the pairs into islands, ordered by their first pair. The pairs keep
+    // their order within each island.
+    pub fn build(bodies: &'a mut BodyArena, pairs: &[(BodyHandle, BodyHandle)]) -> Vec<Island<'a>> {
+        // Union find over the dynamic bodies of the pairs
+        let mut ids: HashMap<BodyHandle, usize> = HashMap::new();
+        let mut parent: Vec<usize> = vec![];
+        let mut id_of = |handle: BodyHandle, parent: &mut Vec<usize>| {
+            if bodies.get(handle).unwrap().is_static {
+                return None;
+            }
+            Some(*ids.entry(handle).or_insert_with(|| {
+                parent.push(parent.len());
+                parent.len() - 1
+            }))
+        };
+        let pair_ids: Vec<_> = pairs.iter().map(|&(a, b)| (id_of(a, &mut parent), id_of(b, &mut parent))).collect();
+
+        fn root(parent: &mut [usize], mut id: usize) -> usize {
+            while parent[id] != id {
+                parent[id] = parent[parent[id]];
+                id = parent[id];
+            }
+            id
+        }
+
+        for &(a, b) in pair_ids.iter() {
+            if let (Some(a), Some(b)) = (a, b) {
+                let (ra, rb) = (root(&mut parent, a), root(&mut parent, b));
+                parent[ra.max(rb)] = ra.min(rb);
+            }
+        }
+
+        let mut island_of_root: HashMap<usize, usize> = HashMap::new();
+        let pair_islands: Vec<usize> = pair_ids.iter().map(|&(a, b)| {
+            let root = root(&mut parent, a.or(b).unwrap());
+            let next = island_of_root.len();
+            *island_of_root.entry(root).or_insert(next)
+        }).collect();
+
+        let mut islands: Vec<Island> = (0..island_of_root.len()).map(|_| Island {
+            bodies: vec![],
+            pairs: vec![],
+            pair_indices: vec![],
+            collisions: vec![],
+            order: vec![],
+        }).collect();
+
+        // Hand out the bodies, the dynamic ones to their island and the static ones to all
+        let mut members: HashMap<BodyHandle, Member<'a>> = HashMap::new();
+        for (handle, body) in bodies.iter_mut() {
+            if body.is_static {
+                members.insert(handle, Member::Static(body));
+            } else if let Some(&id) = ids.get(&handle) {
+                let island = &mut islands[island_of_root[&root(&mut parent, id)]];
+                members.insert(handle, Member::Dynamic(island.bodies.len()));
+                island.bodies.push(body);
+            }
+        }
+
+        for (i, (&(a, b), &island)) in pairs.iter().zip(pair_islands.iter()).enumerate() {
+            islands[island].pairs.push((members[&a], members[&b]));
+            islands[island].pair_indices.push(i);
+        }
+        islands
+    }
+
+    fn pair_mut(&mut self, pair: usize) -> (ContactBody<'_>, ContactBody<'_>) {
+        match self.pairs[pair] {
+            (Member::Dynamic(i), Member::Dynamic(j)) => {
+                let (first, second) = (i.min(j), i.max(j));
+                let (left, right) = self.bodies.split_at_mut(second);
+                let (a, b) = (&mut *left[first], &mut *right[0]);
+                if i < j { (ContactBody::Dynamic(a), ContactBody::Dynamic(b)) } else { (ContactBody::Dynamic(b), ContactBody::Dynamic(a)) }
+            }
+            (Member::Dynamic(i), Member::Static(b)) => (ContactBody::Dynamic(&mut *self.bodies[i]), ContactBody::Static(b)),
+            (Member::Static(a), Member::Dynamic(j)) => (ContactBody::Static(a), ContactBody::Dynamic(&mut *self.bodies[j])),
+            (Member::Static(_), Member::Static(_)) => unreachable!("static bodies are never paired"),
+        }
+    }
+
+    // Tests every pair for a collision, pushing the bodies apart and keeping the contacts
+    pub fn detect_collisions(&mut self, dt: Real) {
+        self.collisions.clear();
+        for pair in 0..self.pairs.len() {
+            let (mut a, mut b) = self.pair_mut(pair);
+            if let Some(collision) = a.collide_with(&mut b, dt) {
+                self.collisions.push((pair, collision));
+            }
+        }
+    }
+
+    // Solves the collisions once, in the order handed out by solve_islands
+    fn solve(&mut self, materials: &MaterialTable) {
+        let collisions = std::mem::take(&mut self.collisions);
+        for &i in std::mem::take(&mut self.order).iter() {
+            let (pair, collision) = &collisions[i];
+            let (mut a, mut b) = self.pair_mut(*pair);
+            a.resolve_collision(&mut b, collision, materials);
+        }
+        self.collisions = collisions;
+    }
+}
+
+// The collisions of all the islands as (island, collision) indices, in the order of their pairs
+pub fn collision_order(islands: &[Island]) -> Vec<(usize, usize)> {
+    let mut order: Vec<_> = islands.iter().enumerate()
+        .flat_map(|(i, island)| (0..island.collisions.len()).map(move |c| (i, c)))
+        .collect();
+    order.sort_by_key(|&(i, c)| islands[i].pair_indices[islands[i].collisions[c].0]);
+    order
+}
+
+// Shuffles the collisions of all the islands together before every iteration, then lets each
+// island solve its own collisions in the shuffled order. The islands share no dynamic bodies, so 
+// this gives the same results as solving all the collisions one after another on one thread. 
+// Returns the time spent solving each island.
+pub fn solve_islands(islands: &mut [Island], iterations: usize, rng: &mut StdRng, materials: &MaterialTable) -> Vec<Duration> {
+    let mut order = collision_order(islands);
+    let mut work = vec![Duration::ZERO; islands.len()];
+    for _ in 0..iterations {
+        order.shuffle(rng);
+        for island in islands.iter_mut() {
+            island.order.clear();
+        }
+        for &(i, c) in order.as_slice() {
+            islands[i].order.push(c);
+        }
+
+        let times = for_each_island(islands, |island| {
+            let start = Instant::now();
+            island.solve(materials);
+            start.elapsed()
+        });
+        for (total, time) in work.iter_mut().zip(times) {
+            *total += time;
+        }
+    }
+    work
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::physics::material::WOOD;
+    use crate::physics::polygon::Polygon;
+    use crate::physics::shape_type::ShapeType;
+    use crate::Vector2f;
+
+    fn square(x: Real, is_static: bool) -> RigidBody {
+        RigidBody::new(ShapeType::Polygon(Polygon::new_square(Vector2f::new(x, 0.0), 40.0, 0.0)), WOOD, is_static)
+    }
+
+    #[test]
+    fn overlapping_static_bodies_are_never_paired() {
+        let mut bodies = BodyArena::new();
+        let ground = bodies.insert(square(0.0, true));
+        let wall = bodies.insert(square(10.0, true));
+        let block = bodies.insert(square(5.0, false));
+
+        assert_eq!(find_pairs(&bodies, 1.0 / 120.0), vec![(ground, block), (wall, block)]);
+    }
+
+    #[test]
+    fn islands_share_no_dynamic_bodies() {
+        let mut bodies = BodyArena::new();
+        let ground = bodies.insert(RigidBody::new(ShapeType::Polygon(Polygon::new_rectangle(Vector2f::zero(), 400.0, 20.0, 0.0)), WOOD, true));
+        let left = [bodies.insert(square(-100.0, false)), bodies.insert(square(-70.0, false))];
+        let right = bodies.insert(square(100.0, false));
+
+        let pairs = find_pairs(&bodies, 1.0 / 120.0);
+        assert_eq!(pairs, vec![(ground, left[0]), (ground, left[1]), (ground, right), (left[0], left[1])]);
+        let islands = Island::build(&mut bodies, &pairs);
+        assert_eq!(islands.iter().map(|island| island.bodies.len()).collect::<Vec<_>>(), vec![2, 1]);
+        assert_eq!(islands.iter().map(|island| island.pair_indices.clone()).collect::<Vec<_>>(), vec![vec![0, 1, 3], vec![2]]);
+    }
+
+    // Every pair of bodies that are not both static, like the search before islands had
+    fn all_pairs(bodies: &BodyArena) -> Vec<(BodyHandle, BodyHandle)> {
+        let handles = bodies.handles();
+        let is_static = |handle: BodyHandle| bodies.get(handle).unwrap().is_static;
+        handles.iter().enumerate()
+            .flat_map(|(i, &a)| handles[i + 1..].iter().map(move |&b| (a, b)))
+            .filter(|&(a, b)| !is_static(a) || !is_static(b))
+            .collect()
+    }
+
+    // Detects the collisions of the pairs and returns the pairs that collided, in pair order
+    fn colliding_pairs(mut bodies: BodyArena, pairs: &[(BodyHandle, BodyHandle)], dt: Real) -> Vec<(BodyHandle, BodyHandle)> {
+        let mut islands = Island::build(&mut bodies, pairs);
+        for island in islands.iter_mut() {
+            island.detect_collisions(dt);
+        }
+        collision_order(&islands).iter().map(|&(i, c)| pairs[islands[i].pair_indices[islands[i].collisions[c].0]]).collect()
+    }
+
+    #[test]
+    fn pairs_match_a_serial_search() {
+        // With the parallel feature the pairs are found on the thread pool, they have to come
+        // out the same as when every pair is tested one after another
+        let dt = 1.0 / 120.0;
+        let mut bodies = BodyArena::new();
+        bodies.insert(RigidBody::new(ShapeType::Polygon(Polygon::new_rectangle(Vector2f::new(0.0, 300.0), 1000.0, 20.0, 0.0)), WOOD, true));
+        for i in 0..60 {
+            let (x, y) = ((i % 10) as Real * 43.0, (i / 10) as Real * 47.0);
+            let mut body = RigidBody::new(ShapeType::Polygon(Polygon::new_square(Vector2f::new(x, y), 40.0, i as Real * 0.1)), WOOD, false);
+            body.linear_velocity = Vector2f::new((i % 7) as Real * 40.0 - 120.0, (i % 5) as Real * 50.0);
+            body.angular_velocity = (i % 3) as Real - 1.0;
+            bodies.insert(body);
+        }
+
+        let margin = pair_margin(&bodies, dt);
+        let serial: Vec<_> = all_pairs(&bodies).into_iter()
+            .filter(|&(a, b)| bodies.get(a).unwrap().may_collide(bodies.get(b).unwrap(), dt, margin))
+            .collect();
+        assert!(serial.len() > 60);
+        assert_eq!(find_pairs(&bodies, dt), serial);
+    }
+
+    #[test]
+    fn pushes_only_reach_paired_bodies() {
+        // The top block sank 10 into the middle one during the last step, pushing them apart moves
+        // the middle block 5 down onto the bottom one, which is further away than the smallest margin
+        let dt = 1.0 / 120.0;
+        let block = |y: Real| RigidBody::new(ShapeType::Polygon(Polygon::new_square(Vector2f::new(0.0, y), 40.0, 0.0)), WOOD, false);
+        let mut bodies = BodyArena::new();
+        let mut top = block(-30.0);
+        top.linear_velocity = Vector2f::new(0.0, 1200.0);
+        bodies.insert(top);
+        let middle = bodies.insert(block(0.0));
+        let bottom = bodies.insert(block(44.5));
+        assert!(!bodies.get(middle).unwrap().may_collide(bodies.get(bottom).unwrap(), dt, MIN_PAIR_MARGIN));
+
+        let expected = colliding_pairs(bodies.clone(), &all_pairs(&bodies), dt);
+        assert!(expected.contains(&(middle, bottom)));
+        assert_eq!(colliding_pairs(bodies.clone(), &find_pairs(&bodies, dt), dt), expected);
+    }
+}
@@ -1,120 +1,283 @@
+use crate::utils::real::Real;
+use std::collections::{HashMap, HashSet};
+use std::sync::{Mutex, OnceLock};
+
+use serde::Deserialize;
+
 #[derive(Clone, Copy)]
 pub struct Material {
     pub name: MaterialName,
-    pub density:f64, // g/cm³
-    pub restitution: f64,
-    pub static_friction: f64,
-    pub dynamic_friction: f64,
+    pub density:Real, // g/cm³
+    pub restitution: Real,
+    pub static_friction: Real,
+    pub dynamic_friction: Real,
+    pub restitution_combine: CombineMode,
+    pub friction_combine: CombineMode,
+    // Contact impulse per unit of area the material can take before it fractures
+    pub toughness: Real,
+}
+
+// How the coefficients of two materials in contact are combined.
+// When the two materials disagree, the mode declared last wins.
+#[allow(dead_code)]
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Deserialize)]
+pub enum CombineMode {
+    Average,
+    Min,
+    Multiply,
+    GeometricMean,
+    Max,
+}
+
+impl CombineMode {
+    pub fn combine(&self, a: Real, b: Real) -> Real {
+        match self {
+            CombineMode::Average => (a + b) / 2.0,
+            CombineMode::Min => a.min(b),
+            CombineMode::Multiply => a * b,
+            CombineMode::GeometricMean => (a * b).sqrt(),
+            CombineMode::Max => a.max(b),
+        }
+    }
+}
+
+// The coefficients used when resolving a contact between two materials
+#[derive(Clone, Copy)]
+pub struct MaterialPair {
+    pub restitution: Real,
+    pub static_friction: Real,
+    pub dynamic_friction: Real,
+}
+
+// The coefficients of a material on its own
+impl From<&Material> for MaterialPair {
+    fn from(material: &Material) -> Self {
+        MaterialPair {
+            restitution: material.restitution,
+            static_friction: material.static_friction,
+            dynamic_friction: material.dynamic_friction,
+        }
+    }
+}
+
+// Combines materials in contact, with explicit overrides for specific pairs
+#[derive(Clone, Default)]
+pub struct MaterialTable {
+    overrides: HashMap<(MaterialName, MaterialName), MaterialPair>,
 }
 
 #[allow(dead_code)]
-#[derive(Clone, Copy, PartialEq, Eq, Hash)]
-pub enum MaterialName {
-    Rubber,
-    Plastic,
-    Concrete,
-    Steel,
-    Ice,
-    Glass,
-    Wood,
-    Copper,
-    Aluminium,
-    Dirt,
-    HumanBody,
+impl MaterialTable {
+    // The table with the overrides the built-in materials ship with
+    pub fn builtin() -> Self {
+        let mut table = Self::default();
+        for (a, b, pair) in BUILTIN_PAIRS {
+            table.insert(a, b, pair);
+        }
+        table
+    }
+
+    pub fn insert(&mut self, a: MaterialName, b: MaterialName, pair: MaterialPair) {
+        self.overrides.insert(Self::key(a, b), pair);
+    }
+
+    pub fn remove(&mut self, a: MaterialName, b: MaterialName) -> Option<MaterialPair> {
+        self.overrides.remove(&Self::key(a, b))
+    }
+
+    pub fn combine(&self, a: &Material, b: &Material) -> MaterialPair {
+        if let Some(pair) = self.overrides.get(&Self::key(a.name, b.name)) {
+            return *pair;
+        }
+
+        let restitution_mode = a.restitution_combine.max(b.restitution_combine);
+        let friction_mode = a.friction_combine.max(b.friction_combine);
+        MaterialPair {
+            restitution: restitution_mode.combine(a.restitution, b.restitution),
+            static_friction: friction_mode.combine(a.static_friction, b.static_friction),
+            dynamic_friction: friction_mode.combine(a.dynamic_friction, b.dynamic_friction),
+        }
+    }
+
+    // Pairs are unordered, so the key is always stored with the smallest name first
+    fn key(a: MaterialName, b: MaterialName) -> (MaterialName, MaterialName) {
+        if a <= b { (a, b) } else { (b, a) }
+    }
+}
+
+// Interned material name, user defined names are leaked once so the name stays cheap to copy
+#[derive(Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Debug)]
+pub struct MaterialName(&'static str);
+
+#[allow(dead_code)]
+impl MaterialName {
+    pub const RUBBER: Self = Self("rubber");
+    pub const PLASTIC: Self = Self("plastic");
+    pub const CONCRETE: Self = Self("concrete");
+    pub const STEEL: Self = Self("steel");
+    pub const ICE: Self = Self("ice");
+    pub const GLASS: Self = Self("glass");
+    pub const WOOD: Self = Self("wood");
+    pub const COPPER: Self = Self("copper");
+    pub const ALUMINIUM: Self = Self("aluminium");
+    pub const DIRT: Self = Self("dirt");
+    pub const HUMAN_BODY: Self = Self("human_body");
+
+    pub fn new(name: &str) -> Self {
+        static NAMES: OnceLock<Mutex<HashSet<&'static str>>> = OnceLock::new();
+        let mut names = NAMES.get_or_init(|| Mutex::new(HashSet::new())).lock().unwrap();
+        if let Some(interned) = names.get(name) {
+            return Self(interned);
+        }
+
+        let interned: &'static str = Box::leak(name.to_string().into_boxed_str());
+        names.insert(interned);
+        Self(interned)
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        self.0
+    }
 }
 
 #[allow(dead_code)]
 pub const RUBBER: Material = Material {
-    name: MaterialName::Rubber,
+    name: MaterialName::RUBBER,
     density: 1.1,
     restitution: 0.85,
     static_friction: 0.5,
     dynamic_friction: 0.4,
+    restitution_combine: CombineMode::Max,
+    friction_combine: CombineMode::Average,
+    toughness: Real::INFINITY,
 };
 
 #[allow(dead_code)]
 pub const PLASTIC: Material = Material {
-    name: MaterialName::Plastic,
+    name: MaterialName::PLASTIC,
     density: 1.175,
     restitution: 0.7,
     static_friction: 0.35,
     dynamic_friction: 0.25,
+    restitution_combine: CombineMode::Min,
+    friction_combine: CombineMode::Average,
+    toughness: 1200.0,
 };
 
 #[allow(dead_code)]
 pub const GLASS: Material = Material {
-    name: MaterialName::Glass,
+    name: MaterialName::GLASS,
     density: 2.5,
     restitution: 0.6,
     static_friction: 0.9,
     dynamic_friction: 0.4,
+    restitution_combine: CombineMode::Min,
+    friction_combine: CombineMode::Average,
+    toughness: 1000.0,
 };
 
 pub const WOOD: Material = Material {
-    name: MaterialName::Wood,
+    name: MaterialName::WOOD,
     density: 0.7,
     restitution: 0.5,
     static_friction: 0.5,
     dynamic_friction: 0.4,
+    restitution_combine: CombineMode::Min,
+    friction_combine: CombineMode::Average,
+    toughness: 650.0,
 };
 
 pub const CONCRETE: Material = Material {
-    name: MaterialName::Concrete,
+    name: MaterialName::CONCRETE,
     density: 2.4,
     restitution: 0.4,
     static_friction: 0.95,
     dynamic_friction: 0.85,
+    restitution_combine: CombineMode::Min,
+    friction_combine: CombineMode::Average,
+    toughness: 1900.0,
 };
 
 pub const STEEL: Material = Material {
-    name: MaterialName::Steel,
+    name: MaterialName::STEEL,
     density: 7.85,
     restitution: 0.3,
     static_friction: 0.6,
     dynamic_friction: 0.45,
+    restitution_combine: CombineMode::Min,
+    friction_combine: CombineMode::Average,
+    toughness: Real::INFINITY,
 };
 
 #[allow(dead_code)]
 pub const COPPER: Material = Material {
-    name: MaterialName::Copper,
+    name: MaterialName::COPPER,
     density: 8.94,
     restitution: 0.3,
     static_friction: 0.53,
     dynamic_friction: 0.4,
+    restitution_combine: CombineMode::Min,
+    friction_combine: CombineMode::Average,
+    toughness: Real::INFINITY,
 };
 
 #[allow(dead_code)]
 pub const ALUMINIUM: Material = Material {
-    name: MaterialName::Aluminium,
+    name: MaterialName::ALUMINIUM,
     density: 2.7,
     restitution: 0.4,
     static_friction: 0.6,
     dynamic_friction: 0.47,
+    restitution_combine: CombineMode::Min,
+    friction_combine: CombineMode::Average,
+    toughness: Real::INFINITY,
 };
 
 #[allow(dead_code)]
 pub const DIRT: Material = Material {
-    name: MaterialName::Dirt,
+    name: MaterialName::DIRT,
     density: 1.6,
     restitution: 0.1,
     static_friction: 0.55,
     dynamic_friction: 0.45,
+    restitution_combine: CombineMode::Min,
+    friction_combine: CombineMode::Average,
+    toughness: 500.0,
 };
 
 #[allow(dead_code)]
 pub const HUMAN_BODY: Material = Material {
-    name: MaterialName::HumanBody,
+    name: MaterialName::HUMAN_BODY,
     density: 0.985,
     restitution: 0.3,
     static_friction: 0.6,
     dynamic_friction: 0.42,
+    restitution_combine: CombineMode::Min,
+    friction_combine: CombineMode::Average,
+    toughness: Real::INFINITY,
 };
 
 pub const ICE: Material = Material {
-    name: MaterialName::Ice,
+    name: MaterialName::ICE,
     density: 0.917,
     restitution: 0.7,
     static_friction: 0.15,
     dynamic_friction: 0.05,
+    restitution_combine: CombineMode::Min,
+    friction_combine: CombineMode::Average,
+    toughness: 450.0,
 };
 
+#[allow(dead_code)]
+pub const BUILTIN_MATERIALS: [Material; 11] = [
+    CONCRETE, ICE, WOOD, STEEL, RUBBER, PLASTIC, GLASS, COPPER, ALUMINIUM, DIRT, HUMAN_BODY,
+];
+
+// Steel skates glide on ice with far less friction than either material suggests
+pub const BUILTIN_PAIRS: [(MaterialName, MaterialName, MaterialPair); 1] = [
+    (MaterialName::ICE, MaterialName::STEEL, MaterialPair {
+        restitution: 0.3,
+        static_friction: 0.1,
+        dynamic_friction: 0.02,
+    }),
+];
@@ -0,0 +1,208 @@
+use std::fmt::Display;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use serde::Deserialize;
+
+use crate::utils::real::Real;
+use super::material::*;
+
+pub const MATERIAL_LIBRARY_PATH: &str = "./src/assets/materials.ron";
+const DEFAULT_TINT: [f32; 4] = [0.6, 0.6, 0.6, 1.0];
+
+// A material as described in the library file, along with how it is presented
+#[derive(Clone, Deserialize)]
+pub struct MaterialDefinition {
+    pub name: String,
+    pub density: Real, // g/cm³
+    pub restitution: Real,
+    pub static_friction: Real,
+    pub dynamic_friction: Real,
+    #[serde(default = "default_restitution_combine")]
+    pub restitution_combine: CombineMode,
+    #[serde(default = "default_friction_combine")]
+    pub friction_combine: CombineMode,
+    // Materials without a toughness never fracture
+    #[serde(default)]
+    pub toughness: Option<Real>,
+    #[serde(default)]
+    pub texture: Option<PathBuf>,
+    // Flat color used when the texture is missing or fails to load
+    #[serde(default = "default_tint")]
+    pub tint: [f32; 4],
+    #[serde(default)]
+    pub sound: Option<PathBuf>,
+}
+
+impl MaterialDefinition {
+    pub fn material(&self) -> Material {
+        Material {
+            name: MaterialName::new(&self.name),
+            density: self.density,
+            restitution: self.restitution,
+            static_friction: self.static_friction,
+            dynamic_friction: self.dynamic_friction,
+            restitution_combine: self.restitution_combine,
+            friction_combine: self.friction_combine,
+            toughness: self.toughness.unwrap_or(Real::INFINITY),
+        }
+    }
+}
+
+impl From<Material> for MaterialDefinition {
+    fn from(value: Material) -> Self {
+        Self {
+            name: value.name.as_str().to_string(),
+            density: value.density,
+            restitution: value.restitution,
+            static_friction: value.static_friction,
+            dynamic_friction: value.dynamic_friction,
+            restitution_combine: value.restitution_combine,
+            friction_combine: value.friction_combine,
+            toughness: if value.toughness.is_finite() { Some(value.toughness) } else { None },
+            texture: None,
+            tint: DEFAULT_TINT,
+            sound: None,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct PairDefinition {
+    a: String,
+    b: String,
+    restitution: Real,
+    static_friction: Real,
+    dynamic_friction: Real,
+}
+
+#[derive(Deserialize)]
+struct LibraryFile {
+    materials: Vec<MaterialDefinition>,
+    #[serde(default)]
+    pairs: Vec<PairDefinition>,
+}
+
+#[derive(Debug)]
+pub enum MaterialLibraryError {
+    Io(std::io::Error),
+    Parse(ron::error::SpannedError),
+}
+
+impl Display for MaterialLibraryError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MaterialLibraryError::Io(e) => write!(f, "could not read material library: {}", e),
+            MaterialLibraryError::Parse(e) => write!(f, "could not parse material library: {}", e),
+        }
+    }
+}
+
+// The set of materials available in the game, in the order they were defined
+#[derive(Clone)]
+pub struct MaterialLibrary {
+    definitions: Vec<MaterialDefinition>,
+    pub pairs: MaterialTable,
+}
+
+impl Default for MaterialLibrary {
+    fn default() -> Self {
+        Self {
+            definitions: BUILTIN_MATERIALS.iter().map(|&m| MaterialDefinition::from(m)).collect(),
+            pairs: MaterialTable::builtin(),
+        }
+    }
+}
+
+#[allow(dead_code)]
+impl MaterialLibrary {
+    // Loads the library from a RON file, texture and sound paths are relative to the file
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, MaterialLibraryError> {
+        let path = path.as_ref();
+        let content = fs::read_to_string(path).map_err(MaterialLibraryError::Io)?;
+        let file: LibraryFile = ron::from_str(&content).map_err(MaterialLibraryError::Parse)?;
+
+        let dir = path.parent().unwrap_or(Path::new("."));
+        let definitions = file.materials.into_iter().map(|mut def| {
+            def.texture = def.texture.map(|p| dir.join(p));
+            def.sound = def.sound.map(|p| dir.join(p));
+            def
+        }).collect();
+
+        let mut pairs = MaterialTable::default();
+        for pair in file.pairs {
+            pairs.insert(MaterialName::new(&pair.a), MaterialName::new(&pair.b), MaterialPair {
+                restitution: pair.restitution,
+                static_friction: pair.static_friction,
+                dynamic_friction: pair.dynamic_friction,
+            });
+        }
+
+        Ok(Self { definitions, pairs })
+    }
+
+    pub fn definitions(&self) -> &[MaterialDefinition] {
+        &self.definitions
+    }
+
+    pub fn get_definition(&self, name: MaterialName) -> Option<&MaterialDefinition> {
+        self.definitions.iter().find(|def| def.name == name.as_str())
+    }
+
+    pub fn get(&self, name: MaterialName) -> Option<Material> {
+        self.get_definition(name).map(|def| def.material())
+    }
+
+    // Returns the library version of the material, or the material itself if it is not in the library
+    pub fn resolve(&self, material: Material) -> Material {
+        self.get(material.name).unwrap_or(material)
+    }
+}
+
+fn default_restitution_combine() -> CombineMode {
+    CombineMode::Min
+}
+
+fn default_friction_combine() -> CombineMode {
+    CombineMode::Average
+}
+
+fn default_tint() -> [f32; 4] {
+    DEFAULT_TINT
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn builtin_materials_match_the_library_file() {
+        let file = MaterialLibrary::load(MATERIAL_LIBRARY_PATH).unwrap();
+        let builtin = MaterialLibrary::default();
+        assert_eq!(file.definitions().len(), builtin.definitions().len());
+
+        for def in file.definitions() {
+            let a = def.material();
+            let b = builtin.get(a.name).unwrap_or_else(|| panic!("{} is not built in", def.name));
+            assert_eq!(a.density, b.density, "{}", def.name);
+            assert_eq!(a.restitution, b.restitution, "{}", def.name);
+            assert_eq!(a.static_friction, b.static_friction, "{}", def.name);
+            assert_eq!(a.dynamic_friction, b.dynamic_friction, "{}", def.name);
+            assert!(a.restitution_combine == b.restitution_combine, "{}", def.name);
+            assert!(a.friction_combine == b.friction_combine, "{}", def.name);
+            assert_eq!(a.toughness, b.toughness, "{}", def.name);
+        }
+
+        // Every combination has to resolve the same, which covers the pair overrides
+        for a in file.definitions().iter().map(|def| def.material()) {
+            for b in file.definitions().iter().map(|def| def.material()) {
+                let from_file = file.pairs.combine(&a, &b);
+                let from_builtin = builtin.pairs.combine(&a, &b);
+                let names = (a.name.as_str(), b.name.as_str());
+                assert_eq!(from_file.restitution, from_builtin.restitution, "{:?}", names);
+                assert_eq!(from_file.static_friction, from_builtin.static_friction, "{:?}", names);
+                assert_eq!(from_file.dynamic_friction, from_builtin.dynamic_friction, "{:?}", names);
+            }
+        }
+    }
+}
@@ -1,5 +1,6 @@
-use core::f64;
-use std::f64::consts::PI;
+use crate::utils::real::consts::PI;
+use crate::utils::real::Real;
+use crate::utils::transform::Transform2;
 use graphics::math::Matrix2d;
 
 use crate::Vector2f;
@@ -8,39 +9,49 @@ use crate::GlGraphics;
 use crate::physics::shape::Shape;
 
 use super::collision::AABB;
+use super::decomposition::*;
 
 #[derive(Clone)]
 pub struct Polygon {
-    pub local_vertices: Vec<Vector2f<f64>>,
-    pub center: Vector2f<f64>,
-    pub rotation: f64,
-    area: f64,
-    intertia: f64,
+    pub local_vertices: Vec<Vector2f<Real>>,
+    // Only changed through the setters, which keep the world space vertices up to date
+    center: Vector2f<Real>,
+    rotation: Real,
+    area: Real,
+    intertia: Real,
+    // Convex parts making up the polygon, in local space
+    parts: Vec<Vec<Vector2f<Real>>>,
+    // The vertices, parts and edge normals of the parts in world space. They are rebuilt in 
+    // place every time the polygon moves, so the collision tests can borrow them without 
+    // transforming or allocating anything.
+    world_vertices: Vec<Vector2f<Real>>,
+    world_parts: Vec<Vec<Vector2f<Real>>>,
+    world_normals: Vec<Vec<Vector2f<Real>>>,
 }
 
 impl Renderable for Polygon {
     fn draw(&self, transform: Matrix2d, gl: &mut GlGraphics, color: [f32; 4]) {
-        let verts: Vec<[f64; 2]> = self.get_transformed_vertices().iter().map(|&v| v.into()).collect();
+        let verts: Vec<[f64; 2]> = self.world_vertices.iter().map(|&v| v.cast().into()).collect();
 
         graphics::polygon(color, &verts, transform, gl);
     }
 }
 
 impl Shape for Polygon {
-    fn area(&self) -> f64 {
+    fn area(&self) -> Real {
         return self.area;
     }
 
-    fn momemnt_of_inertia(&self) -> f64 {
+    fn momemnt_of_inertia(&self) -> Real {
         return self.intertia;
     }
 
     fn get_aabb(&self) -> AABB {
-        let mut min_x = f64::INFINITY;
-        let mut max_x = f64::NEG_INFINITY;
-        let mut min_y = f64::INFINITY;
-        let mut max_y = f64::NEG_INFINITY; 
-        for v in self.get_transformed_vertices() {
+        let mut min_x = Real::INFINITY;
+        let mut max_x = Real::NEG_INFINITY;
+        let mut min_y = Real::INFINITY;
+        let mut max_y = Real::NEG_INFINITY; 
+        for v in self.world_vertices.iter() {
             min_x = min_x.min(v.x);
             max_x = max_x.max(v.x);
             min_y = min_y.min(v.y);
@@ -50,33 +61,15 @@ impl Shape for Polygon {
         return AABB { top_left: Vector2f::new(min_x, min_y), bottom_right: Vector2f::new(max_x, max_y) };
     }
 
-    fn contains_point(&self, point: Vector2f<f64>) -> bool {
-        let mut pos = 0;
-        let mut neg = 0;
-
-        let verts = self.get_transformed_vertices();
-        for i in 0..verts.len() {
-            let v1 = verts[i];
-            let v2 = verts[(i + 1) % verts.len()];
-
-            let d = (point.x - v1.x) * (v2.y - v1.y) - (point.y - v1.y) * (v2.x - v1.x);
-
-            if d > 0.0 { pos += 1; }
-            if d < 0.0 { neg += 1; }
-
-            if pos > 0 && neg > 0 {
-                return false;
-            }
-        }
-
-        return true;
+    fn contains_point(&self, point: Vector2f<Real>) -> bool {
+        self.world_parts.iter().any(|part| convex_contains_point(part, point))
     }
 
-    fn find_closest_surface_point(&self, point: Vector2f<f64>) -> (Vector2f<f64>, Vector2f<f64>) {
-        let verts = self.get_transformed_vertices();
+    fn find_closest_surface_point(&self, point: Vector2f<Real>) -> (Vector2f<Real>, Vector2f<Real>) {
+        let verts = self.world_vertices.as_slice();
         let mut edge = Vector2f::zero();
         let mut closest_point = Vector2f::zero();
-        let mut distance = f64::INFINITY;
+        let mut distance = Real::INFINITY;
         for i in 0..verts.len() {
             let a = verts[i];
             let b = verts[(i + 1) % verts.len()];
@@ -91,10 +84,20 @@ impl Shape for Polygon {
 
         return (closest_point, edge.perpendicular().normalize());
     }
+
+    // Concave polygons are seen as their convex hull
+    fn support(&self, direction: Vector2f<Real>) -> Vector2f<Real> {
+        let transform = self.get_transform();
+        let local_dir = transform.rotation.apply_inv(direction);
+        let farthest = self.local_vertices.iter()
+            .max_by(|a, b| a.dot(local_dir).total_cmp(&b.dot(local_dir)))
+            .unwrap();
+        transform.apply(*farthest)
+    }
 }
 
 impl Polygon {
-    pub fn new_rectangle(center: Vector2f<f64>, width: f64, height: f64, rotation: f64) -> Self {
+    pub fn new_rectangle(center: Vector2f<Real>, width: Real, height: Real, rotation: Real) -> Self {
         let half_width = width / 2.0;
         let half_height = height / 2.0;
         let local_verts = vec![
@@ -104,79 +107,180 @@ impl Polygon {
             Vector2f::new(-half_width, half_height), // Bottom left
         ];
 
-        Self { 
-            area: Self::compute_area(&local_verts),
-            intertia: Self::compute_momemnt_of_inertia(&local_verts),
-            local_vertices: local_verts, 
-            center, 
-            rotation,
-        }
+        Self::from_parts(vec![local_verts.clone()], local_verts, center, rotation)
     }
 
     #[allow(dead_code)]
-    pub fn new_square(position: Vector2f<f64>, size: f64, rotation: f64) -> Self {
+    pub fn new_square(position: Vector2f<Real>, size: Real, rotation: Real) -> Self {
         Self::new_rectangle(position, size, size, rotation)
     }
 
-    pub fn new_regular_polygon(n_sides: u32, radius: f64, center: Vector2f<f64>, rotation: f64) -> Self {
+    pub fn new_regular_polygon(n_sides: u32, radius: Real, center: Vector2f<Real>, rotation: Real) -> Self {
         let mut angle = PI * 270.0 / 180.0; // Starting at 270 degrees
-        let angle_increment = (2.0 * PI) / n_sides as f64;
+        let angle_increment = (2.0 * PI) / n_sides as Real;
         if n_sides % 2 == 0 { angle += angle_increment / 2.0; }
         let mut local_verts = vec![];
         for _ in 0..n_sides {
-            let x = radius * f64::cos(angle);
-            let y = radius * f64::sin(angle);
+            let x = radius * Real::cos(angle);
+            let y = radius * Real::sin(angle);
             local_verts.push(Vector2f::new(x, y));
             angle += angle_increment;
         }
 
-        Self { 
-            area: Self::compute_area(&local_verts),
-            intertia: Self::compute_momemnt_of_inertia(&local_verts),
-            local_vertices: local_verts, 
-            center, 
-            rotation, 
-        }
+        Self::from_parts(vec![local_verts.clone()], local_verts, center, rotation)
     }
 
-    pub fn new(vertices: Vec<Vector2f<f64>>, center_pos: Vector2f<f64>, rotation: f64) -> Self {
+    // Accepts any simple polygon, concave ones are split into convex parts
+    pub fn new(mut vertices: Vec<Vector2f<Real>>, center_pos: Vector2f<Real>, rotation: Real) -> Self {
+        if signed_area(&vertices) < 0.0 {
+            vertices.reverse();
+        }
+
         let center = Self::compute_center(&vertices);
-        let localized_verts: Vec<Vector2f<f64>> = vertices.iter().map(|&v| v - center).collect();
+        let localized_verts: Vec<Vector2f<Real>> = vertices.iter().map(|&v| v - center).collect();
+
+        Self::from_parts(decompose(&localized_verts), localized_verts, center_pos, rotation)
+    }
 
-        Self { 
-            area: Self::compute_area(&localized_verts),
-            intertia: Self::compute_momemnt_of_inertia(&localized_verts),
-            local_vertices: localized_verts, 
-            center: center_pos, 
+    fn from_parts(parts: Vec<Vec<Vector2f<Real>>>, local_vertices: Vec<Vector2f<Real>>, center: Vector2f<Real>, rotation: Real) -> Self {
+        let mut polygon = Self { 
+            area: Self::compute_area(&local_vertices),
+            intertia: Self::compute_momemnt_of_inertia(&local_vertices),
+            world_vertices: vec![Vector2f::zero(); local_vertices.len()],
+            world_parts: parts.iter().map(|part| vec![Vector2f::zero(); part.len()]).collect(),
+            world_normals: parts.iter().map(|part| vec![Vector2f::zero(); part.len()]).collect(),
+            parts,
+            local_vertices, 
+            center, 
             rotation, 
-            
+        };
+        polygon.update_world();
+        polygon
+    }
+
+    pub fn get_center(&self) -> Vector2f<Real> {
+        self.center
+    }
+
+    pub fn get_rotation(&self) -> Real {
+        self.rotation
+    }
+
+    // Near zero, since the vertices are moved onto their centroid when the polygon is made
+    pub fn local_centroid(&self) -> Vector2f<Real> {
+        Self::compute_center(&self.local_vertices)
+    }
+
+    pub fn get_transform(&self) -> Transform2<Real> {
+        Transform2::new(self.center, self.rotation)
+    }
+
+    pub fn set_center(&mut self, center: Vector2f<Real>) {
+        self.center = center;
+        self.update_world();
+    }
+
+    pub fn set_rotation(&mut self, rotation: Real) {
+        self.rotation = rotation;
+        self.update_world();
+    }
+
+    pub fn set_transform(&mut self, center: Vector2f<Real>, rotation: Real) {
+        self.center = center;
+        self.rotation = rotation;
+        self.update_world();
+    }
+
+    pub fn translate(&mut self, translation: Vector2f<Real>) {
+        self.set_center(self.center + translation);
+    }
+
+    pub fn rotate(&mut self, radians: Real) {
+        self.set_rotation(self.rotation + radians);
+    }
+
+    // Transforms the local vertices into the world space buffers, which keep their size
+    fn update_world(&mut self) {
+        let transform = self.get_transform();
+        for (world, local) in self.world_vertices.iter_mut().zip(self.local_vertices.iter()) {
+            *world = transform.apply(*local);
         }
+
+        for ((world, normals), local) in self.world_parts.iter_mut().zip(self.world_normals.iter_mut()).zip(self.parts.iter()) {
+            for (w, l) in world.iter_mut().zip(local.iter()) {
+                *w = transform.apply(*l);
+            }
+            for (i, normal) in normals.iter_mut().enumerate() {
+                *normal = (world[i] - world[(i + 1) % world.len()]).perpendicular().normalize();
+            }
+        }
+    }
+
+    pub fn get_transformed_vertices(&self) -> &[Vector2f<Real>] {
+        &self.world_vertices
+    }
+
+    pub fn get_parts(&self) -> &[Vec<Vector2f<Real>>] {
+        &self.parts
+    }
+
+    pub fn get_transformed_parts(&self) -> &[Vec<Vector2f<Real>>] {
+        &self.world_parts
     }
 
-    pub fn get_transformed_vertices(&self) -> Vec<Vector2f<f64>> {
-        return self.local_vertices.iter().map(|v| v.rotate(self.rotation) + self.center).collect();
+    // The outward normals of the edges of the world space parts, the normal at index i belongs
+    // to the edge from vertex i to the next one
+    pub fn get_part_normals(&self) -> &[Vec<Vector2f<Real>>] {
+        &self.world_normals
+    }
+
+    // Returns the world space direction of the longest edge along with the extent of the polygon
+    // along (chord) and across (thickness) that direction
+    pub fn get_principal_axis(&self) -> (Vector2f<Real>, Real, Real) {
+        let n = self.local_vertices.len();
+        let mut axis = Vector2f::new(1.0, 0.0);
+        let mut max_len_sq = 0.0;
+        for i in 0..n {
+            let edge = self.local_vertices[(i + 1) % n] - self.local_vertices[i];
+            if edge.len_squared() > max_len_sq {
+                max_len_sq = edge.len_squared();
+                axis = edge.normalize();
+            }
+        }
+
+        let normal = axis.perpendicular();
+        let (mut min_a, mut max_a, mut min_n, mut max_n) = (Real::INFINITY, Real::NEG_INFINITY, Real::INFINITY, Real::NEG_INFINITY);
+        for v in self.local_vertices.as_slice() {
+            min_a = min_a.min(v.dot(axis));
+            max_a = max_a.max(v.dot(axis));
+            min_n = min_n.min(v.dot(normal));
+            max_n = max_n.max(v.dot(normal));
+        }
+
+        (self.get_transform().rotation.apply(axis), max_a - min_a, max_n - min_n)
     }
 
-    fn compute_center(vertices: &Vec<Vector2f<f64>>) -> Vector2f<f64> {
-        let mut sum_center: Vector2f<f64> = Vector2f::zero();
-        let mut sum_weight = 0.0;
+    // The area weighted centroid, falling back to the average of the vertices for degenerate polygons
+    // Source: https://en.wikipedia.org/wiki/Centroid#Of_a_polygon
+    fn compute_center(vertices: &[Vector2f<Real>]) -> Vector2f<Real> {
         let n = vertices.len();
+        let mut sum_center: Vector2f<Real> = Vector2f::zero();
+        let mut sum_cross = 0.0;
         for i in 0..n {
-            let (prev, curr, next) = 
-            match i {
-                i if i == 0 => (vertices[n - 1], vertices[i], vertices[i + 1]),
-                i if i == n - 1 => (vertices[i - 1], vertices[i], vertices[0]),
-                i => (vertices[i - 1], vertices[i], vertices[i + 1]),
-            };
-            let weight = (curr - next).len() + (curr - prev).len();
-            sum_center += curr * weight;
-            sum_weight += weight;
+            let (curr, next) = (vertices[i], vertices[(i + 1) % n]);
+            let cross = curr.cross(next);
+            sum_center += (curr + next) * cross;
+            sum_cross += cross;
         }
-        
-        return sum_center / sum_weight;
+
+        if sum_cross.abs() <= Real::EPSILON {
+            return vertices.iter().fold(Vector2f::zero(), |sum, &v| sum + v) / n as Real;
+        }
+
+        sum_center / (3.0 * sum_cross)
     }
 
-    fn compute_area(vertices: &Vec<Vector2f<f64>>) -> f64 {
+    fn compute_area(vertices: &Vec<Vector2f<Real>>) -> Real {
         let n = vertices.len();
         let mut sum = 0.0;
         for i in 0..n {
@@ -187,7 +291,7 @@ impl Polygon {
         return sum.abs() / 2.0;
     }
 
-    fn compute_momemnt_of_inertia(vertices: &Vec<Vector2f<f64>>) -> f64 {
+    fn compute_momemnt_of_inertia(vertices: &Vec<Vector2f<Real>>) -> Real {
         let n = vertices.len();
         let mut intertia = 0.0; 
 
@@ -199,4 +303,25 @@ impl Polygon {
         
         return (intertia / 12.0).abs();
     }
-}
\ No newline at end of file
+}
+
+fn convex_contains_point(verts: &[Vector2f<Real>], point: Vector2f<Real>) -> bool {
+    let mut pos = 0;
+    let mut neg = 0;
+
+    for i in 0..verts.len() {
+        let v1 = verts[i];
+        let v2 = verts[(i + 1) % verts.len()];
+
+        let d = (point.x - v1.x) * (v2.y - v1.y) - (point.y - v1.y) * (v2.x - v1.x);
+
+        if d > 0.0 { pos += 1; }
+        if d < 0.0 { neg += 1; }
+
+        if pos > 0 && neg > 0 {
+            return false;
+        }
+    }
+
+    return true;
+}
@@ -0,0 +1,242 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use crate::utils::real::Real;
+use crate::Vector2f;
+
+use super::arena::{BodyArena, BodyHandle};
+use super::broadphase::SpatialGrid;
+use super::collision::{contact_shape_shape, ray_vs_shape, shape_vs_shape, AABB};
+use super::rigid_body::RigidBody;
+use super::soft_body::SoftBody;
+use super::shape::Shape;
+use super::shape_type::ShapeType;
+
+// How many times the path of a cast shape is sampled per body at most, and how many times the
+// first overlap is halved afterwards
+const MAX_CAST_SAMPLES: usize = 256;
+const CAST_BISECTION_STEPS: usize = 24;
+
+// Decides which bodies a world query can return
+#[derive(Clone)]
+pub struct QueryFilter {
+    pub static_bodies: bool,
+    pub dynamic_bodies: bool,
+    // Only used by the overlap queries, casts never hit joints
+    pub joints: bool,
+    pub exclude: Vec<BodyHandle>,
+}
+
+impl Default for QueryFilter {
+    fn default() -> Self {
+        Self {
+            static_bodies: true,
+            dynamic_bodies: true,
+            joints: true,
+            exclude: vec![],
+        }
+    }
+}
+
+impl QueryFilter {
+    pub fn accepts(&self, handle: BodyHandle, obj: &RigidBody) -> bool {
+        (if obj.is_static { self.static_bodies } else { self.dynamic_bodies })
+            && !self.exclude.contains(&handle)
+    }
+}
+
+// A joint of a soft body, found by its index in the joints of the body
+#[derive(Clone)]
+pub struct JointHandle {
+    pub soft_body: Rc<RefCell<SoftBody>>,
+    pub index: usize,
+}
+
+#[derive(Clone, Default)]
+pub struct QueryResult {
+    pub bodies: Vec<BodyHandle>,
+    pub joints: Vec<JointHandle>,
+}
+
+#[allow(dead_code)]
+#[derive(Clone)]
+pub struct RaycastHit {
+    pub body: BodyHandle,
+    pub point: Vector2f<Real>,
+    pub normal: Vector2f<Real>,
+    // How far along the ray the hit is, from 0 at the origin to 1 at the max distance
+    pub fraction: Real,
+}
+
+#[allow(dead_code)]
+#[derive(Clone)]
+pub struct ShapeCastHit {
+    pub body: BodyHandle,
+    pub point: Vector2f<Real>,
+    // Points out of the body that was hit, towards the cast shape
+    pub normal: Vector2f<Real>,
+    // How far along the translation the shape gets before touching the body, from 0 to 1
+    pub toi: Real,
+}
+
+// Returns the bodies in the region that pass the overlap test, in the order of their slots in the
+// arena. The grid holds the handles of the bodies.
+pub fn overlap_bodies(bodies: &BodyArena, grid: &SpatialGrid<BodyHandle>, region: &AABB, filter: &QueryFilter, overlaps: impl Fn(&ShapeType) -> bool) -> Vec<BodyHandle> {
+    let mut handles = grid.query_aabb(region);
+    handles.sort_unstable();
+    handles.into_iter()
+        .filter(|&handle| bodies.get(handle).is_some_and(|obj| filter.accepts(handle, obj) && overlaps(&obj.shape)))
+        .collect()
+}
+
+// Returns the joints in the region that pass the overlap test. The grid holds the index of the
+// soft body and the index of the joint within it.
+pub fn overlap_joints(soft_bodies: &[Rc<RefCell<SoftBody>>], grid: &SpatialGrid<(usize, usize)>, region: &AABB, filter: &QueryFilter, overlaps: impl Fn(Vector2f<Real>) -> bool) -> Vec<JointHandle> {
+    if !filter.joints {
+        return vec![];
+    }
+
+    let mut indices = grid.query_aabb(region);
+    indices.sort_unstable();
+    indices.into_iter()
+        .filter(|&(i, j)| overlaps(soft_bodies[i].borrow().joints[j].position))
+        .map(|(i, j)| JointHandle { soft_body: soft_bodies[i].clone(), index: j })
+        .collect()
+}
+
+// Returns every body hit by the ray, sorted by distance. The grid holds the handles of the bodies.
+pub fn raycast_all(bodies: &BodyArena, grid: &SpatialGrid<BodyHandle>, origin: Vector2f<Real>, dir: Vector2f<Real>, max_dist: Real, filter: &QueryFilter) -> Vec<RaycastHit> {
+    if dir.len_squared() <= Real::EPSILON {
+        return vec![];
+    }
+
+    let ray_dir = dir.normalize() * max_dist;
+    let mut hits: Vec<RaycastHit> = grid.query_ray(origin, ray_dir).into_iter()
+        .filter_map(|handle| {
+            let obj = bodies.get(handle).filter(|obj| filter.accepts(handle, obj))?;
+            let hit = ray_vs_shape(origin, ray_dir, &obj.shape)?;
+            Some(RaycastHit {
+                body: handle,
+                point: origin + ray_dir * hit.sep_or_t,
+                normal: hit.normal,
+                fraction: hit.sep_or_t,
+            })
+        }).collect();
+
+    hits.sort_by(|a, b| a.fraction.total_cmp(&b.fraction));
+    hits
+}
+
+pub fn raycast(bodies: &BodyArena, grid: &SpatialGrid<BodyHandle>, origin: Vector2f<Real>, dir: Vector2f<Real>, max_dist: Real, filter: &QueryFilter) -> Option<RaycastHit> {
+    raycast_all(bodies, grid, origin, dir, max_dist, filter).into_iter().next()
+}
+
+// Moves the shape from the given position and rotation along the translation, and returns the
+// first body it touches. Bodies that already overlap the shape at the start are hit at 0.
+pub fn shape_cast(bodies: &BodyArena, grid: &SpatialGrid<BodyHandle>, shape: &ShapeType, position: Vector2f<Real>, rotation: Real, translation: Vector2f<Real>, filter: &QueryFilter) -> Option<ShapeCastHit> {
+    // Terrain can only be hit, not cast
+    if matches!(shape, ShapeType::Chain(_) | ShapeType::Heightfield(_)) {
+        return None;
+    }
+
+    let mut start = shape.clone();
+    start.set_center(position);
+    start.set_rotation(rotation);
+
+    let start_aabb = start.get_aabb();
+    grid.query_aabb(&start_aabb.expand_by(translation)).into_iter()
+        .filter_map(|handle| {
+            let obj = bodies.get(handle).filter(|obj| filter.accepts(handle, obj))?;
+            let (toi, pose) = time_of_impact(&start, translation, &obj.shape)?;
+            let body_shape = &obj.shape;
+            let collision = shape_vs_shape(&pose, body_shape)?;
+            let contacts = contact_shape_shape(&pose, body_shape, &collision);
+            let point = if contacts.is_empty() {
+                pose.get_center()
+            } else {
+                contacts.iter().fold(Vector2f::zero(), |sum, &c| sum + c) / contacts.len() as Real
+            };
+
+            Some(ShapeCastHit { body: handle, point, normal: -collision.normal, toi })
+        })
+        .min_by(|a, b| a.toi.total_cmp(&b.toi))
+}
+
+// Finds the first fraction of the translation where the shape overlaps the target, along with the
+// shape moved there. Only the part of the path where the bounding boxes meet is searched, in steps
+// small enough that the shape can not skip over the target, and the first overlap is then narrowed
+// down by bisection.
+fn time_of_impact(shape: &ShapeType, translation: Vector2f<Real>, target: &ShapeType) -> Option<(Real, ShapeType)> {
+    let moved = |t: Real| {
+        let mut pose = shape.clone();
+        pose.translate(translation * t);
+        pose
+    };
+
+    let (t_enter, t_exit) = aabb_sweep_interval(&shape.get_aabb(), translation, &target.get_aabb())?;
+    if shape_vs_shape(&moved(t_enter), target).is_some() {
+        return Some((t_enter, moved(t_enter)));
+    }
+
+    let distance = translation.len() * (t_exit - t_enter);
+    let step = thickness_along(shape, translation) / 2.0;
+    let samples = if step > 0.0 { ((distance / step).ceil() as usize).clamp(1, MAX_CAST_SAMPLES) } else { MAX_CAST_SAMPLES };
+
+    let mut lo = t_enter;
+    for sample in 1..=samples {
+        let hi = t_enter + (t_exit - t_enter) * sample as Real / samples as Real;
+        if shape_vs_shape(&moved(hi), target).is_none() {
+            lo = hi;
+            continue;
+        }
+
+        let mut hi = hi;
+        for _ in 0..CAST_BISECTION_STEPS {
+            let mid = (lo + hi) / 2.0;
+            if shape_vs_shape(&moved(mid), target).is_some() {
+                hi = mid;
+            } else {
+                lo = mid;
+            }
+        }
+        return Some((lo, moved(hi)));
+    }
+
+    None
+}
+
+// The thinnest width of the shape along the direction of motion. For compounds this is the
+// thinnest child, since any of them could be the one to hit.
+fn thickness_along(shape: &ShapeType, direction: Vector2f<Real>) -> Real {
+    match shape {
+        ShapeType::Compound(compound) => compound.get_transformed_children().iter()
+            .map(|child| thickness_along(child, direction))
+            .fold(Real::INFINITY, Real::min),
+        _ => shape.projected_width(direction.perpendicular()),
+    }
+}
+
+// Returns the range of fractions of the translation where the moving box overlaps the still one
+fn aabb_sweep_interval(moving: &AABB, translation: Vector2f<Real>, still: &AABB) -> Option<(Real, Real)> {
+    let mut t_enter: Real = 0.0;
+    let mut t_exit: Real = 1.0;
+    let axes = [
+        (moving.top_left.x, moving.bottom_right.x, still.top_left.x, still.bottom_right.x, translation.x),
+        (moving.top_left.y, moving.bottom_right.y, still.top_left.y, still.bottom_right.y, translation.y),
+    ];
+
+    for (min, max, still_min, still_max, motion) in axes {
+        if motion == 0.0 {
+            if max < still_min || min > still_max {
+                return None;
+            }
+            continue;
+        }
+
+        let (t0, t1) = ((still_min - max) / motion, (still_max - min) / motion);
+        t_enter = t_enter.max(t0.min(t1));
+        t_exit = t_exit.min(t0.max(t1));
+    }
+
+    (t_enter <= t_exit).then_some((t_enter, t_exit))
+}
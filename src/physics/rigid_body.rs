@@ -1,39 +1,74 @@
-use core::f64;
-use std::{vec};
+use std::ops::Deref;
 use graphics::math::Matrix2d;
 use graphics::{color, Context, Transformed};
 use opengl_graphics::{GlGraphics, Texture};
 
+use crate::utils::real::{to_f64, Real};
 use crate::Vector2f;
 use crate::game::PhysicsData;
 use crate::physics::shape_type::ShapeType;
 use crate::physics::shape::Shape;
 use crate::physics::polygon::Polygon;
 use crate::physics::circle::Circle;
+use crate::physics::capsule::Capsule;
+use crate::physics::compound::Compound;
+use crate::physics::chain::Chain;
+use crate::physics::heightfield::Heightfield;
 use super::material::*;
 use super::tiled_mesh::TiledMesh;
 use super::collision::*;
+use super::fracture::Impact;
+use super::shape::Renderable;
 
 #[allow(dead_code)]
-pub const GRAVITY: Vector2f<f64> = Vector2f { x: 0.0, y: 90.816 };
+pub const GRAVITY: Vector2f<Real> = Vector2f { x: 0.0, y: 90.816 };
 #[allow(dead_code)]
-pub const AIR_RESISTANCE: f64 = 0.08;
+pub const AIR_RESISTANCE: Real = 0.08;
 #[allow(dead_code)]
-pub const BASE_STATIC_FRICTION: f64 = 0.6;
+pub const BASE_STATIC_FRICTION: Real = 0.6;
 #[allow(dead_code)]
-pub const BASE_DYNAMIC_FRICTION: f64 = 0.4;
+pub const BASE_DYNAMIC_FRICTION: Real = 0.4;
 #[allow(dead_code)]
-pub const BASE_ELASTICITY: f64 = 0.5;
+pub const BASE_ELASTICITY: Real = 0.5;
+const AERODYNAMIC_MIN_SPEED: Real = 1.0;
 
 
 #[derive(Clone)]
 pub struct RigidBody {
-    pub linear_velocity: Vector2f<f64>,
-    pub angular_velocity: f64,
+    pub linear_velocity: Vector2f<Real>,
+    pub angular_velocity: Real,
     pub material: Material,
     pub is_static: bool,
     pub shape: ShapeType,
     pub mesh: TiledMesh,
+    // Overrides the global air density when damping the velocities
+    pub linear_damping: Option<Real>,
+    pub angular_damping: Option<Real>,
+    // Strongest contact impulse received during the current step
+    pub impact: Option<Impact>,
+    // Replace the mass and inertia derived from the shape and material, e.g. for hollow objects
+    pub mass_override: Option<Real>,
+    pub inertia_override: Option<Real>,
+    pub locks: MotionLocks,
+}
+
+// Keeps the solver from turning the body or moving it along the locked world axes, e.g. for
+// characters that should not roll or crates that only slide sideways
+#[derive(Clone, Copy, Debug, Default)]
+pub struct MotionLocks {
+    pub rotation: bool,
+    pub x: bool,
+    pub y: bool,
+}
+
+// The mass of a body, its inertia about the center of mass, and where the center of mass is in
+// the local space of the shape. Most shapes are placed around their centroid, so it is only away
+// from the origin for shapes like heightfields, which are placed by a corner.
+#[derive(Clone, Copy, Debug)]
+pub struct MassProperties {
+    pub mass: Real,
+    pub inertia: Real,
+    pub local_center: Vector2f<Real>,
 }
 
 impl From<Circle> for RigidBody {
@@ -44,6 +79,12 @@ impl From<Circle> for RigidBody {
             material: WOOD,
             is_static: false, 
             mesh: TiledMesh::from(&value),
+            linear_damping: None,
+            angular_damping: None,
+            impact: None,
+            mass_override: None,
+            inertia_override: None,
+            locks: MotionLocks::default(),
             shape: ShapeType::Circle(value),
         }
     }
@@ -57,16 +98,103 @@ impl From<Polygon> for RigidBody {
             material: WOOD,
             is_static: false, 
             mesh: TiledMesh::from(&value),
+            linear_damping: None,
+            angular_damping: None,
+            impact: None,
+            mass_override: None,
+            inertia_override: None,
+            locks: MotionLocks::default(),
             shape: ShapeType::Polygon(value),
         }
     }
 }
 
+impl From<Capsule> for RigidBody {
+    fn from(value: Capsule) -> Self {
+        Self { 
+            linear_velocity: Vector2f::zero(), 
+            angular_velocity: 0.0, 
+            material: WOOD,
+            is_static: false, 
+            mesh: TiledMesh::from(&value),
+            linear_damping: None,
+            angular_damping: None,
+            impact: None,
+            mass_override: None,
+            inertia_override: None,
+            locks: MotionLocks::default(),
+            shape: ShapeType::Capsule(value),
+        }
+    }
+}
+
+impl From<Compound> for RigidBody {
+    fn from(value: Compound) -> Self {
+        Self { 
+            linear_velocity: Vector2f::zero(), 
+            angular_velocity: 0.0, 
+            material: WOOD,
+            is_static: false, 
+            mesh: TiledMesh::from(&value),
+            linear_damping: None,
+            angular_damping: None,
+            impact: None,
+            mass_override: None,
+            inertia_override: None,
+            locks: MotionLocks::default(),
+            shape: ShapeType::Compound(value),
+        }
+    }
+}
+
+// Chains have no area, so they are always static
+impl From<Chain> for RigidBody {
+    fn from(value: Chain) -> Self {
+        Self { 
+            linear_velocity: Vector2f::zero(), 
+            angular_velocity: 0.0, 
+            material: WOOD,
+            is_static: true, 
+            mesh: TiledMesh::from(&value),
+            linear_damping: None,
+            angular_damping: None,
+            impact: None,
+            mass_override: None,
+            inertia_override: None,
+            locks: MotionLocks::default(),
+            shape: ShapeType::Chain(value),
+        }
+    }
+}
+
+impl From<Heightfield> for RigidBody {
+    fn from(value: Heightfield) -> Self {
+        Self { 
+            linear_velocity: Vector2f::zero(), 
+            angular_velocity: 0.0, 
+            material: DIRT,
+            is_static: true, 
+            mesh: TiledMesh::from(&value),
+            linear_damping: None,
+            angular_damping: None,
+            impact: None,
+            mass_override: None,
+            inertia_override: None,
+            locks: MotionLocks::default(),
+            shape: ShapeType::Heightfield(value),
+        }
+    }
+}
+
 impl From<ShapeType> for RigidBody {
     fn from(value: ShapeType) -> Self {
         match value {
             ShapeType::Circle(c) => RigidBody::from(c),
             ShapeType::Polygon(p) => RigidBody::from(p),
+            ShapeType::Capsule(c) => RigidBody::from(c),
+            ShapeType::Compound(c) => RigidBody::from(c),
+            ShapeType::Chain(c) => RigidBody::from(c),
+            ShapeType::Heightfield(h) => RigidBody::from(h),
         }
     }
 }
@@ -77,18 +205,58 @@ impl RigidBody {
             linear_velocity: Vector2f::zero(), 
             angular_velocity: 0.0, 
             material,
-            is_static, 
+            is_static: is_static || matches!(shape, ShapeType::Chain(_) | ShapeType::Heightfield(_)), 
             mesh: TiledMesh::from(&shape),
+            linear_damping: None,
+            angular_damping: None,
+            impact: None,
+            mass_override: None,
+            inertia_override: None,
+            locks: MotionLocks::default(),
             shape,
         }
     }
 
-    pub fn get_inv_mass(&self) -> f64 {
-        if self.is_static { 0.0 } else { 1.0 / (self.shape.area() * self.material.density) }
+    // Derives the mass from the area and density of the body, unless it has been overridden. 
+    // An overridden mass changes the density used for the inertia as well.
+    pub fn mass_properties(&self) -> MassProperties {
+        let (mass, inertia) = self.mass_and_inertia();
+        MassProperties { mass, inertia, local_center: self.shape.local_centroid() }
+    }
+
+    // The solver asks for these on every contact, so they skip finding the centroid
+    fn mass_and_inertia(&self) -> (Real, Real) {
+        let area = self.shape.area();
+        let mass = self.mass_override.unwrap_or(area * self.material.density);
+        let density = if area > 0.0 { mass / area } else { self.material.density };
+
+        (mass, self.inertia_override.unwrap_or(self.shape.momemnt_of_inertia() * density))
+    }
+
+    pub fn get_inv_mass(&self) -> Real {
+        if self.is_static { 0.0 } else { 1.0 / self.mass_and_inertia().0 }
+    }
+
+    pub fn get_inv_inertia(&self) -> Real {
+        if self.is_static || self.locks.rotation { 0.0 } else { 1.0 / self.mass_and_inertia().1 }
     }
 
-    pub fn get_inv_inertia(&self) -> f64 {
-        if self.is_static { 0.0 } else { 1.0 / (self.shape.momemnt_of_inertia() * self.material.density) }
+    // The inverse mass seen by an impulse along the unit direction. Locked axes do not give way,
+    // so the body is infinitely heavy along them.
+    pub fn get_inv_mass_along(&self, direction: Vector2f<Real>) -> Real {
+        self.lock_axes(direction).len_squared() * self.get_inv_mass()
+    }
+
+    // Removes the components of a velocity, impulse or translation along the locked axes
+    pub fn lock_axes(&self, v: Vector2f<Real>) -> Vector2f<Real> {
+        Vector2f::new(if self.locks.x { 0.0 } else { v.x }, if self.locks.y { 0.0 } else { v.y })
+    }
+
+    fn apply_locks(&mut self) {
+        self.linear_velocity = self.lock_axes(self.linear_velocity);
+        if self.locks.rotation {
+            self.angular_velocity = 0.0;
+        }
     }
 
     pub fn update_velocity(&mut self, physics: &PhysicsData) {
@@ -98,16 +266,162 @@ impl RigidBody {
 
         self.linear_velocity += physics.gravity * physics.dt;        
         
-        self.linear_velocity *= 1.0 - physics.air_density * physics.dt;
-        self.angular_velocity *= 1.0 - physics.air_density * physics.dt;       
+        self.apply_aerodynamics(physics);
+
+        let linear_damping = self.linear_damping.unwrap_or(physics.air_density);
+        let angular_damping = self.angular_damping.unwrap_or(physics.air_density);
+        self.linear_velocity *= 1.0 - linear_damping * physics.dt;
+        self.angular_velocity *= 1.0 - angular_damping * physics.dt;       
+        self.apply_locks();
+    }
+
+    // Quadratic drag against the projected width, plus lift and torque for flat polygons
+    fn apply_aerodynamics(&mut self, physics: &PhysicsData) {
+        let speed = self.linear_velocity.len();
+        if speed < AERODYNAMIC_MIN_SPEED {
+            return;
+        }
+
+        // Flat polygons act as a plate with the chord oriented towards the leading edge. The center
+        // of pressure moves from the quarter chord towards the center as the plate stalls.
+        let plate = if let ShapeType::Polygon(p) = &self.shape {
+            let (axis, chord, thickness) = p.get_principal_axis();
+            let flatness = 1.0 - thickness / chord;
+            let front = if axis.dot(self.linear_velocity) >= 0.0 { axis } else { -axis };
+            let offset = front * chord * 0.25 * front.dot(self.linear_velocity / speed) * flatness;
+            Some((front, chord, flatness, offset))
+        } else {
+            None
+        };
+        let r = plate.map_or(Vector2f::zero(), |(.., offset)| offset);
+
+        // The air pushes on the center of pressure, so the forces follow the velocity of that 
+        // point. Otherwise the torque could feed energy into a spinning body.
+        let velocity = self.linear_velocity + r.perpendicular() * self.angular_velocity;
+        let point_speed = velocity.len();
+        if point_speed < AERODYNAMIC_MIN_SPEED {
+            return;
+        }
+
+        let dir = velocity / point_speed;
+        let dynamic_pressure = 0.5 * physics.air_density * point_speed * point_speed;
+        let width = self.shape.projected_width(dir);
+        let mut force = -dir * dynamic_pressure * width * physics.drag_coefficient;
+
+        if let Some((front, chord, flatness, _)) = plate {
+            let cos_attack = front.dot(dir);
+            let sin_attack = front.cross(dir);
+
+            // Flat plate lift, strongest at 45 degrees angle of attack. It pushes against the
+            // part of the velocity along the normal of the plate, so it never adds energy.
+            let lift_dir = if sin_attack >= 0.0 { -dir.perpendicular() } else { dir.perpendicular() };
+            let lift = dynamic_pressure * chord * physics.lift_coefficient * flatness * 2.0 * (sin_attack * cos_attack).abs();
+            force += lift_dir * lift;
+        }
+
+        // Never let the air reverse the motion of the body within a single step
+        let mut dv = self.lock_axes(force) * physics.dt * self.get_inv_mass();
+        if dv.len() > speed {
+            let limit = speed / dv.len();
+            dv *= limit;
+            force *= limit;
+        }
+
+        self.linear_velocity += dv;
+        self.angular_velocity += r.cross(force * physics.dt) * self.get_inv_inertia();
     }
 
-    pub fn update_position(&mut self, dt: f64) {
+    // Applies an impulse at a world space point, which also spins the body when it is off center.
+    // The impulse counts as an impact, so brittle bodies can break from it.
+    pub fn apply_impulse(&mut self, impulse: Vector2f<Real>, point: Vector2f<Real>) {
+        if self.is_static {
+            return;
+        }
+
+        let r = point - self.shape.get_center();
+        self.linear_velocity += self.lock_axes(impulse) * self.get_inv_mass();
+        self.angular_velocity += r.cross(impulse) * self.get_inv_inertia();
+        self.record_impact(impulse.len(), point);
+    }
+
+    pub fn update_position(&mut self, dt: Real) {
+        self.apply_locks();
         self.shape.translate(self.linear_velocity * dt);  
         self.shape.rotate(self.angular_velocity * dt); 
     }
 
-    pub fn collide_with(&mut self, other: &mut RigidBody, dt: f64) -> Option<CollisionData> {
+    // Whether the bodies can touch within the step. The test is loose, the bounds of the first body
+    // are grown and swept both ways along the relative velocity, and collide_with does the exact
+    // test later. Static bodies never move, so they are never tested against each other, and
+    // scenery that overlaps on purpose, like a wall standing in the ground, stays where it is put.
+    // Whether the bodies can touch within the step, when either can also be pushed up to the
+    // margin by other contacts first
+    pub fn may_collide(&self, other: &RigidBody, dt: Real, margin: Real) -> bool {
+        if self.is_static && other.is_static {
+            return false;
+        }
+
+        let rel_vel = (self.linear_velocity - other.linear_velocity) * dt;
+        let aabb1 = self.shape.get_aabb().grow(margin).expand_by(rel_vel).expand_by(-rel_vel);
+        aabb1.overlap(&other.shape.get_aabb())
+    }
+
+    fn record_impact(&mut self, impulse: Real, point: Vector2f<Real>) {
+        if self.impact.is_none_or(|impact| impulse > impact.impulse) {
+            self.impact = Some(Impact { impulse, point });
+        }
+    }
+
+    pub fn scale(&self, ratio: Real) -> Self {
+        let mut body = Self::new(self.shape.scale(ratio), self.material, self.is_static);
+        body.linear_damping = self.linear_damping;
+        body.angular_damping = self.angular_damping;
+        body.mass_override = self.mass_override.map(|mass| mass * ratio * ratio);
+        body.inertia_override = self.inertia_override.map(|inertia| inertia * ratio.powi(4));
+        body.locks = self.locks;
+        body
+    }
+
+    pub fn draw(&self, transform: Matrix2d, texture: &Texture, c: Context, gl: &mut GlGraphics) {
+        // Chains have no area to put a texture on, so they are drawn as lines
+        if let ShapeType::Chain(chain) = &self.shape {
+            chain.draw(transform, gl, color::BLACK);
+            return;
+        }
+
+        self.mesh.draw(
+            transform.trans_pos(self.shape.get_center().cast::<f64>()).rot_rad(to_f64(self.shape.get_rotation())), 
+            color::WHITE, texture, c, gl);
+    }
+}
+
+// A body taking part in a contact. Static bodies are only read, so islands touching the same
+// static body can still be solved at the same time.
+pub enum ContactBody<'a> {
+    Dynamic(&'a mut RigidBody),
+    Static(&'a RigidBody),
+}
+
+impl Deref for ContactBody<'_> {
+    type Target = RigidBody;
+
+    fn deref(&self) -> &RigidBody {
+        match self {
+            ContactBody::Dynamic(body) => body,
+            ContactBody::Static(body) => body,
+        }
+    }
+}
+
+impl<'a> ContactBody<'a> {
+    fn get_mut(&mut self) -> Option<&mut RigidBody> {
+        match self {
+            ContactBody::Dynamic(body) => Some(body),
+            ContactBody::Static(_) => None,
+        }
+    }
+
+    pub fn collide_with(&mut self, other: &mut ContactBody<'a>, dt: Real) -> Option<CollisionData> {
         let aabb1 = self.shape.get_aabb();
         let aabb2 = other.shape.get_aabb();
         let rel_vel = (self.linear_velocity - other.linear_velocity) * dt;
@@ -130,7 +444,7 @@ impl RigidBody {
         }
     }
 
-    fn ray_collision(&mut self, other: &mut RigidBody, dt: f64) -> Option<CollisionData> {
+    fn ray_collision(&mut self, other: &mut ContactBody<'a>, dt: Real) -> Option<CollisionData> {
         let (a, b) = if self.linear_velocity.len_squared() > other.linear_velocity.len_squared() {
             (self, other)
         } else {
@@ -140,89 +454,55 @@ impl RigidBody {
         let ray_origin = a.shape.get_center();
         let ray_dir = (a.linear_velocity - b.linear_velocity) * dt;
         
-        if let Some(collision) = match &b.shape {
-            ShapeType::Circle(c) => ray_vs_circle(ray_origin, ray_dir, c),
-            ShapeType::Polygon(p) => ray_vs_polygon(ray_origin, ray_dir, p),
-        } {
-            let time = collision.sep_or_t * dt - f64::EPSILON;
+        if let Some(collision) = ray_vs_shape(ray_origin, ray_dir, &b.shape) {
+            let time = collision.sep_or_t * dt - Real::EPSILON;
             
-            a.shape.translate(a.linear_velocity * time);
-            a.shape.rotate(a.angular_velocity * time);
-
-            b.shape.translate(b.linear_velocity * time);
-            b.shape.rotate(b.angular_velocity * time);
+            for body in [a.get_mut(), b.get_mut()].into_iter().flatten() {
+                body.shape.translate(body.linear_velocity * time);
+                body.shape.rotate(body.angular_velocity * time);
+            }
 
             return b.geometric_collision(a);
         }
         None
     }
 
-    fn geometric_collision(&mut self, other: &mut RigidBody) -> Option<CollisionData> {
-        let push_out = 
-        |data: CollisionData, a: &mut RigidBody, b: &mut RigidBody| -> Option<CollisionData> {
-            let sep = data.sep_or_t - f64::EPSILON;
-            let normal = data.normal;
-            match (a.is_static, b.is_static) {
-                (true, true) | (false, false) => {
-                    a.shape.translate(normal * sep / 2.0);
-                    b.shape.translate(normal * -sep / 2.0);
-                    
-                }
-                (true, false) => b.shape.translate(normal * -sep),
-                (false, true) => a.shape.translate(normal * sep),
-            }
-            Some(data)
-        };
+    fn geometric_collision(&mut self, other: &mut ContactBody<'a>) -> Option<CollisionData> {
+        push_circle_out(self, other);
+        push_circle_out(other, self);
 
-        let push_circle_out = |p: &mut Polygon, c: &mut Circle| {
-            if p.contains_point(c.center) {
-                c.center = p.find_closest_surface_point(c.center).0;
-            }
-        };
+        let mut collision = shape_vs_shape(&self.shape, &other.shape)?;
 
-        let mut collision_data = None;
-        match (&mut self.shape, &mut other.shape) {
-            (ShapeType::Circle(a), ShapeType::Circle(b)) => {
-                if let Some(collision) = circle_vs_circle(&a, &b) {
-                    collision_data = push_out(collision, self, other);
-                } 
-            },
-            (ShapeType::Circle(c), ShapeType::Polygon(p)) => {
-                push_circle_out(p, c);
-                if let Some(mut collision) = polygon_vs_circle(&p, &c) {
-                    collision.normal = -collision.normal;
-                    collision_data = push_out(collision, self, other);
-                }
+        // Each body takes its share of the push along the axes it is free to move on,
+        // bodies locked across the normal leave all of it to the other one
+        let sep = collision.sep_or_t - Real::EPSILON;
+        let normal = collision.normal;
+        let mobility = |body: &RigidBody| if body.is_static { 0.0 } else { body.lock_axes(normal).len_squared() };
+        let total = mobility(self) + mobility(other);
+        if total > Real::EPSILON {
+            let (a_push, b_push) = (self.lock_axes(normal), other.lock_axes(normal));
+            if let Some(a) = self.get_mut() {
+                a.shape.translate(a_push * sep / total);
             }
-            (ShapeType::Polygon(p), ShapeType::Circle(c)) => {
-                push_circle_out(p, c);
-                if let Some(collision) = polygon_vs_circle(&p, &c) {
-                    collision_data = push_out(collision, self, other);
-                }
+            if let Some(b) = other.get_mut() {
+                b.shape.translate(b_push * -sep / total);
             }
-            (ShapeType::Polygon(a), ShapeType::Polygon(b)) => {
-                if let Some(collision) = polygon_vs_polygon(&a, &b) {
-                    collision_data = push_out(collision, self, other);
-                }
-            }   
         }
 
         // Find the contact points
-        if let Some(mut collision) = collision_data {
-            collision.contacts = match (&self.shape, &other.shape) {
-                (ShapeType::Circle(a), ShapeType::Circle(_)) => vec![a.center + collision.normal * a.radius],
-                (ShapeType::Circle(a), ShapeType::Polygon(b)) => contact_poly_circle(b, a),
-                (ShapeType::Polygon(a), ShapeType::Circle(b)) => contact_poly_circle(a, b),
-                (ShapeType::Polygon(a), ShapeType::Polygon(b)) => contact_poly_poly(a, b),
-            };
-            return Some(collision)
-        }
-
-        None
+        collision.contacts = contact_shape_shape(&self.shape, &other.shape, &collision);
+        Some(collision)
     }
 
+    // Adds an impulse at the offset from the center to the velocities, static bodies keep theirs
+    fn apply_contact_impulse(&mut self, impulse: Vector2f<Real>, r: Vector2f<Real>, inv_mass: Real, inv_inertia: Real) {
+        if let Some(body) = self.get_mut() {
+            body.linear_velocity += body.lock_axes(impulse) * inv_mass;
+            body.angular_velocity += r.cross(impulse) * inv_inertia;
+        }
+    }
 
-    pub fn resolve_collision(&mut self, other: &mut RigidBody, collision: &CollisionData) {
+    pub fn resolve_collision(&mut self, other: &mut ContactBody<'a>, collision: &CollisionData, materials: &MaterialTable) {
         let a = self;
         let b = other;
 
@@ -232,10 +512,10 @@ impl RigidBody {
         let b_inv_mass = b.get_inv_mass();
         let b_inv_inertia = b.get_inv_inertia();
 
-        let restitution = a.material.restitution.min(b.material.restitution);
-
-        let sf = (a.material.static_friction + b.material.static_friction) / 2.0;
-        let df = (a.material.dynamic_friction + b.material.dynamic_friction) / 2.0;
+        let pair = materials.combine(&a.material, &b.material);
+        let restitution = pair.restitution;
+        let sf = pair.static_friction;
+        let df = pair.dynamic_friction;
 
         let normal = collision.normal;
         for contact_point in collision.contacts.as_slice() {
@@ -250,19 +530,23 @@ impl RigidBody {
             }
 
             let v_rel = -(1.0 + restitution) * relative_velocity.dot(normal);
-            let mut denom = a_inv_mass + b_inv_mass + 
-                f64::powi(ra.cross(normal), 2) * a_inv_inertia + 
-                f64::powi(rb.cross(normal), 2) * b_inv_inertia;
-            denom = denom.max(f64::EPSILON);
+            let mut denom = a.get_inv_mass_along(normal) + b.get_inv_mass_along(normal) + 
+                Real::powi(ra.cross(normal), 2) * a_inv_inertia + 
+                Real::powi(rb.cross(normal), 2) * b_inv_inertia;
+            denom = denom.max(Real::EPSILON);
 
             let j = v_rel / denom;
             let a_impulse = normal * j;
             let b_impulse = normal * -j;
 
-            a.linear_velocity += a_impulse * a_inv_mass;
-            b.linear_velocity += b_impulse * b_inv_mass;
-            a.angular_velocity += ra.cross(a_impulse) * a_inv_inertia;
-            b.angular_velocity += rb.cross(b_impulse) * b_inv_inertia;
+            a.apply_contact_impulse(a_impulse, ra, a_inv_mass, a_inv_inertia);
+            b.apply_contact_impulse(b_impulse, rb, b_inv_mass, b_inv_inertia);
+
+            // Only the dynamic bodies keep track of their impacts. Static bodies can not break,
+            // see impact_ratio, so their impacts were always thrown away.
+            for body in [a.get_mut(), b.get_mut()].into_iter().flatten() {
+                body.record_impact(-j, *contact_point);
+            }
 
             // Calculate friction
             let a_contact_vel = a.linear_velocity + ra.perpendicular() * a.angular_velocity;
@@ -277,10 +561,10 @@ impl RigidBody {
             tangent = tangent.normalize();
 
             let v_rel = -relative_velocity.dot(tangent);
-            let mut denom = a_inv_mass + b_inv_mass +
-                f64::powi(ra.cross(tangent), 2) * a_inv_inertia + 
-                f64::powi(rb.cross(tangent), 2) * b_inv_inertia;
-            denom = denom.max(f64::EPSILON);
+            let mut denom = a.get_inv_mass_along(tangent) + b.get_inv_mass_along(tangent) +
+                Real::powi(ra.cross(tangent), 2) * a_inv_inertia + 
+                Real::powi(rb.cross(tangent), 2) * b_inv_inertia;
+            denom = denom.max(Real::EPSILON);
 
             let mut jt = v_rel / denom;
             if jt.abs() > -j * sf {
@@ -290,20 +574,182 @@ impl RigidBody {
             let a_friction_impulse = tangent * jt;
             let b_friction_impulse = tangent * -jt;
 
-            a.linear_velocity += a_friction_impulse * a_inv_mass;
-            b.linear_velocity += b_friction_impulse * b_inv_mass;
-            a.angular_velocity += ra.cross(a_friction_impulse) * a_inv_inertia;
-            b.angular_velocity += rb.cross(b_friction_impulse) * b_inv_inertia;
+            a.apply_contact_impulse(a_friction_impulse, ra, a_inv_mass, a_inv_inertia);
+            b.apply_contact_impulse(b_friction_impulse, rb, b_inv_mass, b_inv_inertia);
+        }
+    }
+}
+
+// Moves a circle out of a polygon it has sunk into, since the circle can not be tested against
+// the polygon while its center is inside. Static bodies never move, so a polygon that has sunk
+// over a static circle is moved back by the same offset instead.
+fn push_circle_out(circle: &mut ContactBody, polygon: &mut ContactBody) {
+    let (ShapeType::Circle(c), ShapeType::Polygon(p)) = (&circle.shape, &polygon.shape) else {
+        return;
+    };
+    if !p.contains_point(c.center) {
+        return;
+    }
+
+    let surface_point = p.find_closest_surface_point(c.center).0;
+    let offset = c.center - surface_point;
+    if let Some(body) = circle.get_mut() && let ShapeType::Circle(c) = &mut body.shape {
+        c.center = surface_point;
+    } else if let Some(body) = polygon.get_mut() {
+        body.shape.translate(offset);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::physics::fracture::impact_ratio;
+
+    const DT: Real = 1.0 / 120.0;
+
+    fn circle(center: Vector2f<Real>, radius: Real, is_static: bool) -> RigidBody {
+        RigidBody::new(ShapeType::Circle(Circle::new(center, radius, 0.0)), WOOD, is_static)
+    }
+
+    fn square(center: Vector2f<Real>, size: Real, is_static: bool) -> RigidBody {
+        RigidBody::new(ShapeType::Polygon(Polygon::new_square(center, size, 0.0)), WOOD, is_static)
+    }
+
+    #[test]
+    fn only_dynamic_bodies_record_impacts() {
+        let mut block = square(Vector2f::new(0.0, -29.0), 20.0, false);
+        block.linear_velocity = Vector2f::new(0.0, 500.0);
+        let mut ground = square(Vector2f::zero(), 40.0, true);
+
+        let collision = ContactBody::Dynamic(&mut block).collide_with(&mut ContactBody::Static(&ground), DT).unwrap();
+        ContactBody::Dynamic(&mut block).resolve_collision(&mut ContactBody::Static(&ground), &collision, &MaterialTable::default());
+        assert!(block.impact.is_some());
+        assert!(ground.impact.is_none());
+
+        // Recording them would change nothing, since static bodies never break
+        ground.impact = Some(Impact { impulse: Real::MAX, point: Vector2f::zero() });
+        assert!(impact_ratio(&ground, ground.impact.as_ref().unwrap()).is_none());
+    }
+
+    #[test]
+    fn dynamic_circle_is_moved_out_of_polygon() {
+        let mut ball = circle(Vector2f::new(5.0, 0.0), 10.0, false);
+        let ground = square(Vector2f::zero(), 40.0, true);
+        ContactBody::Dynamic(&mut ball).collide_with(&mut ContactBody::Static(&ground), DT).unwrap();
+        assert!(!ground.shape.contains_point(ball.shape.get_center()));
+    }
+
+    #[test]
+    fn polygon_is_pushed_off_static_circle() {
+        let post = circle(Vector2f::new(5.0, 0.0), 10.0, true);
+        let mut crate_body = square(Vector2f::zero(), 40.0, false);
+        ContactBody::Dynamic(&mut crate_body).collide_with(&mut ContactBody::Static(&post), DT).unwrap();
+        let overlap = shape_vs_shape(&crate_body.shape, &post.shape).map_or(0.0, |c| c.sep_or_t);
+        assert!(overlap > -1e-6);
+    }
+
+    // A thin plank with no damping, so the air is the only thing taking energy out of it
+    fn plank(rotation: Real, velocity: Vector2f<Real>, angular_velocity: Real) -> RigidBody {
+        let shape = ShapeType::Polygon(Polygon::new_rectangle(Vector2f::zero(), 120.0, 8.0, rotation));
+        let mut body = RigidBody::new(shape, WOOD, false);
+        body.linear_velocity = velocity;
+        body.angular_velocity = angular_velocity;
+        body.linear_damping = Some(0.0);
+        body.angular_damping = Some(0.0);
+        body
+    }
+
+    fn mechanical_energy(body: &RigidBody, physics: &PhysicsData) -> Real {
+        let props = body.mass_properties();
+        0.5 * props.mass * body.linear_velocity.len_squared()
+            + 0.5 * props.inertia * body.angular_velocity * body.angular_velocity
+            - props.mass * physics.gravity.dot(body.shape.get_center())
+    }
+
+    fn assert_loses_energy(mut body: RigidBody, physics: &PhysicsData) {
+        let mut energy = mechanical_energy(&body, physics);
+        for step in 0..600 {
+            body.update_velocity(physics);
+            body.update_position(physics.dt);
+            let next = mechanical_energy(&body, physics);
+            assert!(next <= energy + 1e-9 * energy.abs(), "energy rose from {} to {} at step {}", energy, next, step);
+            energy = next;
         }
     }
 
-    pub fn scale(&self, ratio: f64) -> Self {
-        Self::new(self.shape.scale(ratio), self.material, self.is_static)
+    #[test]
+    fn lift_opposes_velocity_along_normal() {
+        let physics = PhysicsData { gravity: Vector2f::zero(), ..Default::default() };
+        let normal = Vector2f::new(0.0, 1.0);
+        for attack in [0.3, -0.3, 0.8, -0.8] {
+            let velocity = Vector2f::new(Real::cos(attack), Real::sin(attack)) * 400.0;
+            let mut body = plank(0.0, velocity, 0.0);
+            body.update_velocity(&physics);
+            assert!(body.linear_velocity.dot(normal).abs() < velocity.dot(normal).abs());
+        }
     }
 
-    pub fn draw(&self, transform: Matrix2d, texture: &Texture, c: Context, gl: &mut GlGraphics) {
-        self.mesh.draw(
-            transform.trans_pos(self.shape.get_center()).rot_rad(self.shape.get_rotation()), 
-            color::WHITE, texture, c, gl);
+    #[test]
+    fn tumbling_plank_loses_energy() {
+        let physics = PhysicsData { gravity: Vector2f::zero(), ..Default::default() };
+        for rotation in [0.3, 1.0, 2.0, -0.7] {
+            assert_loses_energy(plank(rotation, Vector2f::new(400.0, 0.0), 3.0), &physics);
+        }
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn falling_plank_loses_energy() {
+        let physics = PhysicsData::default();
+        for rotation in [0.2, 0.8, -0.5] {
+            assert_loses_energy(plank(rotation, Vector2f::new(150.0, 0.0), 0.0), &physics);
+        }
+    }
+
+    // Area weighted centroid of a simple polygon
+    fn centroid(verts: &[Vector2f<Real>]) -> Vector2f<Real> {
+        let (mut sum, mut area) = (Vector2f::zero(), 0.0);
+        for i in 0..verts.len() {
+            let (a, b) = (verts[i], verts[(i + 1) % verts.len()]);
+            sum += (a + b) * a.cross(b);
+            area += a.cross(b);
+        }
+        sum / (3.0 * area)
+    }
+
+    #[test]
+    fn center_of_mass_is_the_shape_origin() {
+        // An L shape, whose centroid is well away from the middle of its bounding box
+        let verts = vec![
+            Vector2f::new(0.0, 0.0), Vector2f::new(60.0, 0.0), Vector2f::new(60.0, 20.0),
+            Vector2f::new(20.0, 20.0), Vector2f::new(20.0, 60.0), Vector2f::new(0.0, 60.0),
+        ];
+        let polygon = Polygon::new(verts, Vector2f::new(100.0, 100.0), 0.3);
+        assert!(centroid(&polygon.local_vertices).len() < 1e-3);
+        let body = RigidBody::new(ShapeType::Polygon(polygon), WOOD, false);
+        assert!(body.mass_properties().local_center.len() < 1e-3);
+
+        let compound = Compound::new(vec![
+            ShapeType::Polygon(Polygon::new_square(Vector2f::zero(), 40.0, 0.0)),
+            ShapeType::Circle(Circle::new(Vector2f::new(45.0, 10.0), 10.0, 0.0)),
+        ], Vector2f::zero(), 0.0);
+        let moment = compound.get_children().iter().fold(Vector2f::zero(), |sum, child| sum + child.get_center() * child.area());
+        assert!(moment.len() < 1e-2);
+        let body = RigidBody::new(ShapeType::Compound(compound), WOOD, false);
+        assert!(body.mass_properties().local_center.len() < 1e-3);
+    }
+
+    #[test]
+    fn heightfield_center_of_mass_is_offset_from_its_origin() {
+        // A ramp from 0 to 30 over a single column is a triangle, whose centroid is a third of the
+        // way in from the tall side
+        let mut ramp = Heightfield::flat(Vector2f::new(50.0, 200.0), 30.0, 2, 0.0);
+        ramp.heights[1] = 30.0;
+        let props = RigidBody::new(ShapeType::Heightfield(ramp), WOOD, true).mass_properties();
+        assert!(props.local_center.nearly_equal(Vector2f::new(20.0, -10.0), 1e-3), "{:?}", props.local_center);
+
+        // Flat ground is a rectangle centered half way up and along
+        let ground = Heightfield::flat(Vector2f::zero(), 10.0, 5, 8.0);
+        let props = RigidBody::new(ShapeType::Heightfield(ground), WOOD, true).mass_properties();
+        assert!(props.local_center.nearly_equal(Vector2f::new(20.0, -4.0), 1e-3), "{:?}", props.local_center);
+    }
+}
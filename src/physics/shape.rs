@@ -1,5 +1,6 @@
 use graphics::math::Matrix2d;
 
+use crate::utils::real::Real;
 use crate::Vector2f;
 use crate::GlGraphics;
 
@@ -10,14 +11,17 @@ pub trait Renderable {
 }
 
 pub trait Shape : Renderable {
-    fn area(&self) -> f64;
+    fn area(&self) -> Real;
 
-    fn momemnt_of_inertia(&self) -> f64;
+    fn momemnt_of_inertia(&self) -> Real;
 
     fn get_aabb(&self) -> AABB;
 
-    fn contains_point(&self, point: Vector2f<f64>) -> bool;
+    fn contains_point(&self, point: Vector2f<Real>) -> bool;
 
     // Returns closest surface point and surface normal
-    fn find_closest_surface_point(&self, point: Vector2f<f64>) -> (Vector2f<f64>, Vector2f<f64>);
+    fn find_closest_surface_point(&self, point: Vector2f<Real>) -> (Vector2f<Real>, Vector2f<Real>);
+
+    // Returns the point of the shape furthest along the direction, used by GJK and EPA
+    fn support(&self, direction: Vector2f<Real>) -> Vector2f<Real>;
 }
\ No newline at end of file
@@ -1,18 +1,30 @@
 use graphics::math::Matrix2d;
 
+use crate::utils::real::Real;
+use crate::utils::transform::{Rot2, Transform2};
 use crate::Vector2f;
 use crate::physics::shape::Renderable;
 use crate::physics::circle::Circle;
 use crate::physics::polygon::Polygon;
+use crate::physics::compound::Compound;
+use crate::physics::capsule::Capsule;
+use crate::physics::chain::Chain;
+use crate::physics::heightfield::Heightfield;
 use crate::GlGraphics;
 use crate::physics::shape::Shape;
 
 use super::collision::AABB;
 
+const CIRCLE_OUTLINE_SIDES: u32 = 32;
+
 #[derive(Clone)]
 pub enum ShapeType {
     Circle(Circle),
     Polygon(Polygon),
+    Capsule(Capsule),
+    Compound(Compound),
+    Chain(Chain),
+    Heightfield(Heightfield),
 }
 
 impl Renderable for ShapeType {
@@ -20,22 +32,34 @@ impl Renderable for ShapeType {
         match self {
             ShapeType::Circle(circle) => circle.draw(transform, gl, color),
             ShapeType::Polygon(poly) => poly.draw(transform, gl, color),
+            ShapeType::Capsule(capsule) => capsule.draw(transform, gl, color),
+            ShapeType::Compound(compound) => compound.draw(transform, gl, color),
+            ShapeType::Chain(chain) => chain.draw(transform, gl, color),
+            ShapeType::Heightfield(heightfield) => heightfield.draw(transform, gl, color),
         }
     }
 }
 
 impl Shape for ShapeType {
-    fn area(&self) -> f64 {
+    fn area(&self) -> Real {
         match self {
             ShapeType::Circle(c) => c.area(),
             ShapeType::Polygon(p) => p.area(),
+            ShapeType::Capsule(c) => c.area(),
+            ShapeType::Compound(c) => c.area(),
+            ShapeType::Chain(c) => c.area(),
+            ShapeType::Heightfield(h) => h.area(),
         }
     }   
 
-    fn momemnt_of_inertia(&self) -> f64 {
+    fn momemnt_of_inertia(&self) -> Real {
         match self {
             ShapeType::Circle(c) => c.momemnt_of_inertia(),
             ShapeType::Polygon(p) => p.momemnt_of_inertia(),
+            ShapeType::Capsule(c) => c.momemnt_of_inertia(),
+            ShapeType::Compound(c) => c.momemnt_of_inertia(),
+            ShapeType::Chain(c) => c.momemnt_of_inertia(),
+            ShapeType::Heightfield(h) => h.momemnt_of_inertia(),
         }
     }
 
@@ -43,76 +67,208 @@ impl Shape for ShapeType {
         match self {
             ShapeType::Circle(c) => c.get_aabb(),
             ShapeType::Polygon(p) => p.get_aabb(),
+            ShapeType::Capsule(c) => c.get_aabb(),
+            ShapeType::Compound(c) => c.get_aabb(),
+            ShapeType::Chain(c) => c.get_aabb(),
+            ShapeType::Heightfield(h) => h.get_aabb(),
         }
     }
 
-    fn contains_point(&self, point: Vector2f<f64>) -> bool {
+    fn contains_point(&self, point: Vector2f<Real>) -> bool {
         match self {
             ShapeType::Circle(c) => c.contains_point(point),
-            ShapeType::Polygon(p) => p.contains_point(point)
+            ShapeType::Polygon(p) => p.contains_point(point),
+            ShapeType::Capsule(c) => c.contains_point(point),
+            ShapeType::Compound(c) => c.contains_point(point),
+            ShapeType::Chain(c) => c.contains_point(point),
+            ShapeType::Heightfield(h) => h.contains_point(point),
         }
     }
 
-    fn find_closest_surface_point(&self, point: Vector2f<f64>) -> (Vector2f<f64>, Vector2f<f64>) {
+    fn find_closest_surface_point(&self, point: Vector2f<Real>) -> (Vector2f<Real>, Vector2f<Real>) {
         match self {
             ShapeType::Circle(c) => c.find_closest_surface_point(point),
             ShapeType::Polygon(p) => p.find_closest_surface_point(point),
+            ShapeType::Capsule(c) => c.find_closest_surface_point(point),
+            ShapeType::Compound(c) => c.find_closest_surface_point(point),
+            ShapeType::Chain(c) => c.find_closest_surface_point(point),
+            ShapeType::Heightfield(h) => h.find_closest_surface_point(point),
+        }
+    }
+
+    fn support(&self, direction: Vector2f<Real>) -> Vector2f<Real> {
+        match self {
+            ShapeType::Circle(c) => c.support(direction),
+            ShapeType::Polygon(p) => p.support(direction),
+            ShapeType::Capsule(c) => c.support(direction),
+            ShapeType::Compound(c) => c.support(direction),
+            ShapeType::Chain(c) => c.support(direction),
+            ShapeType::Heightfield(h) => h.support(direction),
         }
     }
 }
 
 impl ShapeType {
-    pub fn get_center(&self) -> Vector2f<f64> {
+    pub fn get_center(&self) -> Vector2f<Real> {
         match self {
             ShapeType::Circle(c) => c.center,
-            ShapeType::Polygon(p) => p.center,
+            ShapeType::Polygon(p) => p.get_center(),
+            ShapeType::Capsule(c) => c.center,
+            ShapeType::Compound(c) => c.get_center(),
+            ShapeType::Chain(c) => c.center,
+            ShapeType::Heightfield(h) => h.origin,
         }
     }
     
-    pub fn set_center(&mut self, position: Vector2f<f64>) {
+    pub fn set_center(&mut self, position: Vector2f<Real>) {
         match self {
             ShapeType::Circle(c) => c.center = position,
-            ShapeType::Polygon(p) => p.center = position,
+            ShapeType::Polygon(p) => p.set_center(position),
+            ShapeType::Capsule(c) => c.center = position,
+            ShapeType::Compound(c) => c.set_center(position),
+            ShapeType::Chain(c) => c.center = position,
+            ShapeType::Heightfield(h) => h.origin = position,
         }
     }
 
-    pub fn translate(&mut self, translation: Vector2f<f64>) {
+    pub fn translate(&mut self, translation: Vector2f<Real>) {
         match self {
             ShapeType::Circle(c) => c.center += translation,
-            ShapeType::Polygon(p) => p.center += translation,
+            ShapeType::Polygon(p) => p.translate(translation),
+            ShapeType::Capsule(c) => c.center += translation,
+            ShapeType::Compound(c) => c.translate(translation),
+            ShapeType::Chain(c) => c.center += translation,
+            ShapeType::Heightfield(h) => h.origin += translation,
         }
     }
 
-    pub fn get_rotation(&self) -> f64 {
+    pub fn get_rotation(&self) -> Real {
         match self {
             ShapeType::Circle(c) => c.rotation,
-            ShapeType::Polygon(p) => p.rotation,
+            ShapeType::Polygon(p) => p.get_rotation(),
+            ShapeType::Capsule(c) => c.rotation,
+            ShapeType::Compound(c) => c.get_rotation(),
+            ShapeType::Chain(c) => c.rotation,
+            ShapeType::Heightfield(_) => 0.0,
         }
     } 
 
-    #[allow(dead_code)]
-    pub fn set_rotation(&mut self, rotation: f64) {
+    // Takes points from the local space of the shape into world space
+    // The area weighted centroid in the local space of the shape
+    pub fn local_centroid(&self) -> Vector2f<Real> {
+        match self {
+            ShapeType::Circle(_) | ShapeType::Capsule(_) => Vector2f::zero(),
+            ShapeType::Polygon(p) => p.local_centroid(),
+            ShapeType::Compound(c) => c.local_centroid(),
+            // Chains have no area, their vertices are placed around their average
+            ShapeType::Chain(_) => Vector2f::zero(),
+            ShapeType::Heightfield(h) => h.local_centroid(),
+        }
+    }
+
+    pub fn get_transform(&self) -> Transform2<Real> {
+        Transform2::new(self.get_center(), self.get_rotation())
+    }
+
+    // Moves and turns the shape at once, so shapes that cache their world space data only
+    // rebuild it once
+    pub fn set_transform(&mut self, center: Vector2f<Real>, rotation: Real) {
+        match self {
+            ShapeType::Polygon(p) => p.set_transform(center, rotation),
+            ShapeType::Compound(c) => c.set_transform(center, rotation),
+            _ => {
+                self.set_center(center);
+                self.set_rotation(rotation);
+            }
+        }
+    }
+
+    pub fn set_rotation(&mut self, rotation: Real) {
         match self {
             ShapeType::Circle(c) => c.rotation = rotation,
-            ShapeType::Polygon(p) => p.rotation = rotation,
+            ShapeType::Polygon(p) => p.set_rotation(rotation),
+            ShapeType::Capsule(c) => c.rotation = rotation,
+            ShapeType::Compound(c) => c.set_rotation(rotation),
+            ShapeType::Chain(c) => c.rotation = rotation,
+            // Heightfields are always level
+            ShapeType::Heightfield(_) => {}
         }
     }
 
-    pub fn rotate(&mut self, radians: f64) {
+    pub fn rotate(&mut self, radians: Real) {
         match self {
             ShapeType::Circle(c) => c.rotation += radians,
-            ShapeType::Polygon(p) => p.rotation += radians,
+            ShapeType::Polygon(p) => p.rotate(radians),
+            ShapeType::Capsule(c) => c.rotation += radians,
+            ShapeType::Compound(c) => c.rotate(radians),
+            ShapeType::Chain(c) => c.rotation += radians,
+            // Heightfields are always level
+            ShapeType::Heightfield(_) => {}
+        }
+    }
+
+    // Returns the width of the shape seen from the given direction
+    pub fn projected_width(&self, direction: Vector2f<Real>) -> Real {
+        match self {
+            ShapeType::Circle(c) => c.radius * 2.0,
+            ShapeType::Capsule(c) => {
+                let axis = Rot2::new(c.rotation).apply(Vector2f::new(1.0, 0.0));
+                axis.cross(direction.normalize()).abs() * c.half_length * 2.0 + c.radius * 2.0
+            }
+            _ => {
+                let axis = direction.perpendicular();
+                let mut min = Real::INFINITY;
+                let mut max = Real::NEG_INFINITY;
+                for v in self.get_convex_outlines().iter().flatten() {
+                    let proj = v.dot(axis);
+                    min = min.min(proj);
+                    max = max.max(proj);
+                }
+                max - min
+            }
+        }
+    }
+
+    // Returns the world space outlines of the convex pieces making up the shape, circles are 
+    // approximated by a regular polygon. Chains have no area, so they have no outlines
+    pub fn get_convex_outlines(&self) -> Vec<Vec<Vector2f<Real>>> {
+        match self {
+            ShapeType::Circle(c) => vec![Polygon::new_regular_polygon(CIRCLE_OUTLINE_SIDES, c.radius, c.center, c.rotation).get_transformed_vertices().to_vec()],
+            ShapeType::Polygon(p) => p.get_transformed_parts().to_vec(),
+            ShapeType::Capsule(c) => vec![c.get_outline(CIRCLE_OUTLINE_SIDES / 2)],
+            ShapeType::Compound(c) => c.get_transformed_children().iter().flat_map(|child| child.get_convex_outlines()).collect(),
+            ShapeType::Chain(_) => vec![],
+            ShapeType::Heightfield(h) => h.get_local_columns().into_iter()
+                .map(|column| column.into_iter().map(|v| v + h.origin).collect()).collect(),
         }
     }
 
     // Returns a clone of the given shape scaled by the ratio
-    pub fn scale(&self, ratio: f64) -> Self {
+    pub fn scale(&self, ratio: Real) -> Self {
         match self {
             ShapeType::Circle(c) => ShapeType::Circle(Circle::new(c.center, c.radius * ratio, c.rotation)),
             ShapeType::Polygon(p) => {
                 let verts = p.local_vertices.iter().map(|&v| v * ratio).collect();
-                ShapeType::Polygon(Polygon::new(verts, p.center, p.rotation))
+                ShapeType::Polygon(Polygon::new(verts, p.get_center(), p.get_rotation()))
             } 
+            ShapeType::Capsule(c) => ShapeType::Capsule(Capsule::new(c.center, c.half_length * 2.0 * ratio, c.radius * ratio, c.rotation)),
+            ShapeType::Compound(c) => {
+                let children = c.get_children().iter().map(|child| {
+                    let mut child = child.scale(ratio);
+                    child.set_center(child.get_center() * ratio);
+                    child
+                }).collect();
+                ShapeType::Compound(Compound::new(children, c.get_center(), c.get_rotation()))
+            }
+            ShapeType::Chain(c) => ShapeType::Chain(Chain {
+                local_vertices: c.local_vertices.iter().map(|&v| v * ratio).collect(),
+                ..c.clone()
+            }),
+            ShapeType::Heightfield(h) => ShapeType::Heightfield(Heightfield {
+                heights: h.heights.iter().map(|&height| height * ratio).collect(),
+                spacing: h.spacing * ratio,
+                origin: h.origin,
+            }),
         }
     }
 }
\ No newline at end of file
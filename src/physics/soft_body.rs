@@ -1,41 +1,40 @@
-use core::f64;
-use std::{cell::{RefCell}, rc::Rc};
 
 use graphics::{ellipse, line, math::Matrix2d, rectangle::square, Context};
 use opengl_graphics::GlGraphics;
 use graphics::color;
+use crate::utils::real::{to_f64, Real};
 use crate::{game::{ContactDebug, PhysicsData}, Vector2f};
 use crate::utils::helpers::*;
 use super::collision::*;
-use super::{rigid_body::RigidBody, shape::Shape, shape_type::ShapeType};
+use super::{arena::{BodyArena, BodyHandle}, rigid_body::RigidBody, shape::Shape};
+use super::material::*;
 
 // The soft body string is implemented based on Position Based Dynamics 
 // source: https://matthias-research.github.io/pages/publications/posBasedDyn.pdf
 
 #[derive(Clone)]
 pub struct Attachment {
-    pub obj_ref: Rc<RefCell<RigidBody>>,
-    pub rel_pos: Vector2f<f64>, 
+    pub body: BodyHandle,
+    pub rel_pos: Vector2f<Real>, 
 }
 
 impl Attachment {
-    pub fn get_attachment_point(&self) -> Vector2f<f64> {
-        let obj = self.obj_ref.borrow();
-        obj.shape.get_center() + self.rel_pos.rotate(obj.shape.get_rotation())
+    pub fn get_attachment_point(&self, obj: &RigidBody) -> Vector2f<Real> {
+        obj.shape.get_transform().apply(self.rel_pos)
     }
 }
 
 #[derive(Clone)]
 pub struct Joint {
-    pub position: Vector2f<f64>,
-    pub predicted_position: Vector2f<f64>,
-    pub velocity: Vector2f<f64>,
-    pub mass: f64,
+    pub position: Vector2f<Real>,
+    pub predicted_position: Vector2f<Real>,
+    pub velocity: Vector2f<Real>,
+    pub mass: Real,
     pub attachment: Option<Attachment>,
 }
 
 impl Joint {
-    pub fn new(position: Vector2f<f64>, attachment: Option<Attachment>) -> Self {
+    pub fn new(position: Vector2f<Real>, attachment: Option<Attachment>) -> Self {
         Self { 
             position, 
             predicted_position: position, 
@@ -45,11 +44,17 @@ impl Joint {
         }
     }
 
-    fn get_inv_mass(&self) -> f64 {
-        if let Some(attachment) = &self.attachment {
-            attachment.obj_ref.borrow().get_inv_mass()
-        } else {
-            1.0 / self.mass
+    // Attached joints move with their body, so only free joints are pushed
+    pub fn apply_impulse(&mut self, impulse: Vector2f<Real>) {
+        if self.attachment.is_none() {
+            self.velocity += impulse / self.mass;
+        }
+    }
+
+    fn get_inv_mass(&self, bodies: &BodyArena) -> Real {
+        match self.attachment.as_ref().and_then(|att| bodies.get(att.body)) {
+            Some(obj) => obj.get_inv_mass(),
+            None => 1.0 / self.mass,
         }
     }
 }
@@ -58,38 +63,42 @@ impl Joint {
 pub struct Constraint {
     pub index_a: usize,
     pub index_b: usize,
-    pub rest_length: f64,
-    pub tear_length: f64,
-    pub stiffness: f64,    
+    pub rest_length: Real,
+    pub tear_length: Real,
+    pub stiffness: Real,    
 }
 
 #[derive(Clone)]
 pub struct CollisionConstraint {
     pub index: usize,
-    pub contact_point: Vector2f<f64>,
-    pub normal: Vector2f<f64>,
-    pub object: Rc<RefCell<RigidBody>>,
+    pub contact_point: Vector2f<Real>,
+    pub normal: Vector2f<Real>,
+    pub object: BodyHandle,
 }
 
 #[derive(Clone)]
 pub struct SoftBody {
     pub joints: Vec<Joint>,
     pub constraints: Vec<Constraint>,
-    pub damping: f64,
+    pub damping: Real,
+    // Strings without a material collide with the coefficients of whatever they touch
+    pub material: Option<Material>,
 }
 
-const BASE_DAMPING: f64 = 0.2;
+const BASE_DAMPING: Real = 0.2;
 const CONSTRAINT_ITERATIONS: usize = 8;
-const BASE_STIFFNESS: f64 = 0.9;
+const BASE_STIFFNESS: Real = 0.9;
 #[allow(dead_code)]
-const BASE_REST_LENGTH: f64 = 10.0;
-const BASE_JOINT_MASS: f64 = 50.0;
+const BASE_REST_LENGTH: Real = 10.0;
+const BASE_JOINT_MASS: Real = 50.0;
 #[allow(dead_code)]
-const BASE_TEAR_LENGTH: f64 = 100.0;
+const BASE_TEAR_LENGTH: Real = 100.0;
+// Joints are points, this is how big they are drawn and how close a query has to be to touch them
+pub const JOINT_RADIUS: Real = 2.5;
 
 impl From<Vec<Joint>> for SoftBody {
     fn from(joints: Vec<Joint>) -> Self {
-        let stiffness = 1.0 - f64::powf(1.0 - BASE_STIFFNESS, 1.0 / CONSTRAINT_ITERATIONS as f64);
+        let stiffness = 1.0 - Real::powf(1.0 - BASE_STIFFNESS, 1.0 / CONSTRAINT_ITERATIONS as Real);
         let mut constraints = vec![];
 
         if joints.len() >= 2 {
@@ -108,28 +117,29 @@ impl From<Vec<Joint>> for SoftBody {
         Self { 
             joints,
             constraints, 
-            damping: BASE_DAMPING 
+            damping: BASE_DAMPING,
+            material: None,
         }
     }
 }
 
 #[allow(dead_code)]
 impl SoftBody {
-    pub fn new_string(start_position: Vector2f<f64>, end_position: Vector2f<f64>, num_joints: usize) -> Self {
+    pub fn new_string(start_position: Vector2f<Real>, end_position: Vector2f<Real>, num_joints: usize) -> Self {
         let rel_pos = end_position - start_position;
         let dir = rel_pos.normalize();
         let length = rel_pos.len();
 
-        let rest_length = length / (num_joints - 1) as f64;
+        let rest_length = length / (num_joints - 1) as Real;
         let tear_length = 2.0 * rest_length;
 
-        let stiffness = 1.0 - f64::powf(1.0 - BASE_STIFFNESS, 1.0 / CONSTRAINT_ITERATIONS as f64);
+        let stiffness = 1.0 - Real::powf(1.0 - BASE_STIFFNESS, 1.0 / CONSTRAINT_ITERATIONS as Real);
         let mass = BASE_JOINT_MASS;
 
         let mut joints = vec![];
         let mut constraints = vec![];
         for i in 0..num_joints {
-            let position = start_position + (dir * rest_length * i as f64);
+            let position = start_position + (dir * rest_length * i as Real);
             let joint = Joint {  
                 position,
                 predicted_position: position,
@@ -156,10 +166,11 @@ impl SoftBody {
             joints, 
             constraints,
             damping: BASE_DAMPING,
+            material: None,
         }
     }
 
-    pub fn add_joint(&mut self, position: Vector2f<f64>, attachment: Option<Attachment>) {
+    pub fn add_joint(&mut self, position: Vector2f<Real>, attachment: Option<Attachment>) {
         let new_joint = Joint {
             position,
             predicted_position: position,
@@ -168,7 +179,7 @@ impl SoftBody {
             attachment,
         };
 
-        let stiffness = 1.0 - f64::powf(1.0 - BASE_STIFFNESS, 1.0 / CONSTRAINT_ITERATIONS as f64);
+        let stiffness = 1.0 - Real::powf(1.0 - BASE_STIFFNESS, 1.0 / CONSTRAINT_ITERATIONS as Real);
         let n = self.joints.len();
         let rest_length = (self.joints[n - 1].position - position).len();
         let new_constraint = Constraint {
@@ -183,14 +194,12 @@ impl SoftBody {
         self.constraints.push(new_constraint);
     }
 
-    fn damp_velocities(&mut self) {
+    fn damp_velocities(&mut self, bodies: &BodyArena) {
         let mut joints_and_masses = vec![];
         for joint in self.joints.as_mut_slice() {
-            let mass = if let Some(att) = &joint.attachment {
-                let obj = att.obj_ref.borrow();
-                obj.shape.area() * obj.material.density
-            } else {
-                joint.mass
+            let mass = match joint.attachment.as_ref().and_then(|att| bodies.get(att.body)) {
+                Some(obj) => obj.mass_properties().mass,
+                None => joint.mass,
             };
             joints_and_masses.push((joint, mass));
         }
@@ -225,27 +234,28 @@ impl SoftBody {
     pub fn resolve_constraints(
         &mut self, 
         physics: &PhysicsData, 
-        objects: &Vec<Rc<RefCell<RigidBody>>>, 
+        bodies: &mut BodyArena, 
         contacts: &mut Vec<ContactDebug>
     ) -> Option<SoftBody> {
         let dt = physics.dt;
+        self.detach_removed(bodies);
         for joint in self.joints.as_mut_slice() {
-            if let Some(att) = &joint.attachment {
-                joint.position = att.get_attachment_point();
-                joint.velocity = att.obj_ref.borrow().linear_velocity;
+            if let Some(att) = &joint.attachment && let Some(obj) = bodies.get(att.body) {
+                joint.position = att.get_attachment_point(obj);
+                joint.velocity = obj.linear_velocity;
             } else {
                 joint.velocity += physics.gravity * dt;
                 joint.velocity *= 1.0 - physics.air_density * dt;
             }
         }
 
-        self.damp_velocities();
+        self.damp_velocities(bodies);
 
         for joint in self.joints.as_mut_slice() {
             joint.predicted_position = joint.position + joint.velocity * dt;
         }
 
-        let collision_constraints = self.generate_collision_constraints(dt, objects);
+        let collision_constraints = self.generate_collision_constraints(dt, bodies);
         for c in collision_constraints.as_slice() {
             contacts.push(ContactDebug { contact: c.contact_point, normal: c.normal });
         }
@@ -263,8 +273,8 @@ impl SoftBody {
                 } 
                 
                 if stretch > 0.0 {
-                    let a_inv_mass = a.get_inv_mass();
-                    let b_inv_mass = b.get_inv_mass();
+                    let a_inv_mass = a.get_inv_mass(bodies);
+                    let b_inv_mass = b.get_inv_mass(bodies);
                     let denom = a_inv_mass + b_inv_mass;
                     let normal = rel_pos / dist;
                     
@@ -280,16 +290,19 @@ impl SoftBody {
             for constraint in collision_constraints.as_slice() {
                 let normal = constraint.normal;
                 let joint = &mut self.joints[constraint.index];
-                let joint_inv_mass = joint.get_inv_mass();
+                let joint_inv_mass = joint.get_inv_mass(bodies);
 
-                let mut obj = constraint.object.borrow_mut();
-                let obj_inv_mass = obj.get_inv_mass();
+                let Some(obj) = bodies.get_mut(constraint.object) else {
+                    continue;
+                };
+                let obj_inv_mass = obj.get_inv_mass_along(normal);
                 
                 let rel_pos = joint.predicted_position - constraint.contact_point;
                 let depth = rel_pos.dot(normal);
                 if depth < 0.0 {
                     let denom = joint_inv_mass + obj_inv_mass;
-                    obj.linear_velocity += normal * depth * obj_inv_mass / (denom * dt);
+                    let push = obj.lock_axes(normal) * depth * obj.get_inv_mass() / (denom * dt);
+                    obj.linear_velocity += push;
                     joint.predicted_position += -normal * depth;
                 }
             }
@@ -299,9 +312,9 @@ impl SoftBody {
         for joint in self.joints.as_mut_slice() {
             let next_velocity = (joint.predicted_position - joint.position) / dt;
             if let Some(att) = &joint.attachment {
-                let mut obj = att.obj_ref.borrow_mut();
-                if !obj.is_static {
-                    obj.linear_velocity += next_velocity - joint.velocity;
+                if let Some(obj) = bodies.get_mut(att.body) && !obj.is_static {
+                    let pull = obj.lock_axes(next_velocity - joint.velocity);
+                    obj.linear_velocity += pull;
                 }
             } else {
                 joint.position = joint.predicted_position; 
@@ -309,23 +322,29 @@ impl SoftBody {
             }
         }
 
-        self.resolve_collisions(&collision_constraints);
+        self.resolve_collisions(&collision_constraints, bodies, &physics.material_pairs);
 
         None
     }
 
-    fn resolve_collisions(&mut self, collision_constraints: &Vec<CollisionConstraint>) {
+    fn resolve_collisions(&mut self, collision_constraints: &Vec<CollisionConstraint>, bodies: &mut BodyArena, materials: &MaterialTable) {
         for constraint in collision_constraints {
             let p = &mut self.joints[constraint.index];
-            let p_inv_mass = p.get_inv_mass();
+            let p_inv_mass = p.get_inv_mass(bodies);
 
-            let mut obj = constraint.object.borrow_mut();
+            let Some(obj) = bodies.get_mut(constraint.object) else {
+                continue;
+            };
             let obj_inv_mass = obj.get_inv_mass();
             let obj_inv_inertia = obj.get_inv_inertia();
 
-            let restitution = obj.material.restitution;
-            let sf = obj.material.static_friction;
-            let df = obj.material.dynamic_friction;
+            let pair = match &self.material {
+                Some(material) => materials.combine(material, &obj.material),
+                None => MaterialPair::from(&obj.material),
+            };
+            let restitution = pair.restitution;
+            let sf = pair.static_friction;
+            let df = pair.dynamic_friction;
 
             let normal = constraint.normal;
             let r = constraint.contact_point - obj.shape.get_center();
@@ -336,14 +355,15 @@ impl SoftBody {
             }
             
             let v_rel = -(1.0 + restitution) * relative_velocity.dot(normal);
-            let denom = (p_inv_mass + obj_inv_mass + (f64::powi(r.cross(normal), 2) * obj_inv_inertia)).max(f64::EPSILON);
+            let denom = (p_inv_mass + obj.get_inv_mass_along(normal) + (Real::powi(r.cross(normal), 2) * obj_inv_inertia)).max(Real::EPSILON);
             let j = v_rel / denom;
             let p_impulse = normal * j;
             let obj_impulse = normal * -j;
 
             // Apply impulse
             p.velocity += p_impulse * p_inv_mass;
-            obj.linear_velocity += obj_impulse * obj_inv_mass;
+            let obj_dv = obj.lock_axes(obj_impulse) * obj_inv_mass;
+            obj.linear_velocity += obj_dv;
             obj.angular_velocity += r.cross(obj_impulse) * obj_inv_inertia;
 
             // Compute friction
@@ -355,7 +375,7 @@ impl SoftBody {
             tangent = tangent.normalize();
             
             let v_rel = -relative_velocity.dot(tangent);
-            let denom = (p_inv_mass + obj_inv_mass + (f64::powi(r.cross(tangent), 2) * obj_inv_inertia)).max(f64::EPSILON);
+            let denom = (p_inv_mass + obj.get_inv_mass_along(tangent) + (Real::powi(r.cross(tangent), 2) * obj_inv_inertia)).max(Real::EPSILON);
 
             let mut jt = v_rel / denom;
             if jt.abs() > -j * sf {
@@ -366,7 +386,8 @@ impl SoftBody {
             let obj_friction_impulse = tangent * -jt;
 
             p.velocity += p_friction_impulse * p_inv_mass;
-            obj.linear_velocity += obj_friction_impulse * obj_inv_mass;
+            let obj_dv = obj.lock_axes(obj_friction_impulse) * obj_inv_mass;
+            obj.linear_velocity += obj_dv;
             obj.angular_velocity += r.cross(obj_friction_impulse) * obj_inv_inertia; 
 
         }
@@ -394,17 +415,27 @@ impl SoftBody {
                 joints: self.joints.split_off(i + 1),
                 constraints,
                 damping: self.damping,
+                material: self.material,
             });
         }
 
         return None;
     }
 
+    // Joints attached to bodies that have been removed from the world hang free instead
+    fn detach_removed(&mut self, bodies: &BodyArena) {
+        for joint in self.joints.as_mut_slice() {
+            if joint.attachment.as_ref().is_some_and(|att| !bodies.contains(att.body)) {
+                joint.attachment = None;
+            }
+        }
+    }
+
     pub fn get_aabb(&self) -> AABB {
-        let mut min_x = f64::INFINITY;
-        let mut max_x = f64::NEG_INFINITY;
-        let mut min_y = f64::INFINITY;
-        let mut max_y = f64::NEG_INFINITY;
+        let mut min_x = Real::INFINITY;
+        let mut max_x = Real::NEG_INFINITY;
+        let mut min_y = Real::INFINITY;
+        let mut max_y = Real::NEG_INFINITY;
 
         for joint in self.joints.as_slice() {
             min_x = min_x.min(joint.position.x).min(joint.predicted_position.x);
@@ -416,11 +447,31 @@ impl SoftBody {
         AABB { top_left: Vector2f::new(min_x, min_y), bottom_right: Vector2f::new(max_x, max_y)}
     }
 
-    fn generate_collision_constraints(&mut self, dt: f64, objects: &Vec<Rc<RefCell<RigidBody>>>) -> Vec<CollisionConstraint> {
+    // The energy stored in the stretched constraints. A constraint removes the fraction
+    // 1 - (1 - stiffness)^iterations of its stretch every step, which is what a spring with
+    // k = fraction / (inv_mass * dt^2) would do, so it is counted as one.
+    pub fn spring_energy(&self, bodies: &BodyArena, dt: Real) -> Real {
+        let mut energy = 0.0;
+        for constraint in self.constraints.as_slice() {
+            let a = &self.joints[constraint.index_a];
+            let b = &self.joints[constraint.index_b];
+            let stretch = (b.position - a.position).len() - constraint.rest_length;
+            let inv_mass = a.get_inv_mass(bodies) + b.get_inv_mass(bodies);
+            if stretch <= 0.0 || inv_mass <= 0.0 {
+                continue;
+            }
+
+            let fraction = 1.0 - (1.0 - constraint.stiffness).powi(CONSTRAINT_ITERATIONS as i32);
+            let k = fraction / (inv_mass * dt * dt);
+            energy += 0.5 * k * stretch * stretch;
+        }
+        energy
+    }
+
+    fn generate_collision_constraints(&mut self, dt: Real, bodies: &BodyArena) -> Vec<CollisionConstraint> {
         let mut constraints = vec![];
         let string_aabb = self.get_aabb();
-        'obj_loop: for obj_ref in objects {
-            let obj = obj_ref.borrow_mut();
+        'obj_loop: for (handle, obj) in bodies.iter() {
             let obj_step = obj.linear_velocity * dt;
             let aabb = obj.shape.get_aabb();
 
@@ -428,7 +479,7 @@ impl SoftBody {
             for (i, joint) in self.joints.iter().enumerate() {
                 if let Some(attachment) = &joint.attachment {
                     indices_to_skip.push(i);
-                    if attachment.obj_ref.as_ptr() == obj_ref.as_ptr() && !obj.is_static {
+                    if attachment.body == handle && !obj.is_static {
                         continue 'obj_loop;
                     }
                 }
@@ -448,13 +499,10 @@ impl SoftBody {
                 let collision = if obj.shape.contains_point(ray_origin) {     
                     let (cp, normal) = obj.shape.find_closest_surface_point(joint.predicted_position);   
                     let c_cp = cp - obj.shape.get_center();
-                    Some(CollisionData { sep_or_t: (c_cp).len(), normal: normal, contacts: vec![cp] })
+                    Some(CollisionData { sep_or_t: (c_cp).len(), normal: normal, contacts: vec![cp], child_a: 0, child_b: 0 })
                 } else {
                     let ray_dir = joint.predicted_position - joint.position - obj_step;
-                    match &obj.shape {
-                        ShapeType::Circle(c) => ray_vs_circle(ray_origin, ray_dir, c),
-                        ShapeType::Polygon(p) => ray_vs_polygon(ray_origin, ray_dir, p),
-                    }
+                    ray_vs_shape(ray_origin, ray_dir, &obj.shape)
                 };
                 
                 if let Some(collision) = collision {
@@ -462,7 +510,7 @@ impl SoftBody {
                         index: i,
                         contact_point: collision.contacts[0],
                         normal: collision.normal,
-                        object: obj_ref.clone(),
+                        object: handle,
                     });
                 }
             }
@@ -474,19 +522,13 @@ impl SoftBody {
                 }
 
                 let (a, b) = (&self.joints[i], &self.joints[j]);                
-                let mut collision = match &obj.shape {
-                    ShapeType::Circle(c) => circle_vs_segment(c, a.predicted_position, b.predicted_position),
-                    ShapeType::Polygon(p) => polygon_vs_segment(p, a.predicted_position, b.predicted_position),
-                };
+                let mut collision = shape_vs_segment(&obj.shape, a.predicted_position, b.predicted_position);
 
                 // Double check using ray tracing
                 if collision.is_none() {
                     let min_dimension = aabb.width().min(aabb.height());
                     if obj.linear_velocity.len_squared() >= min_dimension * min_dimension / 2.0 {
-                        collision = match &obj.shape {
-                            ShapeType::Circle(c) => swept_circle_vs_segment(c, obj_step, a.predicted_position, b.predicted_position),
-                            ShapeType::Polygon(p) => swept_polygon_vs_segment(p, obj_step, a.predicted_position, b.predicted_position),
-                        };
+                        collision = swept_shape_vs_segment(&obj.shape, obj_step, a.predicted_position, b.predicted_position);
                     }
                 }
 
@@ -497,7 +539,7 @@ impl SoftBody {
                             index: *joint,
                             contact_point: collision.contacts[0],
                             normal: collision.normal,
-                            object: obj_ref.clone(),
+                            object: handle,
                         });
                     }
                 }
@@ -510,12 +552,13 @@ impl SoftBody {
     pub fn draw(&self, transform: Matrix2d, _: Context, gl: &mut GlGraphics) {
         for constraint in self.constraints.as_slice() {
             let (a, b) = (&self.joints[constraint.index_a], &self.joints[constraint.index_b]);
-            let l = [a.position.x, a.position.y, b.position.x, b.position.y];
+            let (a, b) = (a.position.cast::<f64>(), b.position.cast::<f64>());
+            let l = [a.x, a.y, b.x, b.y];
             line(color::RED, 2.0, l, transform, gl);
         }
 
         for joint in self.joints.as_slice() {
-            let square = square(joint.position.x - 2.5, joint.position.y - 2.5, 5.0);
+            let square = square(to_f64(joint.position.x - JOINT_RADIUS), to_f64(joint.position.y - JOINT_RADIUS), to_f64(JOINT_RADIUS * 2.0));
             ellipse(color::GREEN, square, transform, gl);
         }
     }
@@ -2,9 +2,10 @@ use graphics::{color, line, math::Matrix2d, Context};
 use opengl_graphics::{GlGraphics, Texture};
 use graphics::triangulation::*;
 use piston_window::Graphics;
+use crate::utils::real::{to_f64, Real};
 use crate::Vector2f;
 
-use super::{circle::Circle, shape_type::ShapeType, polygon::Polygon};
+use super::{capsule::Capsule, chain::Chain, heightfield::Heightfield, circle::Circle, compound::Compound, shape_type::ShapeType, polygon::Polygon};
 
 const TILE_WIDTH: u32 = 64;
 const TILE_HEIGHT: u32 = 64;
@@ -19,6 +20,8 @@ struct Mesh {
 #[derive(Clone)]
 pub struct TiledMesh {
     tiles: Vec<Mesh>,
+    // Local position of the top left corner of the tile grid
+    grid_origin: Vector2f<Real>,
 }
 
 impl TiledMesh {
@@ -46,11 +49,56 @@ impl TiledMesh {
     }
 }
 
+impl TiledMesh {
+    // Creates the mesh of a fragment cut out of this mesh, keeping the tiles aligned with the 
+    // original texture. The vertices are given in the local space of this mesh, and the 
+    // fragment mesh is centered around the offset.
+    pub fn fragment(&self, verts: &[Vector2f<Real>], offset: Vector2f<Real>) -> Self {
+        let tile_size = Vector2f::new(TILE_WIDTH as Real, TILE_HEIGHT as Real);
+        let min_x = verts.iter().map(|v| v.x).fold(Real::INFINITY, Real::min);
+        let max_x = verts.iter().map(|v| v.x).fold(Real::NEG_INFINITY, Real::max);
+        let min_y = verts.iter().map(|v| v.y).fold(Real::INFINITY, Real::min);
+        let max_y = verts.iter().map(|v| v.y).fold(Real::NEG_INFINITY, Real::max);
+
+        // Snap the start of the grid to the tiles of the original mesh
+        let start_x = self.grid_origin.x + ((min_x - self.grid_origin.x) / tile_size.x).floor() * tile_size.x;
+        let start_y = self.grid_origin.y + ((min_y - self.grid_origin.y) / tile_size.y).floor() * tile_size.y;
+        
+        let mut tiles = generate_tiles(
+            start_x, max_x, start_y, max_y, verts.to_vec());
+        for mesh in tiles.as_mut_slice() {
+            mesh.translate(-offset);
+        }
+
+        Self { 
+            tiles,
+            grid_origin: self.grid_origin - offset,
+        }
+    }
+}
+
+impl Mesh {
+    fn translate(&mut self, offset: Vector2f<Real>) {
+        for v in self.verts.as_mut_slice() {
+            v[0] += to_f64(offset.x) as f32;
+            v[1] += to_f64(offset.y) as f32;
+        }
+        for v in self.tile_verts.as_mut_slice() {
+            v[0] += to_f64(offset.x);
+            v[1] += to_f64(offset.y);
+        }
+    }
+}
+
 impl From<&ShapeType> for TiledMesh {
     fn from(value: &ShapeType) -> Self {
         match value {
             ShapeType::Circle(circle) => Self::from(circle),
             ShapeType::Polygon(poly) => Self::from(poly),
+            ShapeType::Capsule(capsule) => Self::from(capsule),
+            ShapeType::Compound(compound) => Self::from(compound),
+            ShapeType::Chain(chain) => Self::from(chain),
+            ShapeType::Heightfield(heightfield) => Self::from(heightfield),
         }
     }
 }
@@ -62,7 +110,8 @@ impl From<&Circle> for TiledMesh {
             circle.radius as u32 * 3, circle.radius, Vector2f::new(0.0, 0.0), circle.rotation).local_vertices;
 
         Self { 
-            tiles: generate_tiles(-r, r, -r, r, verts),
+            tiles: generate_tiles(-r as Real, r as Real, -r as Real, r as Real, verts),
+            grid_origin: Vector2f::new(-r as Real, -r as Real),
         }
     }
 }
@@ -76,18 +125,99 @@ impl From<&Polygon> for TiledMesh {
         let min_y = verts.iter().map(|v| v.y as i32).min().unwrap();
         let max_y = verts.iter().map(|v| v.y as i32).max().unwrap();
         
+        // The clipping only works on convex shapes, so concave polygons are tiled part by part
+        let tiles = poly.get_parts().iter().flat_map(|part| {
+            generate_tiles(min_x as Real, max_x as Real, min_y as Real, max_y as Real, part.clone())
+        }).collect();
+
+        Self { 
+            tiles,
+            grid_origin: Vector2f::new(min_x as Real, min_y as Real),
+        }
+    }
+}
+
+impl From<&Capsule> for TiledMesh {
+    fn from(capsule: &Capsule) -> Self {
+        let verts = capsule.get_local_outline(capsule.radius as u32 * 3 / 2 + 1);
+        let half_width = (capsule.half_length + capsule.radius) as i32;
+        let r = capsule.radius as i32;
+
+        Self { 
+            tiles: generate_tiles(-half_width as Real, half_width as Real, -r as Real, r as Real, verts),
+            grid_origin: Vector2f::new(-half_width as Real, -r as Real),
+        }
+    }
+}
+
+// A chain has no area, so it has no tiles
+impl From<&Chain> for TiledMesh {
+    fn from(_: &Chain) -> Self {
+        Self { 
+            tiles: vec![],
+            grid_origin: Vector2f::zero(),
+        }
+    }
+}
+
+impl From<&Heightfield> for TiledMesh {
+    fn from(heightfield: &Heightfield) -> Self {
+        let tile_size = Vector2f::new(TILE_WIDTH as Real, TILE_HEIGHT as Real);
+        let top = -heightfield.heights.iter().fold(0.0, |max: Real, &h| max.max(h));
+
+        // The columns are tiled one by one so only the tiles under each column are generated, 
+        // with the start snapped to a shared grid to keep the texture continuous
+        let tiles = heightfield.get_local_columns().into_iter().flat_map(|column| {
+            let min_y = column[0].y.min(column[1].y);
+            let start_x = (column[0].x / tile_size.x).floor() * tile_size.x;
+            let start_y = top + ((min_y - top) / tile_size.y).floor() * tile_size.y;
+            generate_tiles(start_x, column[1].x, start_y, 0.0, column)
+        }).collect();
+
+        Self { 
+            tiles,
+            grid_origin: Vector2f::new(0.0, top),
+        }
+    }
+}
+
+impl From<&Compound> for TiledMesh {
+    fn from(compound: &Compound) -> Self {
+        // Collect the convex pieces of the children in the local space of the compound
+        let mut parts = vec![];
+        for child in compound.get_children() {
+            let (center, rotation) = (child.get_center(), child.get_rotation());
+            match child {
+                ShapeType::Circle(c) => parts.push(Polygon::new_regular_polygon(
+                    c.radius as u32 * 3, c.radius, center, rotation).get_transformed_vertices().to_vec()),
+                ShapeType::Polygon(p) => parts.extend(p.get_transformed_parts().iter().cloned()),
+                ShapeType::Capsule(_) | ShapeType::Compound(_) | ShapeType::Chain(_) | ShapeType::Heightfield(_) => parts.extend(child.get_convex_outlines()),
+            }
+        }
+
+        let all_verts = parts.iter().flatten();
+        let min_x = all_verts.clone().map(|v| v.x as i32).min().unwrap_or(0);
+        let max_x = all_verts.clone().map(|v| v.x as i32).max().unwrap_or(0);
+        let min_y = all_verts.clone().map(|v| v.y as i32).min().unwrap_or(0);
+        let max_y = all_verts.map(|v| v.y as i32).max().unwrap_or(0);
+
+        let tiles = parts.into_iter().flat_map(|part| {
+            generate_tiles(min_x as Real, max_x as Real, min_y as Real, max_y as Real, part)
+        }).collect();
+
         Self { 
-            tiles: generate_tiles(min_x, max_x, min_y, max_y, verts),
+            tiles,
+            grid_origin: Vector2f::new(min_x as Real, min_y as Real),
         }
     }
 }
 
-fn generate_tiles(min_x: i32, max_x: i32,  min_y: i32, max_y: i32, clip_verts: Vec<Vector2f<f64>>) -> Vec<Mesh> {
-    let width = (max_x - min_x) as f64;
-    let height = (max_y - min_y) as f64;
+fn generate_tiles(min_x: Real, max_x: Real,  min_y: Real, max_y: Real, clip_verts: Vec<Vector2f<Real>>) -> Vec<Mesh> {
+    let width = max_x - min_x;
+    let height = max_y - min_y;
 
-    let tile_width = TILE_WIDTH as f64;
-    let tile_height = TILE_HEIGHT as f64;
+    let tile_width = TILE_WIDTH as Real;
+    let tile_height = TILE_HEIGHT as Real;
 
     let ratio_x = width / tile_width;
     let ratio_y = height / tile_height;
@@ -98,9 +228,9 @@ fn generate_tiles(min_x: i32, max_x: i32,  min_y: i32, max_y: i32, clip_verts: V
     let mut tiles = vec![];
     for i in 0..tiles_x {
         for j in 0..tiles_y {
-            let tile_min_x = min_x as f64 + i as f64 * tile_width;
+            let tile_min_x = min_x + i as Real * tile_width;
             let tile_max_x = tile_min_x + tile_width;
-            let tile_min_y = min_y as f64 + j as f64 * tile_height;
+            let tile_min_y = min_y + j as Real * tile_height;
             let tile_max_y = tile_min_y + tile_height;
 
             let mut tile_box = vec![
@@ -117,8 +247,8 @@ fn generate_tiles(min_x: i32, max_x: i32,  min_y: i32, max_y: i32, clip_verts: V
             for v in tile_box {
                 let uv_x = (v.x - tile_min_x) / tile_width; 
                 let uv_y = 1.0 - (v.y - tile_min_y) / tile_height;
-                tiles_uv.push([uv_x, uv_y]);
-                tiles_xy.push([v.x, v.y]);
+                tiles_uv.push([to_f64(uv_x), to_f64(uv_y)]);
+                tiles_xy.push([to_f64(v.x), to_f64(v.y)]);
             }
             tiles.push((tiles_xy, tiles_uv));
         }
@@ -148,7 +278,7 @@ fn generate_tiles(min_x: i32, max_x: i32,  min_y: i32, max_y: i32, clip_verts: V
 
 
 // Returns the point of intersection between two lines (p1, p2) and (p3, p4)
-fn intersection_line_line(p1: Vector2f<f64>, p2: Vector2f<f64>, p3: Vector2f<f64>, p4: Vector2f<f64>) -> Option<Vector2f<f64>> {
+fn intersection_line_line(p1: Vector2f<Real>, p2: Vector2f<Real>, p3: Vector2f<Real>, p4: Vector2f<Real>) -> Option<Vector2f<Real>> {
     let denom = (p1.x - p2.x) * (p3.y - p4.y) - (p1.y - p2.y) * (p3.x - p4.x);
 
     if denom.abs() < 1e-10 {
@@ -161,13 +291,14 @@ fn intersection_line_line(p1: Vector2f<f64>, p2: Vector2f<f64>, p3: Vector2f<f64
     Some(Vector2f::new(x, y))
 }
 
-fn is_inside(poly: Vector2f<f64>, a: Vector2f<f64>, b: Vector2f<f64>) -> bool {
+fn is_inside(poly: Vector2f<Real>, a: Vector2f<Real>, b: Vector2f<Real>) -> bool {
     let ab = b - a;
     let ap = poly - a;
     ap.cross(ab) <= 0.0
 }
 
-fn clip(poly: &mut Vec<Vector2f<f64>>, a: Vector2f<f64>, b: Vector2f<f64>) {
+// Clips the polygon against the line (a, b), keeping the part on the inner side of the line
+pub fn clip(poly: &mut Vec<Vector2f<Real>>, a: Vector2f<Real>, b: Vector2f<Real>) {
     let mut new_poly = vec![];
 
     for i in 0..poly.len() {
@@ -197,7 +328,7 @@ fn clip(poly: &mut Vec<Vector2f<f64>>, a: Vector2f<f64>, b: Vector2f<f64>) {
 }
 
 // Source: https://www.geeksforgeeks.org/polygon-clipping-sutherland-hodgman-algorithm/
-fn suth_hodg_clip(subject_polygon: &mut Vec<Vector2f<f64>>, clip_polygon: &Vec<Vector2f<f64>>) {
+pub fn suth_hodg_clip(subject_polygon: &mut Vec<Vector2f<Real>>, clip_polygon: &Vec<Vector2f<Real>>) {
     for i in 0..clip_polygon.len() {
         let a = clip_polygon[i];
         let b = clip_polygon[(i + 1) % clip_polygon.len()];
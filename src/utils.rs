@@ -1,2 +1,4 @@
 pub mod vector2f;
-pub mod helpers;
\ No newline at end of file
+pub mod helpers;
+pub mod real;
+pub mod transform;
\ No newline at end of file
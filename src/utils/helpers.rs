@@ -1,3 +1,5 @@
+use num_traits::Float;
+
 pub fn get_pair_mut<T>(vec: &mut Vec<T>, i: usize, j: usize) -> (&mut T, &mut T) {
     assert!(i != j);
     if i < j {
@@ -9,6 +11,6 @@ pub fn get_pair_mut<T>(vec: &mut Vec<T>, i: usize, j: usize) -> (&mut T, &mut T)
     }
 }
 
-pub fn nearly_equal(a: f64, b: f64, tolerance: f64) -> bool {
+pub fn nearly_equal<T: Float>(a: T, b: T, tolerance: T) -> bool {
     (a - b).abs() < tolerance
 }
\ No newline at end of file
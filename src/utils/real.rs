@@ -0,0 +1,32 @@
+// The scalar type used by the physics. The engine runs in f64 unless the f32 feature is
+// enabled, which trades precision for speed. The graphics always take f64.
+#[cfg(not(feature = "f32"))]
+pub type Real = f64;
+#[cfg(not(feature = "f32"))]
+pub use std::f64::consts;
+
+#[cfg(feature = "f32")]
+pub type Real = f32;
+#[cfg(feature = "f32")]
+pub use std::f32::consts;
+
+// Converts to and from the f64 of the graphics and the UI
+#[cfg(not(feature = "f32"))]
+pub const fn to_f64(x: Real) -> f64 {
+    x
+}
+
+#[cfg(not(feature = "f32"))]
+pub const fn from_f64(x: f64) -> Real {
+    x
+}
+
+#[cfg(feature = "f32")]
+pub const fn to_f64(x: Real) -> f64 {
+    x as f64
+}
+
+#[cfg(feature = "f32")]
+pub const fn from_f64(x: f64) -> Real {
+    x as f32
+}
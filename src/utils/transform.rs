@@ -0,0 +1,236 @@
+use num_traits::Float;
+use std::ops::Mul;
+
+use super::vector2f::Vector2f;
+
+// A rotation stored as its cosine and sine, so rotating many vectors by the same angle only
+// evaluates them once
+#[derive(Clone, Copy, Debug)]
+pub struct Rot2<T: Float> {
+    pub cos: T,
+    pub sin: T,
+}
+
+#[allow(dead_code)]
+impl<T: Float> Rot2<T> {
+    pub fn new(radians: T) -> Self {
+        Rot2 { cos: radians.cos(), sin: radians.sin() }
+    }
+
+    pub fn identity() -> Self {
+        Rot2 { cos: T::one(), sin: T::zero() }
+    }
+
+    pub fn angle(&self) -> T {
+        self.sin.atan2(self.cos)
+    }
+
+    // Same as Vector2f::rotate by the angle
+    pub fn apply(&self, v: Vector2f<T>) -> Vector2f<T> {
+        Vector2f { x: v.x * self.cos - v.y * self.sin, y: v.x * self.sin + v.y * self.cos }
+    }
+
+    pub fn apply_inv(&self, v: Vector2f<T>) -> Vector2f<T> {
+        Vector2f { x: v.x * self.cos + v.y * self.sin, y: -v.x * self.sin + v.y * self.cos }
+    }
+
+    pub fn inverse(&self) -> Self {
+        Rot2 { cos: self.cos, sin: -self.sin }
+    }
+
+    pub fn to_mat22(self) -> Mat22<T> {
+        Mat22 { col1: Vector2f::new(self.cos, self.sin), col2: Vector2f::new(-self.sin, self.cos) }
+    }
+}
+
+// Rotating by the product rotates by the right hand side first
+impl<T: Float> Mul for Rot2<T> {
+    type Output = Rot2<T>;
+
+    fn mul(self, rhs: Rot2<T>) -> Self::Output {
+        Rot2 {
+            cos: self.cos * rhs.cos - self.sin * rhs.sin,
+            sin: self.sin * rhs.cos + self.cos * rhs.sin,
+        }
+    }
+}
+
+// A rotation followed by a translation, taking points from the local space of a shape into
+// world space
+#[derive(Clone, Copy, Debug)]
+pub struct Transform2<T: Float> {
+    pub rotation: Rot2<T>,
+    pub translation: Vector2f<T>,
+}
+
+#[allow(dead_code)]
+impl<T: Float> Transform2<T> {
+    pub fn new(translation: Vector2f<T>, radians: T) -> Self {
+        Transform2 { rotation: Rot2::new(radians), translation }
+    }
+
+    pub fn identity() -> Self {
+        Transform2 { rotation: Rot2::identity(), translation: Vector2f::new(T::zero(), T::zero()) }
+    }
+
+    pub fn apply(&self, point: Vector2f<T>) -> Vector2f<T> {
+        self.rotation.apply(point) + self.translation
+    }
+
+    pub fn apply_inv(&self, point: Vector2f<T>) -> Vector2f<T> {
+        self.rotation.apply_inv(point - self.translation)
+    }
+
+    pub fn inverse(&self) -> Self {
+        let rotation = self.rotation.inverse();
+        Transform2 { rotation, translation: -rotation.apply(self.translation) }
+    }
+}
+
+// Applying the product applies the right hand side first
+impl<T: Float> Mul for Transform2<T> {
+    type Output = Transform2<T>;
+
+    fn mul(self, rhs: Transform2<T>) -> Self::Output {
+        Transform2 { rotation: self.rotation * rhs.rotation, translation: self.apply(rhs.translation) }
+    }
+}
+
+// A 2x2 matrix stored by its columns
+#[derive(Clone, Copy, Debug)]
+pub struct Mat22<T: Float> {
+    pub col1: Vector2f<T>,
+    pub col2: Vector2f<T>,
+}
+
+#[allow(dead_code)]
+impl<T: Float> Mat22<T> {
+    pub fn new(a11: T, a12: T, a21: T, a22: T) -> Self {
+        Mat22 { col1: Vector2f::new(a11, a21), col2: Vector2f::new(a12, a22) }
+    }
+
+    pub fn identity() -> Self {
+        Self::new(T::one(), T::zero(), T::zero(), T::one())
+    }
+
+    pub fn transpose(&self) -> Self {
+        Self::new(self.col1.x, self.col1.y, self.col2.x, self.col2.y)
+    }
+
+    pub fn determinant(&self) -> T {
+        self.col1.cross(self.col2)
+    }
+
+    // None for singular matrices
+    pub fn inverse(&self) -> Option<Self> {
+        let det = self.determinant();
+        if det == T::zero() {
+            return None;
+        }
+
+        let inv = T::one() / det;
+        Some(Self::new(self.col2.y * inv, -self.col2.x * inv, -self.col1.y * inv, self.col1.x * inv))
+    }
+
+    // Solves A * x = b, which is cheaper and more accurate than going through the inverse
+    pub fn solve(&self, b: Vector2f<T>) -> Option<Vector2f<T>> {
+        let det = self.determinant();
+        if det == T::zero() {
+            return None;
+        }
+
+        let inv = T::one() / det;
+        Some(Vector2f::new(
+            (self.col2.y * b.x - self.col2.x * b.y) * inv,
+            (self.col1.x * b.y - self.col1.y * b.x) * inv,
+        ))
+    }
+}
+
+impl<T: Float> Mul<Vector2f<T>> for Mat22<T> {
+    type Output = Vector2f<T>;
+
+    fn mul(self, rhs: Vector2f<T>) -> Self::Output {
+        self.col1 * rhs.x + self.col2 * rhs.y
+    }
+}
+
+impl<T: Float> Mul for Mat22<T> {
+    type Output = Mat22<T>;
+
+    fn mul(self, rhs: Mat22<T>) -> Self::Output {
+        Mat22 { col1: self * rhs.col1, col2: self * rhs.col2 }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn close(a: Vector2f<f64>, b: Vector2f<f64>) -> bool {
+        (a - b).len() < 1e-9
+    }
+
+    fn same_transform(a: Transform2<f64>, b: Transform2<f64>) -> bool {
+        (a.rotation.cos - b.rotation.cos).abs() < 1e-9
+            && (a.rotation.sin - b.rotation.sin).abs() < 1e-9
+            && close(a.translation, b.translation)
+    }
+
+    #[test]
+    fn rotation_matches_vector_rotate() {
+        let v = Vector2f::new(3.0, -2.0);
+        for angle in [0.0, 0.3, 1.7, -2.5] {
+            assert!(close(Rot2::new(angle).apply(v), v.rotate(angle)));
+            assert!(close(Rot2::new(angle).to_mat22() * v, v.rotate(angle)));
+        }
+    }
+
+    #[test]
+    fn rotations_compose_by_adding_angles() {
+        let r = Rot2::new(0.4) * Rot2::new(1.1);
+        assert!((r.angle() - 1.5).abs() < 1e-9);
+        assert!((Rot2::new(0.7) * Rot2::new(0.7).inverse()).angle().abs() < 1e-9);
+    }
+
+    #[test]
+    fn composition_applies_the_right_hand_side_first() {
+        let a = Transform2::new(Vector2f::new(5.0, -1.0), 0.8);
+        let b = Transform2::new(Vector2f::new(-2.0, 3.0), -1.9);
+        let p = Vector2f::new(1.5, 4.0);
+        assert!(close((a * b).apply(p), a.apply(b.apply(p))));
+    }
+
+    #[test]
+    fn transform_times_inverse_is_identity() {
+        let t = Transform2::new(Vector2f::new(12.0, -7.5), 2.3);
+        assert!(same_transform(t * t.inverse(), Transform2::identity()));
+        assert!(same_transform(t.inverse() * t, Transform2::identity()));
+    }
+
+    #[test]
+    fn apply_and_apply_inv_round_trip() {
+        let t = Transform2::new(Vector2f::new(-4.0, 9.0), -0.6);
+        let p = Vector2f::new(2.0, 3.0);
+        assert!(close(t.apply_inv(t.apply(p)), p));
+        assert!(close(t.apply(t.apply_inv(p)), p));
+        assert!(close(t.inverse().apply(p), t.apply_inv(p)));
+    }
+
+    #[test]
+    fn matrix_inverse_and_solve() {
+        let m = Mat22::new(2.0, 1.0, -1.0, 3.0);
+        let inv = m.inverse().unwrap();
+        let id = m * inv;
+        assert!(close(id.col1, Mat22::<f64>::identity().col1) && close(id.col2, Mat22::<f64>::identity().col2));
+
+        let b = Vector2f::new(4.0, 5.0);
+        let x = m.solve(b).unwrap();
+        assert!(close(m * x, b));
+        assert!(close(inv * b, x));
+        assert!(close(m.transpose().transpose().col1, m.col1));
+
+        assert!(Mat22::new(1.0, 2.0, 2.0, 4.0).inverse().is_none());
+        assert!(Mat22::new(1.0, 2.0, 2.0, 4.0).solve(b).is_none());
+    }
+}
@@ -3,11 +3,12 @@ use std::ops::*;
 use rand::Rng;
 
 use super::helpers;
+use super::real::Real;
 
 #[allow(dead_code)]
 pub const EPSILON: f64 = 0.0005;
 
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, Default)]
 pub struct Vector2f<T: Float> {
     pub x: T,
     pub y: T,
@@ -51,24 +52,29 @@ impl<T: Float> Vector2f<T> {
         Vector2f { x: self.x * cos - self.y * sin, 
                  y: self.x * sin + self.y * cos }
     }
+
+    pub fn zero() -> Self {
+        Vector2f { x: T::zero(), y: T::zero() }
+    }
+
+    pub fn nearly_equal(self, other: Self, tolerance: T) -> bool {
+        helpers::nearly_equal(self.x, other.x, tolerance) &&
+        helpers::nearly_equal(self.y, other.y, tolerance)
+    }
+
+    // Converts between the precision of the physics and the f64 of the graphics
+    pub fn cast<U: Float>(self) -> Vector2f<U> {
+        Vector2f { x: U::from(self.x).unwrap(), y: U::from(self.y).unwrap() }
+    }
 }
 
-impl Vector2f<f64> {
+impl Vector2f<Real> {
     pub fn random_direction() -> Self {
         let mut r = rand::rng();
         let x = r.random_range(-1.0..1.0);
         let y = r.random_range(-1.0..1.0);
         Vector2f { x, y }.normalize()
     }
-
-    pub fn zero() -> Self {
-        Vector2f { x: 0.0, y: 0.0 }
-    }
-
-    pub fn nearly_equal(self, other: Self, tolerance: f64) -> bool {
-        helpers::nearly_equal(self.x, other.x, tolerance) &&
-        helpers::nearly_equal(self.y, other.y, tolerance)
-    }
 }
 
 impl<T: Float> From<[T; 2]> for Vector2f<T> where T: Copy {